@@ -0,0 +1,543 @@
+//! Builds typster projects without the app. Projects are loaded the same way
+//! as in the app, including their `.typster/project.json`, so the output is
+//! exactly what the app shows.
+
+use anyhow::{anyhow, bail, Context};
+use env_logger::Env;
+use notify::event::ModifyKind;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use pico_args::Arguments;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::mpsc;
+use std::time::Duration;
+use typst::World;
+use typster_lib::export::{
+    self, BatchExportOptions, ExportDiagnostic, ExportError, ImageExportOptions, ImageFormat,
+    PdfExportOptions, PdfExportStandard, WatchExporter,
+};
+use typster_lib::ide;
+use typster_lib::project::{publish_local, Project, PublishOptions, VersionBump, WatchConfig};
+
+const HELP: &str = "\
+Builds typster projects without the app.
+
+USAGE:
+  typster-cli <COMMAND> [OPTIONS]
+
+COMMANDS:
+  compile [OUTPUT]         Compiles the project to a PDF, `<main>.pdf` by default
+  watch [OUTPUT]           Compiles the project whenever one of its files changes
+  export <png|svg> <DIR>   Exports the pages as images to a directory
+  batch <DATA> <DIR>       Exports a PDF for each row of a CSV or JSON file,
+                           with the row as `sys.inputs`
+  query <SELECTOR>         Prints the elements matching a selector as JSON
+  publish                  Installs the project as an `@local` package,
+                           creating its `typst.toml` if necessary
+  fonts                    Lists the available font families
+
+OPTIONS:
+  --root <DIR>             The project directory, the current one by default
+  --preset <NAME>          A PDF preset from the project configuration
+  --pages <PAGES>          The pages to export, e.g. `1-3,5,8-`
+  --standard <STANDARD>    The PDF standard: none, 1.7 or a-2b
+  --dpi <DPI>              The resolution of PNG images
+  --pattern <PATTERN>      The file name of each image, e.g. `{name}-{p}.png`,
+                           or of each PDF of a batch, e.g. `{column}.pdf`
+  --transparent            Exports images without a background
+  --field <FIELD>          Only prints this field of each queried element
+  --one                    Expects exactly one queried element
+  --variants               Also lists the styles of each font family
+  --bump <PART>            Increments the package version before publishing:
+                           major, minor or patch
+  --overwrite              Replaces a published package of the same version
+  -h, --help               Prints this help
+";
+
+/// Changes arriving within this time are handled together.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+fn main() {
+    env_logger::init_from_env(Env::default().default_filter_or("warn"));
+
+    let mut args = Arguments::from_env();
+    if args.contains(["-h", "--help"]) {
+        print!("{HELP}");
+        return;
+    }
+
+    if let Err(e) = run(args) {
+        eprintln!("error: {e:#}");
+        process::exit(1);
+    }
+}
+
+fn run(mut args: Arguments) -> anyhow::Result<()> {
+    let root = match args.opt_value_from_str::<_, PathBuf>("--root")? {
+        Some(root) => root,
+        None => env::current_dir()?,
+    };
+    let Some(command) = args.subcommand()? else {
+        print!("{HELP}");
+        bail!("no command given");
+    };
+    if !root.join("main.typ").exists() && !root.join(".typster/project.json").exists() {
+        bail!("{} is not a typster project", root.display());
+    }
+    let project = Project::load_from_path(root).context("unable to load the project")?;
+    project.config.read().unwrap().apply(&project);
+
+    match command.as_str() {
+        "compile" => {
+            let options = pdf_options(&mut args, &project)?;
+            let output = output(&mut args)?;
+            finish(args)?;
+            compile(&project, output, &options)
+        }
+        "watch" => {
+            let options = pdf_options(&mut args, &project)?;
+            let output = output(&mut args)?;
+            finish(args)?;
+            watch(&project, output, options)
+        }
+        "export" => {
+            let options = image_options(&mut args)?;
+            let format = match args.free_from_str::<String>()?.as_str() {
+                "png" => ImageFormat::Png,
+                "svg" => ImageFormat::Svg,
+                format => bail!("unknown image format `{format}`, expected png or svg"),
+            };
+            let dir: PathBuf = args.free_from_str()?;
+            finish(args)?;
+            export_images(&project, format, &dir, &options)
+        }
+        "batch" => {
+            let options = BatchExportOptions {
+                pdf: pdf_options(&mut args, &project)?,
+                pattern: args.opt_value_from_str("--pattern")?,
+            };
+            let data: PathBuf = args.free_from_str()?;
+            let dir: PathBuf = args.free_from_str()?;
+            finish(args)?;
+            batch(&project, &data, &dir, &options)
+        }
+        "query" => {
+            let field: Option<String> = args.opt_value_from_str("--field")?;
+            let one = args.contains("--one");
+            let selector: String = args.free_from_str()?;
+            finish(args)?;
+            query(&project, &selector, field.as_deref(), one)
+        }
+        "publish" => {
+            let bump = match args.opt_value_from_str::<_, String>("--bump")?.as_deref() {
+                None => None,
+                Some("major") => Some(VersionBump::Major),
+                Some("minor") => Some(VersionBump::Minor),
+                Some("patch") => Some(VersionBump::Patch),
+                Some(part) => {
+                    bail!("unknown version part `{part}`, expected major, minor or patch")
+                }
+            };
+            let overwrite = args.contains("--overwrite");
+            finish(args)?;
+            publish(&project, &PublishOptions { bump, overwrite })
+        }
+        "fonts" => {
+            let variants = args.contains("--variants");
+            finish(args)?;
+            fonts(&project, variants);
+            Ok(())
+        }
+        command => bail!("unknown command `{command}`, see --help"),
+    }
+}
+
+fn compile(
+    project: &Project,
+    output: Option<PathBuf>,
+    options: &PdfExportOptions,
+) -> anyhow::Result<()> {
+    let world = project.world.lock().unwrap();
+    let compiled = check(export::compile(&world))?;
+    print_diagnostics("warning", &compiled.warnings);
+
+    let output = output.unwrap_or_else(|| default_output(project, &*world));
+    let buffer = check(export::pdf(&*world, &compiled.document, options))?;
+    fs::write(&output, buffer).with_context(|| format!("unable to write {}", output.display()))
+}
+
+/// Recompiles the project after changes like `typst watch`. Without an output
+/// path, the project's watch mode exports are written if they are enabled.
+fn watch(
+    project: &Project,
+    output: Option<PathBuf>,
+    options: PdfExportOptions,
+) -> anyhow::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = RecommendedWatcher::new(tx, notify::Config::default())?;
+    watcher.watch(&project.root, RecursiveMode::Recursive)?;
+
+    let mut exporter = WatchExporter::default();
+    let mut written: HashSet<PathBuf> = HashSet::new();
+    loop {
+        // An explicit output is written to its own directory, which is then
+        // the root the export has to stay in.
+        let (root, config) = match &output {
+            Some(output) => {
+                let output = env::current_dir()?.join(output);
+                let (Some(dir), Some(name)) = (output.parent(), output.file_name()) else {
+                    bail!("{} is not a file", output.display());
+                };
+                let config = WatchConfig {
+                    enabled: true,
+                    pdf: Some(name.into()),
+                    pdf_options: options.clone(),
+                    ..Default::default()
+                };
+                (dir.to_path_buf(), config)
+            }
+            None => {
+                let config = project.config.read().unwrap().export.watch.clone();
+                if config.enabled {
+                    (project.root.clone(), config)
+                } else {
+                    let world = project.world.lock().unwrap();
+                    let config = WatchConfig {
+                        enabled: true,
+                        pdf: Some(format!("{}.pdf", export::document_name(&*world)).into()),
+                        pdf_options: options.clone(),
+                        ..Default::default()
+                    };
+                    (project.root.clone(), config)
+                }
+            }
+        };
+
+        let world = project.world.lock().unwrap();
+        match check(export::compile(&world)) {
+            Ok(compiled) => {
+                print_diagnostics("warning", &compiled.warnings);
+                match check(exporter.export(&*world, &root, &compiled.document, &config)) {
+                    Ok(files) => {
+                        for file in &files {
+                            eprintln!("wrote {}", file.display());
+                        }
+                        written.extend(files);
+                    }
+                    Err(e) => eprintln!("error: {e:#}"),
+                }
+            }
+            Err(e) => eprintln!("error: {e:#}"),
+        }
+        drop(world);
+
+        // Waits for changes to files other than the ones just written.
+        loop {
+            let mut paths = changed_paths(rx.recv()?);
+            while let Ok(event) = rx.recv_timeout(DEBOUNCE) {
+                paths.extend(changed_paths(event));
+            }
+            paths.retain(|path| !written.contains(path));
+            if !paths.is_empty() {
+                for path in paths {
+                    project.reload_file(&path);
+                }
+                break;
+            }
+        }
+    }
+}
+
+fn changed_paths(event: notify::Result<notify::Event>) -> Vec<PathBuf> {
+    match event {
+        Ok(event)
+            if matches!(
+                event.kind,
+                EventKind::Create(_)
+                    | EventKind::Remove(_)
+                    | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Name(_))
+            ) =>
+        {
+            event
+                .paths
+                .into_iter()
+                .map(|path| path.canonicalize().unwrap_or(path))
+                .collect()
+        }
+        Ok(_) => vec![],
+        Err(e) => {
+            eprintln!("error: {e}");
+            vec![]
+        }
+    }
+}
+
+fn export_images(
+    project: &Project,
+    format: ImageFormat,
+    dir: &Path,
+    options: &ImageExportOptions,
+) -> anyhow::Result<()> {
+    let world = project.world.lock().unwrap();
+    let compiled = check(export::compile(&world))?;
+    print_diagnostics("warning", &compiled.warnings);
+
+    let name = export::document_name(&*world);
+    let images = check(export::images(&compiled.document, format, &name, options))?;
+    fs::create_dir_all(dir)?;
+    for image in images {
+        let path = dir.join(&image.file_name);
+        fs::write(&path, &image.data)
+            .with_context(|| format!("unable to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn batch(
+    project: &Project,
+    data: &Path,
+    dir: &Path,
+    options: &BatchExportOptions,
+) -> anyhow::Result<()> {
+    let rows = export::read_rows(data)?;
+    let mut world = project.world.lock().unwrap();
+    let report = export::batch(&mut world, &rows, dir, options, |result, total| {
+        match (&result.file, &result.error) {
+            (Some(file), _) => eprintln!("[{}/{total}] wrote {}", result.row, file.display()),
+            (None, error) => {
+                eprintln!(
+                    "[{}/{total}] error: {}",
+                    result.row,
+                    error.as_deref().unwrap_or_default()
+                );
+                print_diagnostics("error", &result.diagnostics);
+            }
+        }
+    })?;
+
+    if report.failed > 0 {
+        bail!("{} of {} rows failed", report.failed, rows.len());
+    }
+    Ok(())
+}
+
+fn query(project: &Project, selector: &str, field: Option<&str>, one: bool) -> anyhow::Result<()> {
+    let world = project.world.lock().unwrap();
+    let compiled = check(export::compile(&world))?;
+    print_diagnostics("warning", &compiled.warnings);
+
+    let value = ide::query(&*world, &compiled.document, selector, field, one)?;
+    println!("{}", serde_json::to_string_pretty(&value)?);
+    Ok(())
+}
+
+fn publish(project: &Project, options: &PublishOptions) -> anyhow::Result<()> {
+    let world = project.world.lock().unwrap();
+    let entrypoint = world.main().vpath().as_rootless_path().to_path_buf();
+    let published = publish_local(&project.root, &entrypoint, world.package_storage(), options)?;
+    if published.manifest_created {
+        eprintln!("created {}", project.root.join("typst.toml").display());
+    }
+    eprintln!(
+        "published @{}/{}:{} to {}",
+        published.namespace,
+        published.name,
+        published.version,
+        published.path.display()
+    );
+    Ok(())
+}
+
+fn fonts(project: &Project, variants: bool) {
+    let world = project.world.lock().unwrap();
+    let mut families: Vec<_> = world.book().families().collect();
+    families.sort_by_key(|(family, _)| *family);
+    for (family, infos) in families {
+        println!("{family}");
+        if variants {
+            for info in infos {
+                let variant = info.variant;
+                println!(
+                    "- style: {:?}, weight: {}, stretch: {:?}",
+                    variant.style,
+                    variant.weight.to_number(),
+                    variant.stretch
+                );
+            }
+        }
+    }
+}
+
+/// Builds the PDF options from a preset and the options on the command line.
+fn pdf_options(args: &mut Arguments, project: &Project) -> anyhow::Result<PdfExportOptions> {
+    let mut options = match args.opt_value_from_str::<_, String>("--preset")? {
+        Some(preset) => project
+            .config
+            .read()
+            .unwrap()
+            .export
+            .pdf_presets
+            .get(&preset)
+            .cloned()
+            .ok_or_else(|| anyhow!("unknown export preset `{preset}`"))?,
+        None => PdfExportOptions::default(),
+    };
+    if let Some(pages) = args.opt_value_from_str("--pages")? {
+        options.pages = Some(pages);
+    }
+    if let Some(standard) = args.opt_value_from_str::<_, String>("--standard")? {
+        options.standard = match standard.as_str() {
+            "none" => PdfExportStandard::None,
+            "1.7" => PdfExportStandard::V1_7,
+            "a-2b" => PdfExportStandard::A2b,
+            standard => bail!("unknown PDF standard `{standard}`, expected none, 1.7 or a-2b"),
+        };
+    }
+    Ok(options)
+}
+
+fn image_options(args: &mut Arguments) -> anyhow::Result<ImageExportOptions> {
+    let defaults = ImageExportOptions::default();
+    Ok(ImageExportOptions {
+        pages: args.opt_value_from_str("--pages")?,
+        dpi: args.opt_value_from_str("--dpi")?.unwrap_or(defaults.dpi),
+        pattern: args.opt_value_from_str("--pattern")?,
+        transparent: args.contains("--transparent"),
+    })
+}
+
+fn output(args: &mut Arguments) -> anyhow::Result<Option<PathBuf>> {
+    Ok(args.opt_free_from_str()?)
+}
+
+fn finish(args: Arguments) -> anyhow::Result<()> {
+    let rest = args.finish();
+    if !rest.is_empty() {
+        bail!("unexpected arguments: {:?}", rest);
+    }
+    Ok(())
+}
+
+/// The PDF next to the main file, named after it.
+fn default_output(project: &Project, world: &dyn World) -> PathBuf {
+    project
+        .root
+        .join(format!("{}.pdf", export::document_name(world)))
+}
+
+/// Prints the diagnostics of a failed export before passing the error on.
+fn check<T>(result: Result<T, ExportError>) -> anyhow::Result<T> {
+    result.map_err(|e| {
+        print_diagnostics("error", e.diagnostics());
+        e.into()
+    })
+}
+
+fn print_diagnostics(severity: &str, diagnostics: &[ExportDiagnostic]) {
+    for diagnostic in diagnostics {
+        match &diagnostic.source {
+            Some(source) => eprintln!(
+                "{}:{}:{}: {severity}: {}",
+                source
+                    .path
+                    .strip_prefix("/")
+                    .unwrap_or(&source.path)
+                    .display(),
+                source.line + 1,
+                source.column + 1,
+                diagnostic.message
+            ),
+            None => eprintln!("{severity}: {}", diagnostic.message),
+        }
+        for hint in &diagnostic.hints {
+            eprintln!("  hint: {hint}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsString;
+    use tempfile::TempDir;
+
+    fn args(args: &[&str]) -> Arguments {
+        Arguments::from_vec(args.iter().map(OsString::from).collect())
+    }
+
+    fn project(dir: &TempDir) -> Project {
+        fs::write(dir.path().join("main.typ"), "A\n#pagebreak()\nB").unwrap();
+        Project::load_from_path(dir.path().into()).unwrap()
+    }
+
+    #[test]
+    fn test_pdf_options() {
+        let dir = TempDir::new().unwrap();
+        let project = project(&dir);
+        let print = PdfExportOptions {
+            pages: Some("1".into()),
+            standard: PdfExportStandard::V1_7,
+            ident: None,
+        };
+        let mut config = project.config.write().unwrap();
+        config.export.pdf_presets.insert("print".into(), print);
+        drop(config);
+
+        let options = pdf_options(&mut args(&[]), &project).unwrap();
+        assert_eq!(options.standard, PdfExportStandard::A2b);
+
+        // Options on the command line override those of the preset.
+        let mut rest = args(&["--preset", "print", "--pages", "2-", "out.pdf"]);
+        let options = pdf_options(&mut rest, &project).unwrap();
+        assert_eq!(options.pages.as_deref(), Some("2-"));
+        assert_eq!(options.standard, PdfExportStandard::V1_7);
+        assert_eq!(output(&mut rest).unwrap(), Some("out.pdf".into()));
+        finish(rest).unwrap();
+
+        let error = pdf_options(&mut args(&["--preset", "screen"]), &project).unwrap_err();
+        assert_eq!(error.to_string(), "unknown export preset `screen`");
+        let error = pdf_options(&mut args(&["--standard", "a-3"]), &project).unwrap_err();
+        assert!(error.to_string().starts_with("unknown PDF standard `a-3`"));
+    }
+
+    #[test]
+    fn test_finish() {
+        assert!(finish(args(&[])).is_ok());
+        let error = finish(args(&["--pdf"])).unwrap_err();
+        assert_eq!(error.to_string(), r#"unexpected arguments: ["--pdf"]"#);
+    }
+
+    #[test]
+    fn test_run() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().to_str().unwrap();
+        let error = run(args(&["--root", root, "compile"])).unwrap_err();
+        assert!(error.to_string().ends_with("is not a typster project"));
+
+        project(&dir);
+        run(args(&["--root", root, "compile"])).unwrap();
+        assert!(dir.path().join("main.pdf").exists());
+
+        let out = dir.path().join("pages");
+        run(args(&[
+            "--root",
+            root,
+            "export",
+            "svg",
+            out.to_str().unwrap(),
+        ]))
+        .unwrap();
+        assert_eq!(fs::read_dir(&out).unwrap().count(), 2);
+
+        let error = run(args(&["--root", root, "export", "gif", "out"])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown image format `gif`, expected png or svg"
+        );
+        let error = run(args(&["--root", root, "print"])).unwrap_err();
+        assert_eq!(error.to_string(), "unknown command `print`, see --help");
+    }
+}
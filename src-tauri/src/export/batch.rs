@@ -0,0 +1,329 @@
+use super::{compile, document_name, pdf, ExportDiagnostic, ExportError, PdfExportOptions};
+use crate::project::ProjectWorld;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use typst::foundations::{Dict, Value};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct BatchExportOptions {
+    /// The file name of each PDF. `{n}` is replaced with the row number,
+    /// `{0n}` with the zero-padded row number, `{name}` with the name of the
+    /// main file and `{column}` with the value of a column of the row. A `/`
+    /// in the pattern puts the file into a subdirectory. Defaults to
+    /// `{name}-{0n}.pdf`.
+    pub pattern: Option<String>,
+    pub pdf: PdfExportOptions,
+}
+
+/// The outcome of exporting a single row.
+#[derive(Serialize, Debug, Clone)]
+pub struct BatchRowResult {
+    /// The one-based row number.
+    pub row: usize,
+    pub file: Option<PathBuf>,
+    pub error: Option<String>,
+    pub diagnostics: Vec<ExportDiagnostic>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct BatchReport {
+    pub written: usize,
+    pub failed: usize,
+    pub rows: Vec<BatchRowResult>,
+}
+
+/// Reads the rows of a CSV file with a header or of a JSON array of objects.
+pub fn read_rows(path: &Path) -> Result<Vec<Dict>, ExportError> {
+    let data = fs::read(path)?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => {
+            let mut reader = csv::Reader::from_reader(data.as_slice());
+            let headers = reader
+                .headers()
+                .map_err(|e| ExportError::Data(e.to_string()))?
+                .clone();
+            reader
+                .records()
+                .map(|record| {
+                    let record = record.map_err(|e| ExportError::Data(e.to_string()))?;
+                    Ok(headers
+                        .iter()
+                        .zip(record.iter())
+                        .map(|(key, value)| (key.into(), Value::Str(value.into())))
+                        .collect())
+                })
+                .collect()
+        }
+        Some("json") => serde_json::from_slice(&data)
+            .map_err(|e| ExportError::Data(format!("expected an array of objects ({e})"))),
+        _ => Err(ExportError::Data(
+            "expected a `.csv` or `.json` file".into(),
+        )),
+    }
+}
+
+/// Compiles the project once for each row, with the row as `sys.inputs`, and
+/// writes a PDF per row to a directory. A failing row doesn't stop the
+/// export, `progress` is called after each row. The world is reused for all
+/// rows, so that fonts and packages are only loaded once, and its inputs are
+/// cleared afterwards.
+pub fn batch(
+    world: &mut ProjectWorld,
+    rows: &[Dict],
+    dir: &Path,
+    options: &BatchExportOptions,
+    mut progress: impl FnMut(&BatchRowResult, usize),
+) -> Result<BatchReport, ExportError> {
+    fs::create_dir_all(dir)?;
+    let dir = dir.canonicalize()?;
+    let name = document_name(world);
+    let pattern = options.pattern.as_deref().unwrap_or("{name}-{0n}.pdf");
+
+    let mut report = BatchReport::default();
+    let mut files: HashMap<PathBuf, usize> = HashMap::new();
+    for (i, row) in rows.iter().enumerate() {
+        let n = i + 1;
+        let result = batch_file_name(pattern, &name, row, n, rows.len()).and_then(|file_name| {
+            let file = dir.join(file_name);
+            if let Some(other) = files.get(&file) {
+                return Err(ExportError::Pattern(format!(
+                    "row {n} has the same file name as row {other}"
+                )));
+            }
+            files.insert(file.clone(), n);
+            create_parent(&dir, &file)?;
+            export_row(world, row.clone(), &file, &options.pdf).map(|_| file)
+        });
+
+        let result = match result {
+            Ok(file) => {
+                report.written += 1;
+                BatchRowResult {
+                    row: n,
+                    file: Some(file),
+                    error: None,
+                    diagnostics: vec![],
+                }
+            }
+            Err(e) => {
+                report.failed += 1;
+                BatchRowResult {
+                    row: n,
+                    file: None,
+                    error: Some(e.to_string()),
+                    diagnostics: e.diagnostics().to_vec(),
+                }
+            }
+        };
+        progress(&result, rows.len());
+        report.rows.push(result);
+    }
+
+    world.set_inputs(Dict::new());
+    Ok(report)
+}
+
+/// Creates the subdirectories of a file below the export directory. Fails if
+/// they resolve to a place outside of it, e.g. through a symbolic link.
+fn create_parent(dir: &Path, file: &Path) -> Result<(), ExportError> {
+    let Some(parent) = file.parent() else {
+        return Ok(());
+    };
+    fs::create_dir_all(parent)?;
+    if !parent.canonicalize()?.starts_with(dir) {
+        return Err(ExportError::Pattern(format!(
+            "`{}` is outside of the export directory",
+            file.display()
+        )));
+    }
+    Ok(())
+}
+
+fn export_row(
+    world: &mut ProjectWorld,
+    inputs: Dict,
+    file: &Path,
+    options: &PdfExportOptions,
+) -> Result<(), ExportError> {
+    world.set_inputs(inputs);
+    let compiled = compile(world)?;
+    let buffer = pdf(&*world, &compiled.document, options)?;
+    fs::write(file, buffer)?;
+    Ok(())
+}
+
+/// Fills in the placeholders of a file name pattern for a row. Column values
+/// take precedence over `{name}`, so that a `name` column can be used.
+/// Returns a path relative to the export directory, which can't leave it.
+pub fn batch_file_name(
+    pattern: &str,
+    name: &str,
+    row: &Dict,
+    n: usize,
+    total: usize,
+) -> Result<PathBuf, ExportError> {
+    let width = total.to_string().len();
+    let mut out = String::new();
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| ExportError::Pattern("unclosed `{`".into()))?
+            + start;
+        let key = &rest[start + 1..end];
+        let value = match row.get(key) {
+            Ok(Value::Str(value)) => value.to_string(),
+            Ok(Value::Int(value)) => value.to_string(),
+            Ok(Value::Float(value)) => value.to_string(),
+            Ok(Value::Bool(value)) => value.to_string(),
+            Ok(value) => {
+                return Err(ExportError::Pattern(format!(
+                    "`{key}` is a {} and cannot be used in a file name",
+                    value.ty()
+                )))
+            }
+            Err(_) => match key {
+                "n" => n.to_string(),
+                "0n" => format!("{n:0width$}"),
+                "name" => name.to_string(),
+                _ => {
+                    return Err(ExportError::Pattern(format!(
+                        "row {n} has no column `{key}`"
+                    )))
+                }
+            },
+        };
+        out.extend(value.chars().map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        }));
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+
+    // Checks each part separately, as `Path::components` skips `.` parts.
+    let mut path = PathBuf::new();
+    for part in out.split('/') {
+        let mut components = Path::new(part).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(normal)), None) if normal == part => path.push(part),
+            _ => {
+                return Err(ExportError::Pattern(format!(
+                    "`{out}` must be a relative path without empty, `.` or `..` parts"
+                )))
+            }
+        }
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use typst::foundations::IntoValue;
+
+    fn row(entries: &[(&str, Value)]) -> Dict {
+        entries
+            .iter()
+            .map(|(key, value)| ((*key).into(), value.clone()))
+            .collect()
+    }
+
+    fn file_name(pattern: &str, row: &Dict) -> Result<PathBuf, ExportError> {
+        batch_file_name(pattern, "letter", row, 7, 120)
+    }
+
+    #[test]
+    fn test_batch_file_name() {
+        let empty = Dict::new();
+        assert_eq!(
+            file_name("{name}-{0n}.pdf", &empty).unwrap(),
+            Path::new("letter-007.pdf")
+        );
+        assert_eq!(file_name("{n}.pdf", &empty).unwrap(), Path::new("7.pdf"));
+
+        let person = row(&[
+            ("name", "Ada Lovelace".into_value()),
+            ("city", "London/UK".into_value()),
+            ("age", 36.into_value()),
+        ]);
+        assert_eq!(
+            file_name("{city}/{name}-{age}.pdf", &person).unwrap(),
+            Path::new("London_UK/Ada Lovelace-36.pdf")
+        );
+        assert_eq!(
+            file_name("out/{city}/{n}.pdf", &person).unwrap(),
+            Path::new("out/London_UK/7.pdf")
+        );
+
+        assert!(file_name("{missing}.pdf", &person).is_err());
+        assert!(file_name("{name.pdf", &person).is_err());
+        let array = row(&[("list", vec![1.into_value()].into_value())]);
+        assert!(file_name("{list}.pdf", &array).is_err());
+    }
+
+    #[test]
+    fn test_batch_file_name_stays_in_the_directory() {
+        let dots = row(&[("up", "..".into_value()), ("here", ".".into_value())]);
+        for pattern in [
+            "../{n}.pdf",
+            "{up}/{n}.pdf",
+            "a/{up}/{n}.pdf",
+            "{up}",
+            "./{n}.pdf",
+            "a/{here}/{n}.pdf",
+            "/{n}.pdf",
+            "a//{n}.pdf",
+            "a/",
+            "",
+        ] {
+            assert!(file_name(pattern, &dots).is_err(), "{pattern}");
+        }
+    }
+
+    #[test]
+    fn test_create_parent() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let out = root.join("out");
+        fs::create_dir(&out).unwrap();
+        create_parent(&out, &out.join("a/b/c.pdf")).unwrap();
+        assert!(out.join("a/b").is_dir());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&root, out.join("link")).unwrap();
+            assert!(create_parent(&out, &out.join("link/escaped/c.pdf")).is_err());
+        }
+    }
+
+    #[test]
+    fn test_read_rows() {
+        let dir = TempDir::new().unwrap();
+        let csv = dir.path().join("rows.csv");
+        fs::write(&csv, "name,age\nAda,36\nAlan,41\n").unwrap();
+        let rows = read_rows(&csv).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].get("name").unwrap(), &"Alan".into_value());
+        assert_eq!(rows[1].get("age").unwrap(), &"41".into_value());
+
+        let json = dir.path().join("rows.json");
+        fs::write(&json, r#"[{"name": "Ada", "age": 36}]"#).unwrap();
+        let rows = read_rows(&json).unwrap();
+        assert_eq!(rows[0].get("age").unwrap(), &36.into_value());
+
+        fs::write(&json, r#"{"name": "Ada"}"#).unwrap();
+        assert!(matches!(read_rows(&json), Err(ExportError::Data(_))));
+        fs::write(&csv, "name,age\nAda\n").unwrap();
+        assert!(matches!(read_rows(&csv), Err(ExportError::Data(_))));
+        let txt = dir.path().join("rows.txt");
+        fs::write(&txt, "Ada").unwrap();
+        assert!(matches!(read_rows(&txt), Err(ExportError::Data(_))));
+    }
+}
@@ -0,0 +1,252 @@
+use super::{parse_page_ranges, ExportError};
+use serde::{Deserialize, Serialize};
+use typst::foundations::Smart;
+use typst::layout::{Page, Size};
+use typst::model::Document;
+
+/// The number of typographic points per inch.
+const PT_PER_INCH: f32 = 72.0;
+
+/// The highest resolution of PNG images.
+pub const MAX_DPI: u32 = 2400;
+
+/// The most pixels a PNG image may have, about 400 MB of RGBA data.
+const MAX_PIXELS: u64 = 100_000_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFormat {
+    Png,
+    Svg,
+}
+
+impl ImageFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Svg => "svg",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Hash)]
+#[serde(default)]
+pub struct ImageExportOptions {
+    /// The pages to export, e.g. `1-3,5,8-`. All pages are exported if not
+    /// set.
+    pub pages: Option<String>,
+    /// The resolution of PNG images in pixels per inch, from 1 to
+    /// [`MAX_DPI`].
+    pub dpi: u32,
+    /// The file name of each page. `{name}` is replaced with the name of the
+    /// main file, `{p}` with the page number, `{0p}` with the zero-padded
+    /// page number and `{t}` with the total number of pages. Defaults to
+    /// `{name}-{p}` with the format's extension.
+    pub pattern: Option<String>,
+    /// Whether pages without an explicit fill get a transparent instead of a
+    /// white background.
+    pub transparent: bool,
+}
+
+impl Default for ImageExportOptions {
+    fn default() -> Self {
+        Self {
+            pages: None,
+            dpi: 144,
+            pattern: None,
+            transparent: false,
+        }
+    }
+}
+
+/// An exported page.
+#[derive(Debug)]
+pub struct PageImage {
+    /// The one-based page number.
+    pub page: usize,
+    pub file_name: String,
+    pub data: Vec<u8>,
+}
+
+/// Exports the selected pages of a document to PNG or SVG images. `name`
+/// fills in the `{name}` placeholder of the file name pattern.
+pub fn images(
+    document: &Document,
+    format: ImageFormat,
+    name: &str,
+    options: &ImageExportOptions,
+) -> Result<Vec<PageImage>, ExportError> {
+    image_files(document, format, name, options)?
+        .into_iter()
+        .map(|(i, file_name)| {
+            Ok(PageImage {
+                page: i + 1,
+                file_name,
+                data: page_image(&document.pages[i], format, options)?,
+            })
+        })
+        .collect()
+}
+
+/// Lists the indices of the selected pages along with their file names.
+pub fn image_files(
+    document: &Document,
+    format: ImageFormat,
+    name: &str,
+    options: &ImageExportOptions,
+) -> Result<Vec<(usize, String)>, ExportError> {
+    let page_ranges = options
+        .pages
+        .as_deref()
+        .map(parse_page_ranges)
+        .transpose()?;
+    let pages: Vec<usize> = (0..document.pages.len())
+        .filter(|&i| {
+            page_ranges
+                .as_ref()
+                .map_or(true, |ranges| ranges.includes_page_index(i))
+        })
+        .collect();
+
+    let default_pattern = format!("{{name}}-{{p}}.{}", format.extension());
+    let pattern = options.pattern.as_deref().unwrap_or(&default_pattern);
+    if pages.len() > 1 && !pattern.contains("{p}") && !pattern.contains("{0p}") {
+        return Err(ExportError::Pattern(
+            "`{p}` or `{0p}` is required to export several pages".into(),
+        ));
+    }
+
+    let total = document.pages.len();
+    Ok(pages
+        .into_iter()
+        .map(|i| (i, image_file_name(pattern, name, i + 1, total)))
+        .collect())
+}
+
+/// Renders a single page to PNG or SVG.
+pub fn page_image(
+    page: &Page,
+    format: ImageFormat,
+    options: &ImageExportOptions,
+) -> Result<Vec<u8>, ExportError> {
+    let mut page = page.clone();
+    if options.transparent && page.fill.is_auto() {
+        page.fill = Smart::Custom(None);
+    }
+
+    match format {
+        ImageFormat::Png => {
+            let pixel_per_pt = pixel_per_pt(&page, options.dpi)?;
+            let pixmap = typst_render::render(&page, pixel_per_pt);
+            pixmap
+                .encode_png()
+                .map_err(|e| ExportError::Failed(e.to_string()))
+        }
+        ImageFormat::Svg => Ok(typst_svg::svg(&page).into_bytes()),
+    }
+}
+
+/// The scale of a page rendered at a resolution, as long as the image isn't
+/// too large to be rendered.
+fn pixel_per_pt(page: &Page, dpi: u32) -> Result<f32, ExportError> {
+    if !(1..=MAX_DPI).contains(&dpi) {
+        return Err(ExportError::Resolution(format!(
+            "{dpi} dpi is not between 1 and {MAX_DPI} dpi"
+        )));
+    }
+    let pixel_per_pt = dpi as f32 / PT_PER_INCH;
+    check_render_size(page.frame.size(), pixel_per_pt)
+        .map_err(|e| ExportError::Resolution(format!("page {} {e} at {dpi} dpi", page.number)))?;
+    Ok(pixel_per_pt)
+}
+
+/// Checks that a frame can be rendered at a scale in pixels per point,
+/// i.e. that the scale is positive and the image has at most
+/// [`MAX_PIXELS`] pixels. Describes the problem otherwise.
+pub fn check_render_size(size: Size, pixel_per_pt: f32) -> Result<(), String> {
+    if !(pixel_per_pt.is_finite() && pixel_per_pt > 0.0) {
+        return Err(format!("can't be rendered at a scale of {pixel_per_pt}"));
+    }
+    let width = (pixel_per_pt * size.x.to_pt() as f32).round().max(1.0) as u64;
+    let height = (pixel_per_pt * size.y.to_pt() as f32).round().max(1.0) as u64;
+    if width.saturating_mul(height) > MAX_PIXELS {
+        return Err(format!("would be {width}x{height} pixels"));
+    }
+    Ok(())
+}
+
+/// Fills in the placeholders of a file name pattern.
+pub fn image_file_name(pattern: &str, name: &str, page: usize, total: usize) -> String {
+    let width = total.to_string().len();
+    pattern
+        .replace("{name}", name)
+        .replace("{0p}", &format!("{page:0width$}"))
+        .replace("{p}", &page.to_string())
+        .replace("{t}", &total.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use typst::layout::{Abs, Frame};
+
+    fn page(width: f64, height: f64) -> Page {
+        Page {
+            frame: Frame::hard(Size::new(Abs::pt(width), Abs::pt(height))),
+            fill: Smart::Auto,
+            numbering: None,
+            number: 1,
+        }
+    }
+
+    #[test]
+    fn test_pixel_per_pt() {
+        let a4 = page(595.0, 842.0);
+        assert_eq!(pixel_per_pt(&a4, 72).unwrap(), 1.0);
+        assert_eq!(pixel_per_pt(&a4, 144).unwrap(), 2.0);
+        assert!(pixel_per_pt(&page(100.0, 100.0), MAX_DPI).is_ok());
+        assert!(pixel_per_pt(&a4, MAX_DPI).is_err());
+        assert!(pixel_per_pt(&a4, 0).is_err());
+        assert!(pixel_per_pt(&a4, MAX_DPI + 1).is_err());
+        assert!(pixel_per_pt(&page(5000.0, 5000.0), 600).is_err());
+    }
+
+    #[test]
+    fn test_check_render_size() {
+        let size = Size::new(Abs::pt(100.0), Abs::pt(100.0));
+        assert!(check_render_size(size, 2.0).is_ok());
+        assert!(check_render_size(size, 0.0).is_err());
+        assert!(check_render_size(size, -1.0).is_err());
+        assert!(check_render_size(size, f32::NAN).is_err());
+        assert!(check_render_size(size, f32::INFINITY).is_err());
+        assert_eq!(
+            check_render_size(size, 200.0).unwrap_err(),
+            "would be 20000x20000 pixels"
+        );
+        let tall = Size::new(Abs::pt(100.0), Abs::inf());
+        assert!(check_render_size(tall, 1.0).is_err());
+    }
+
+    #[test]
+    fn test_page_image_rejects_zero_dpi() {
+        let options = ImageExportOptions {
+            dpi: 0,
+            ..Default::default()
+        };
+        let result = page_image(&page(100.0, 100.0), ImageFormat::Png, &options);
+        assert!(matches!(result, Err(ExportError::Resolution(_))));
+        let options = ImageExportOptions::default();
+        let png = page_image(&page(100.0, 100.0), ImageFormat::Png, &options).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+    }
+
+    #[test]
+    fn test_image_file_name() {
+        assert_eq!(image_file_name("{name}-{p}.png", "doc", 3, 12), "doc-3.png");
+        assert_eq!(
+            image_file_name("{0p}-of-{t}.svg", "doc", 3, 12),
+            "03-of-12.svg"
+        );
+        assert_eq!(image_file_name("page.png", "doc", 1, 1), "page.png");
+    }
+}
@@ -0,0 +1,231 @@
+mod batch;
+mod image;
+mod pdf;
+mod preflight;
+mod watch;
+
+pub use batch::*;
+pub use image::*;
+pub use pdf::*;
+pub use preflight::*;
+pub use watch::*;
+
+use crate::ide::SourceLocation;
+use crate::project::ProjectWorld;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::io;
+use std::num::NonZeroUsize;
+use thiserror::Error;
+use typst::diag::{SourceDiagnostic, Warned};
+use typst::layout::{PageRange, PageRanges};
+use typst::model::Document;
+use typst::World;
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("the document has errors")]
+    Compile(Vec<ExportDiagnostic>),
+    #[error("invalid page range: {0}")]
+    PageRange(String),
+    #[error("invalid file name pattern: {0}")]
+    Pattern(String),
+    #[error("unknown export preset: {0}")]
+    UnknownPreset(String),
+    #[error("invalid data file: {0}")]
+    Data(String),
+    #[error("invalid output path: {0}")]
+    Output(String),
+    #[error("invalid image resolution: {0}")]
+    Resolution(String),
+    /// Mostly violations of the selected PDF standard, e.g. text that cannot
+    /// be mapped to unicode in PDF/A.
+    #[error("the document cannot be exported to PDF")]
+    Pdf(Vec<ExportDiagnostic>),
+    #[error("unable to write the export: {0}")]
+    Io(#[from] io::Error),
+    #[error("export failed: {0}")]
+    Failed(String),
+}
+
+impl ExportError {
+    /// The diagnostics explaining the error, if there are any.
+    pub fn diagnostics(&self) -> &[ExportDiagnostic] {
+        match self {
+            Self::Compile(diagnostics) | Self::Pdf(diagnostics) => diagnostics,
+            _ => &[],
+        }
+    }
+}
+
+/// Export errors are sent to the frontend with their diagnostics, so that
+/// the offending places can be shown in the editor.
+impl Serialize for ExportError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("ExportError", 2)?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("diagnostics", self.diagnostics())?;
+        state.end()
+    }
+}
+
+/// An error or warning that prevented or accompanies an export.
+#[derive(Serialize, Debug, Clone)]
+pub struct ExportDiagnostic {
+    pub message: String,
+    pub hints: Vec<String>,
+    pub source: Option<SourceLocation>,
+}
+
+impl ExportDiagnostic {
+    pub fn new(world: &dyn World, diagnostic: &SourceDiagnostic) -> Self {
+        Self {
+            message: diagnostic.message.to_string(),
+            hints: diagnostic.hints.iter().map(ToString::to_string).collect(),
+            source: SourceLocation::from_span(world, diagnostic.span),
+        }
+    }
+}
+
+/// A document compiled for export.
+pub struct CompiledDocument {
+    pub document: Document,
+    /// The revision of the sources the document was compiled from, see
+    /// [`ProjectWorld::revision`].
+    pub revision: String,
+    pub warnings: Vec<ExportDiagnostic>,
+}
+
+/// Compiles the project in its current state, including unsaved edits, so
+/// that exports never reflect an outdated document. Fails with the errors of
+/// the compilation, if there are any.
+pub fn compile(world: &ProjectWorld) -> Result<CompiledDocument, ExportError> {
+    let diagnostics = |diagnostics: &[SourceDiagnostic]| {
+        diagnostics
+            .iter()
+            .map(|diagnostic| ExportDiagnostic::new(world, diagnostic))
+            .collect()
+    };
+
+    let Warned { output, warnings } = typst::compile(world);
+    let document = output.map_err(|errors| ExportError::Compile(diagnostics(&errors)))?;
+    Ok(CompiledDocument {
+        document,
+        revision: hex::encode(world.revision().to_be_bytes()),
+        warnings: diagnostics(&warnings),
+    })
+}
+
+/// The name of the document, taken from the main file, e.g. `thesis` for
+/// `/thesis.typ`.
+pub fn document_name(world: &dyn World) -> String {
+    world
+        .main()
+        .vpath()
+        .as_rootless_path()
+        .file_stem()
+        .map_or_else(
+            || "document".into(),
+            |stem| stem.to_string_lossy().into_owned(),
+        )
+}
+
+/// Parses comma separated, one-based page ranges as in `1-3,5,8-`. Either end
+/// of a range may be omitted to leave it open.
+pub fn parse_page_ranges(text: &str) -> Result<PageRanges, ExportError> {
+    let page = |number: &str| -> Result<Option<NonZeroUsize>, ExportError> {
+        let number = number.trim();
+        if number.is_empty() {
+            return Ok(None);
+        }
+        number
+            .parse::<NonZeroUsize>()
+            .map(Some)
+            .map_err(|_| ExportError::PageRange(format!("`{number}` is not a page number")))
+    };
+
+    let ranges = text
+        .split(',')
+        .map(|part| -> Result<PageRange, ExportError> {
+            let part = part.trim();
+            let range = match part.split_once('-') {
+                Some((start, end)) => page(start)?..=page(end)?,
+                None => {
+                    let page = page(part)?
+                        .ok_or_else(|| ExportError::PageRange("empty page range".into()))?;
+                    Some(page)..=Some(page)
+                }
+            };
+            if let (Some(start), Some(end)) = (range.start(), range.end()) {
+                if start > end {
+                    return Err(ExportError::PageRange(format!(
+                        "`{part}` ends before it starts"
+                    )));
+                }
+            }
+            Ok(range)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(PageRanges::new(ranges))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::test_world;
+    use std::path::Path;
+
+    fn pages(text: &str) -> Vec<usize> {
+        let ranges = parse_page_ranges(text).unwrap();
+        (0..12)
+            .filter(|&i| ranges.includes_page_index(i))
+            .map(|i| i + 1)
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_page_ranges() {
+        assert_eq!(pages("3"), vec![3]);
+        assert_eq!(pages("1-3, 7"), vec![1, 2, 3, 7]);
+        assert_eq!(pages(" 2 - 2 "), vec![2]);
+        assert_eq!(pages("10-"), vec![10, 11, 12]);
+        assert_eq!(pages("-2,11"), vec![1, 2, 11]);
+        assert_eq!(pages("-"), (1..=12).collect::<Vec<_>>());
+        for text in ["", "0", "1,,2", "a", "3-1", "1-2-3"] {
+            assert!(parse_page_ranges(text).is_err(), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn test_compile() {
+        let (_dir, mut world) = test_world(&[("thesis.typ", "A")]);
+        assert_eq!(document_name(&world), "thesis");
+
+        let compiled = compile(&world).unwrap();
+        assert_eq!(compiled.document.pages.len(), 1);
+        assert!(compiled.warnings.is_empty());
+        assert_eq!(compile(&world).unwrap().revision, compiled.revision);
+
+        // Unsaved edits are compiled and change the revision.
+        world
+            .slot_update("/thesis.typ", Some("A\n#pagebreak()\nB".into()))
+            .unwrap();
+        let edited = compile(&world).unwrap();
+        assert_eq!(edited.document.pages.len(), 2);
+        assert_ne!(edited.revision, compiled.revision);
+
+        world
+            .slot_update("/thesis.typ", Some("#nope".into()))
+            .unwrap();
+        let Err(ExportError::Compile(errors)) = compile(&world) else {
+            panic!("the document has errors");
+        };
+        assert_eq!(errors.len(), 1);
+        let source = errors[0].source.as_ref().unwrap();
+        assert_eq!(source.path, Path::new("/thesis.typ"));
+        assert_eq!(source.range, 1..5);
+    }
+}
@@ -0,0 +1,128 @@
+use super::{parse_page_ranges, ExportDiagnostic, ExportError};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use typst::foundations::{Datetime, Smart};
+use typst::model::Document;
+use typst::World;
+use typst_pdf::{PdfOptions, PdfStandard, PdfStandards};
+
+/// The standard an exported PDF conforms to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PdfExportStandard {
+    /// No particular standard, typst picks the PDF version.
+    #[serde(rename = "none")]
+    None,
+    #[serde(rename = "1.7")]
+    V1_7,
+    /// PDF/A-2b for archiving, which also implies PDF 1.7.
+    #[default]
+    #[serde(rename = "a-2b")]
+    A2b,
+}
+
+impl PdfExportStandard {
+    fn standards(self) -> &'static [PdfStandard] {
+        match self {
+            Self::None => &[],
+            Self::V1_7 => &[PdfStandard::V_1_7],
+            Self::A2b => &[PdfStandard::A_2b, PdfStandard::V_1_7],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Hash)]
+#[serde(default)]
+pub struct PdfExportOptions {
+    /// The pages to export, e.g. `1-3,5,8-`. All pages are exported if not
+    /// set.
+    pub pages: Option<String>,
+    pub standard: PdfExportStandard,
+    /// A string identifying the document across exports, e.g. a report
+    /// number. If not set, typst derives the identifier from the document's
+    /// title and author.
+    pub ident: Option<String>,
+}
+
+/// Exports a document to PDF. Violations of the selected standard are
+/// returned as [`ExportError::Pdf`].
+pub fn pdf(
+    world: &dyn World,
+    document: &Document,
+    options: &PdfExportOptions,
+) -> Result<Vec<u8>, ExportError> {
+    let page_ranges = options
+        .pages
+        .as_deref()
+        .map(parse_page_ranges)
+        .transpose()?;
+    let standards = PdfStandards::new(options.standard.standards())
+        .map_err(|e| ExportError::Failed(e.to_string()))?;
+
+    let options = PdfOptions {
+        ident: options.ident.as_deref().map_or(Smart::Auto, Smart::Custom),
+        timestamp: convert_datetime(Utc::now()),
+        page_ranges,
+        standards,
+    };
+    typst_pdf::pdf(document, &options).map_err(|diagnostics| {
+        ExportError::Pdf(
+            diagnostics
+                .iter()
+                .map(|diagnostic| ExportDiagnostic::new(world, diagnostic))
+                .collect(),
+        )
+    })
+}
+
+/// Convert [`chrono::DateTime`] to [`Datetime`]
+pub fn convert_datetime(date_time: DateTime<Utc>) -> Option<Datetime> {
+    Datetime::from_ymd_hms(
+        date_time.year(),
+        date_time.month().try_into().ok()?,
+        date_time.day().try_into().ok()?,
+        date_time.hour().try_into().ok()?,
+        date_time.minute().try_into().ok()?,
+        date_time.second().try_into().ok()?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::test_world;
+
+    #[test]
+    fn test_pdf_options() {
+        let options: PdfExportOptions =
+            serde_json::from_str(r#"{"pages": "2-", "standard": "1.7"}"#).unwrap();
+        assert_eq!(options.pages.as_deref(), Some("2-"));
+        assert_eq!(options.standard, PdfExportStandard::V1_7);
+        assert_eq!(options.ident, None);
+
+        let options: PdfExportOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(options.standard, PdfExportStandard::A2b);
+    }
+
+    #[test]
+    fn test_pdf() {
+        let (_dir, world) = test_world(&[("main.typ", "A\n#pagebreak()\nB")]);
+        let document = typst::compile(&world).output.unwrap();
+
+        let options = PdfExportOptions {
+            pages: Some("2".into()),
+            standard: PdfExportStandard::None,
+            ident: Some("report-1".into()),
+        };
+        let buffer = pdf(&world, &document, &options).unwrap();
+        assert!(buffer.starts_with(b"%PDF"));
+
+        let options = PdfExportOptions {
+            pages: Some("3-1".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            pdf(&world, &document, &options),
+            Err(ExportError::PageRange(_))
+        ));
+    }
+}
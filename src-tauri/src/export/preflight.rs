@@ -0,0 +1,409 @@
+use crate::ide::SourceLocation;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use ttf_parser::{name_id, Permissions};
+use typst::foundations::{NativeElement, Repr, Selector};
+use typst::layout::{Frame, FrameItem, Point, Size, Transform};
+use typst::model::{Destination, Document, LinkElem, LinkTarget};
+use typst::syntax::Span;
+use typst::text::Font;
+use typst::visualize::ImageKind;
+use typst::World;
+
+/// Page sizes closer than this many points count as equal.
+const SIZE_TOLERANCE: f64 = 0.01;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PreflightOptions {
+    /// Raster images with a lower effective resolution are reported.
+    pub min_dpi: f64,
+}
+
+impl Default for PreflightOptions {
+    fn default() -> Self {
+        Self { min_dpi: 300.0 }
+    }
+}
+
+/// Findings of a preflight check. Page numbers are one-based and positions
+/// are given in points from the top left corner of the page.
+#[derive(Serialize, Debug, Default)]
+pub struct PreflightReport {
+    pub low_resolution_images: Vec<LowResolutionImage>,
+    /// All fonts used in the document, including those that may be embedded
+    /// without restrictions.
+    pub fonts: Vec<FontUsage>,
+    pub empty_pages: Vec<usize>,
+    /// Pages whose size differs from the size most pages have.
+    pub mismatched_pages: Vec<PageSizeMismatch>,
+    pub broken_links: Vec<BrokenLink>,
+}
+
+impl PreflightReport {
+    /// Whether anything that is likely to get a print job rejected was found.
+    pub fn has_issues(&self) -> bool {
+        !self.low_resolution_images.is_empty()
+            || self.fonts.iter().any(|font| !font.embeddable)
+            || !self.empty_pages.is_empty()
+            || !self.mismatched_pages.is_empty()
+            || !self.broken_links.is_empty()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct LowResolutionImage {
+    pub page: usize,
+    pub position: (f64, f64),
+    /// The effective resolution at the size the image is placed with.
+    pub dpi: f64,
+    pub source: Option<SourceLocation>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FontEmbedding {
+    Installable,
+    Editable,
+    PreviewAndPrint,
+    Restricted,
+    /// The font has no `OS/2` table declaring its permissions.
+    Unknown,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FontUsage {
+    pub family: String,
+    pub postscript_name: Option<String>,
+    pub style: String,
+    pub weight: u16,
+    /// The license description from the font's naming table.
+    pub license: Option<String>,
+    pub license_url: Option<String>,
+    pub embedding: FontEmbedding,
+    /// Whether the font's permissions allow embedding it into a PDF.
+    pub embeddable: bool,
+    pub subsetting_allowed: bool,
+    pub pages: Vec<usize>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PageSizeMismatch {
+    pub page: usize,
+    pub width: f64,
+    pub height: f64,
+    pub expected_width: f64,
+    pub expected_height: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BrokenLink {
+    pub page: usize,
+    pub position: (f64, f64),
+    /// What the link points to, e.g. `<intro>` or `page 12`.
+    pub target: String,
+    pub reason: String,
+    pub source: Option<SourceLocation>,
+}
+
+/// Checks a compiled document for problems that commonly get PDFs rejected by
+/// print shops.
+pub fn preflight(
+    world: &dyn World,
+    document: &Document,
+    options: &PreflightOptions,
+) -> PreflightReport {
+    let mut report = PreflightReport::default();
+    let mut fonts: Vec<(Font, Vec<usize>)> = Vec::new();
+
+    for (i, page) in document.pages.iter().enumerate() {
+        let number = i + 1;
+        let mut visitor = PageVisitor {
+            world,
+            document,
+            options,
+            page: number,
+            report: &mut report,
+            fonts: &mut fonts,
+            visible: false,
+        };
+        visitor.visit(&page.frame, Transform::identity());
+        if !visitor.visible {
+            report.empty_pages.push(number);
+        }
+    }
+
+    report.fonts = fonts
+        .into_iter()
+        .map(|(font, pages)| font_usage(&font, pages))
+        .collect();
+    report
+        .fonts
+        .sort_by(|a, b| (&a.family, a.weight, &a.style).cmp(&(&b.family, b.weight, &b.style)));
+
+    report.mismatched_pages = mismatched_pages(document);
+    report
+        .broken_links
+        .extend(broken_label_links(world, document));
+    report
+}
+
+struct PageVisitor<'a> {
+    world: &'a dyn World,
+    document: &'a Document,
+    options: &'a PreflightOptions,
+    page: usize,
+    report: &'a mut PreflightReport,
+    /// The fonts used so far and the pages they are used on.
+    fonts: &'a mut Vec<(Font, Vec<usize>)>,
+    /// Whether anything visible was found on the page.
+    visible: bool,
+}
+
+impl PageVisitor<'_> {
+    fn visit(&mut self, frame: &Frame, ts: Transform) {
+        for (pos, item) in frame.items() {
+            let ts = ts.pre_concat(Transform::translate(pos.x, pos.y));
+            match item {
+                FrameItem::Group(group) => self.visit(&group.frame, ts.pre_concat(group.transform)),
+                FrameItem::Text(text) => {
+                    self.visible = true;
+                    match self.fonts.iter_mut().find(|(font, _)| *font == text.font) {
+                        Some((_, pages)) if pages.last() == Some(&self.page) => {}
+                        Some((_, pages)) => pages.push(self.page),
+                        None => self.fonts.push((text.font.clone(), vec![self.page])),
+                    }
+                }
+                FrameItem::Shape(..) => self.visible = true,
+                FrameItem::Image(image, size, span) => {
+                    self.visible = true;
+                    if let ImageKind::Raster(_) = image.kind() {
+                        self.check_image(image.width(), image.height(), *size, ts, *span);
+                    }
+                }
+                FrameItem::Link(dest, _) => self.check_link(dest, ts),
+                FrameItem::Tag(_) => {}
+            }
+        }
+    }
+
+    fn check_image(&mut self, width: f64, height: f64, size: Size, ts: Transform, span: Span) {
+        let scale_x = ts.sx.get().hypot(ts.ky.get());
+        let scale_y = ts.kx.get().hypot(ts.sy.get());
+        let dpi_x = width / (size.x.to_inches() * scale_x);
+        let dpi_y = height / (size.y.to_inches() * scale_y);
+        let dpi = dpi_x.min(dpi_y);
+        if dpi.is_finite() && dpi < self.options.min_dpi {
+            self.report.low_resolution_images.push(LowResolutionImage {
+                page: self.page,
+                position: position(ts),
+                dpi,
+                source: SourceLocation::from_span(self.world, span),
+            });
+        }
+    }
+
+    fn check_link(&mut self, dest: &Destination, ts: Transform) {
+        let (target, reason) = match dest {
+            Destination::Position(position) if position.page.get() > self.document.pages.len() => (
+                format!("page {}", position.page),
+                "the page does not exist".to_string(),
+            ),
+            Destination::Location(location)
+                if self
+                    .document
+                    .introspector
+                    .query(&Selector::Location(*location))
+                    .is_empty() =>
+            {
+                (
+                    "location".to_string(),
+                    "the linked element is not part of the document".to_string(),
+                )
+            }
+            _ => return,
+        };
+        self.report.broken_links.push(BrokenLink {
+            page: self.page,
+            position: position(ts),
+            target,
+            reason,
+            source: None,
+        });
+    }
+}
+
+/// Finds `link` elements pointing to labels that are missing or ambiguous.
+fn broken_label_links(world: &dyn World, document: &Document) -> Vec<BrokenLink> {
+    let introspector = &document.introspector;
+    introspector
+        .query(&Selector::Elem(LinkElem::elem(), None))
+        .iter()
+        .filter_map(|content| {
+            let link = content.to_packed::<LinkElem>()?;
+            let LinkTarget::Label(label) = link.dest else {
+                return None;
+            };
+            let reason = introspector.query_label(label).err()?;
+            let position = content
+                .location()
+                .map(|location| introspector.position(location));
+            Some(BrokenLink {
+                page: position.as_ref().map_or(1, |p| p.page.get()),
+                position: position.map_or((0.0, 0.0), |p| (p.point.x.to_pt(), p.point.y.to_pt())),
+                target: label.repr().to_string(),
+                reason: reason.to_string(),
+                source: SourceLocation::from_span(world, content.span()),
+            })
+        })
+        .collect()
+}
+
+/// Reports pages whose size differs from the most common page size.
+fn mismatched_pages(document: &Document) -> Vec<PageSizeMismatch> {
+    let key = |size: Size| {
+        let round = |v: f64| (v / SIZE_TOLERANCE).round() as i64;
+        (round(size.x.to_pt()), round(size.y.to_pt()))
+    };
+
+    let mut counts: HashMap<(i64, i64), (usize, Size)> = HashMap::new();
+    for page in &document.pages {
+        let size = page.frame.size();
+        counts.entry(key(size)).or_insert((0, size)).0 += 1;
+    }
+    let Some(&(_, expected)) = counts.values().max_by_key(|(count, _)| *count) else {
+        return vec![];
+    };
+
+    document
+        .pages
+        .iter()
+        .enumerate()
+        .filter(|(_, page)| key(page.frame.size()) != key(expected))
+        .map(|(i, page)| PageSizeMismatch {
+            page: i + 1,
+            width: page.frame.width().to_pt(),
+            height: page.frame.height().to_pt(),
+            expected_width: expected.x.to_pt(),
+            expected_height: expected.y.to_pt(),
+        })
+        .collect()
+}
+
+/// Reads the naming table and embedding permissions of a font.
+fn font_usage(font: &Font, pages: Vec<usize>) -> FontUsage {
+    let face = font.ttf();
+    let name = |id: u16| {
+        face.names()
+            .into_iter()
+            .filter(|name| name.name_id == id && name.is_unicode())
+            .find_map(|name| name.to_string())
+    };
+
+    let embedding = match face.permissions() {
+        Some(Permissions::Installable) => FontEmbedding::Installable,
+        Some(Permissions::Editable) => FontEmbedding::Editable,
+        Some(Permissions::PreviewAndPrint) => FontEmbedding::PreviewAndPrint,
+        Some(Permissions::Restricted) => FontEmbedding::Restricted,
+        None => FontEmbedding::Unknown,
+    };
+
+    let info = font.info();
+    FontUsage {
+        family: info.family.clone(),
+        postscript_name: name(name_id::POST_SCRIPT_NAME),
+        style: format!("{:?}", info.variant.style).to_lowercase(),
+        weight: info.variant.weight.to_number(),
+        license: name(name_id::LICENSE),
+        license_url: name(name_id::LICENSE_URL),
+        embedding,
+        embeddable: embedding != FontEmbedding::Restricted,
+        subsetting_allowed: face.is_subsetting_allowed(),
+        pages,
+    }
+}
+
+fn position(ts: Transform) -> (f64, f64) {
+    let point = Point::zero().transform(ts);
+    (point.x.to_pt(), point.y.to_pt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::test_world;
+    use std::path::Path;
+
+    const TEXT: &str = "#set page(width: 200pt, height: 200pt)\n\
+                        = Intro <intro>\n\
+                        See #link(<intro>)[the intro] and #link((page: 9, x: 0pt, y: 0pt))[page 9].\n\
+                        #image(\"tiny.png\", width: 1in)\n\
+                        #pagebreak()\n\
+                        #pagebreak()\n\
+                        #set page(width: 300pt)\n\
+                        Wide\n";
+
+    /// Writes a grey PNG of the given size in pixels.
+    fn write_png(path: &Path, size: u32) {
+        let file = std::fs::File::create(path).unwrap();
+        let mut encoder = png::Encoder::new(file, size, size);
+        encoder.set_color(png::ColorType::Grayscale);
+        let mut writer = encoder.write_header().unwrap();
+        writer
+            .write_image_data(&vec![128; (size * size) as usize])
+            .unwrap();
+    }
+
+    fn report(options: &PreflightOptions) -> PreflightReport {
+        let (dir, world) = test_world(&[("main.typ", TEXT)]);
+        write_png(&dir.path().join("tiny.png"), 100);
+        let document = typst::compile(&world).output.unwrap();
+        preflight(&world, &document, options)
+    }
+
+    #[test]
+    fn test_preflight() {
+        let report = report(&PreflightOptions::default());
+        assert!(report.has_issues());
+
+        let [image] = &report.low_resolution_images[..] else {
+            panic!("expected one image, got {:?}", report.low_resolution_images);
+        };
+        assert_eq!(image.page, 1);
+        assert!((image.dpi - 100.0).abs() < 0.01);
+        assert_eq!(image.source.as_ref().unwrap().path, Path::new("/main.typ"));
+
+        assert_eq!(report.empty_pages, [2]);
+
+        let [page] = &report.mismatched_pages[..] else {
+            panic!("expected one page, got {:?}", report.mismatched_pages);
+        };
+        assert_eq!(page.page, 3);
+        assert_eq!((page.width, page.height), (300.0, 200.0));
+        assert_eq!((page.expected_width, page.expected_height), (200.0, 200.0));
+
+        let [link] = &report.broken_links[..] else {
+            panic!("expected one link, got {:?}", report.broken_links);
+        };
+        assert_eq!(link.target, "page 9");
+        assert_eq!(link.reason, "the page does not exist");
+        assert_eq!(link.page, 1);
+
+        // The heading is set in a bold font used on the first page only.
+        assert!(report.fonts.iter().any(|font| font.pages == [1, 3]));
+        assert!(report
+            .fonts
+            .iter()
+            .any(|font| font.weight == 700 && font.pages == [1]));
+        assert!(report.fonts.iter().all(|font| font.embeddable));
+    }
+
+    #[test]
+    fn test_preflight_min_dpi() {
+        let options = PreflightOptions { min_dpi: 72.0 };
+        assert!(report(&options).low_resolution_images.is_empty());
+
+        let options: PreflightOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(options.min_dpi, 300.0);
+    }
+}
@@ -0,0 +1,209 @@
+use super::{image_files, page_image, ExportError, ImageFormat};
+use crate::project::WatchConfig;
+use parking_lot::RwLock;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use typst::layout::Frame;
+use typst::model::Document;
+use typst::World;
+
+/// Writes the exports configured for watch mode after each compilation,
+/// skipping files whose pages did not change since the last time.
+#[derive(Default)]
+pub struct WatchExporter {
+    /// A hash of the configuration the caches were filled with. Other
+    /// options produce other files, so the caches only apply to this one.
+    config: u128,
+    /// A hash of the pages in the last written PDF.
+    pdf: Option<u128>,
+    png: ExportCache,
+    svg: ExportCache,
+}
+
+impl WatchExporter {
+    /// Writes the exports of a freshly compiled document. The output paths are
+    /// resolved against the project root and must not leave it. Returns the
+    /// files that were written.
+    pub fn export(
+        &mut self,
+        world: &dyn World,
+        root: &Path,
+        document: &Document,
+        config: &WatchConfig,
+    ) -> Result<Vec<PathBuf>, ExportError> {
+        let hash = typst::utils::hash128(config);
+        if hash != self.config {
+            *self = Self {
+                config: hash,
+                ..Default::default()
+            };
+        }
+
+        let root = root.canonicalize()?;
+        let mut written = vec![];
+        if let Some(path) = &config.pdf {
+            let path = output_path(&root, path)?;
+            let frames: Vec<&Frame> = document.pages.iter().map(|page| &page.frame).collect();
+            let hash = typst::utils::hash128(&(frames, &document.info));
+            if self.pdf != Some(hash) || !path.exists() {
+                let buffer = super::pdf(world, document, &config.pdf_options)?;
+                write(&root, &path, &buffer)?;
+                self.pdf = Some(hash);
+                written.push(path);
+            }
+        }
+
+        let name = super::document_name(world);
+        for (format, dir, cache) in [
+            (ImageFormat::Png, &config.png, &self.png),
+            (ImageFormat::Svg, &config.svg, &self.svg),
+        ] {
+            let Some(dir) = dir else {
+                continue;
+            };
+            let dir = output_path(&root, dir)?;
+            for (i, file_name) in image_files(document, format, &name, &config.image_options)? {
+                let path = dir.join(file_name);
+                let hash = typst::utils::hash128(&document.pages[i].frame);
+                if !cache.is_cached(i, hash) || !path.exists() {
+                    let data = page_image(&document.pages[i], format, &config.image_options)?;
+                    write(&root, &path, &data)?;
+                    cache.insert(i, hash);
+                    written.push(path);
+                }
+            }
+            cache.truncate(document.pages.len());
+        }
+        Ok(written)
+    }
+}
+
+/// Resolves a configured output path against the project root. Fails for
+/// absolute paths and paths with `..` parts, which could leave the root.
+fn output_path(root: &Path, path: &Path) -> Result<PathBuf, ExportError> {
+    let relative = path
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    if !relative {
+        return Err(outside(path));
+    }
+    Ok(root.join(path))
+}
+
+/// Writes a file below the project root, creating its directories. Fails if
+/// they resolve to a place outside of the root, e.g. through a symbolic link.
+fn write(root: &Path, path: &Path, data: &[u8]) -> Result<(), ExportError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+        if !parent.canonicalize()?.starts_with(root) {
+            return Err(outside(path));
+        }
+    }
+    fs::write(path, data)?;
+    Ok(())
+}
+
+fn outside(path: &Path) -> ExportError {
+    ExportError::Output(format!(
+        "`{}` is outside of the project root",
+        path.display()
+    ))
+}
+
+/// Caches exported files so that we can avoid re-exporting them if they haven't
+/// changed.
+///
+/// This is done by having a list of size `files.len()` that contains the hashes
+/// of the last written frame in each file. A hash is only recorded once its
+/// file was written, so that a failed write is retried the next time.
+#[derive(Default)]
+pub struct ExportCache {
+    /// The hashes of the written frames.
+    pub cache: RwLock<Vec<Option<u128>>>,
+}
+
+impl ExportCache {
+    /// Creates a new export cache.
+    pub fn new() -> Self {
+        Self {
+            cache: RwLock::new(Vec::with_capacity(32)),
+        }
+    }
+
+    /// Whether the file of a page was written from a frame with this hash.
+    pub fn is_cached(&self, i: usize, hash: u128) -> bool {
+        self.cache.read().get(i) == Some(&Some(hash))
+    }
+
+    /// Records the hash of a page's frame after its file was written.
+    pub fn insert(&self, i: usize, hash: u128) {
+        let mut cache = self.cache.write();
+        if i >= cache.len() {
+            cache.resize(i + 1, None);
+        }
+        cache[i] = Some(hash);
+    }
+
+    /// Forgets the pages after the last one of the document.
+    pub fn truncate(&self, len: usize) {
+        self.cache.write().truncate(len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_output_path() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        assert_eq!(
+            output_path(&root, Path::new("out/doc.pdf")).unwrap(),
+            root.join("out/doc.pdf")
+        );
+        assert!(output_path(&root, Path::new("./doc.pdf")).is_ok());
+        for path in ["../doc.pdf", "out/../../doc.pdf", "/tmp/doc.pdf"] {
+            assert!(
+                matches!(
+                    output_path(&root, Path::new(path)),
+                    Err(ExportError::Output(_))
+                ),
+                "{path}"
+            );
+        }
+
+        write(&root, &root.join("out/a/doc.pdf"), b"%PDF").unwrap();
+        assert!(root.join("out/a/doc.pdf").is_file());
+
+        #[cfg(unix)]
+        {
+            let other = TempDir::new().unwrap();
+            std::os::unix::fs::symlink(other.path(), root.join("link")).unwrap();
+            let path = root.join("link/doc.pdf");
+            assert!(matches!(
+                write(&root, &path, b"%PDF"),
+                Err(ExportError::Output(_))
+            ));
+            assert!(!other.path().join("doc.pdf").exists());
+        }
+    }
+
+    #[test]
+    fn test_export_cache() {
+        let cache = ExportCache::new();
+        assert!(!cache.is_cached(0, 1));
+
+        // A page counts as cached only once its file was written.
+        cache.insert(1, 2);
+        assert!(!cache.is_cached(0, 2));
+        assert!(cache.is_cached(1, 2));
+        assert!(!cache.is_cached(1, 3));
+        cache.insert(1, 3);
+        assert!(cache.is_cached(1, 3));
+
+        cache.truncate(1);
+        assert!(!cache.is_cached(1, 3));
+    }
+}
@@ -0,0 +1,488 @@
+use super::{SymbolIndex, TextEdit};
+use serde::Serialize;
+use std::ops::Range;
+use std::path::Path;
+use typst::diag::SourceDiagnostic;
+use typst::syntax::ast::{self, AstNode};
+use typst::syntax::{LinkedNode, Side, Source, Span, SyntaxKind};
+use typst::World;
+
+/// How many spelling suggestions are offered for an unknown identifier.
+const MAX_SUGGESTIONS: usize = 3;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CodeActionKind {
+    /// Imports an unknown identifier from the project file defining it.
+    AddImport,
+    /// Replaces an unknown identifier with a similar one in scope.
+    FixTypo,
+    /// Turns an expression or argument list into a content block.
+    WrapContent,
+    /// Escapes a markup character that was meant literally.
+    Escape,
+    /// Spells out a multi-letter identifier in math as text or letters.
+    MathText,
+}
+
+/// A quick fix for a diagnostic, applied through edits to the same file.
+#[derive(Serialize, Debug)]
+pub struct CodeAction {
+    pub title: String,
+    pub kind: CodeActionKind,
+    /// The message of the diagnostic this action fixes.
+    pub diagnostic: String,
+    pub edits: Vec<TextEdit>,
+}
+
+/// Proposes fixes for the diagnostics of `source` that overlap the byte
+/// range. The diagnostics are those of the last compilation; ones that no
+/// longer resolve to a location in the source are skipped.
+pub fn code_actions(
+    world: &dyn World,
+    source: &Source,
+    diagnostics: &[SourceDiagnostic],
+    symbols: &SymbolIndex,
+    range: Range<usize>,
+) -> Vec<CodeAction> {
+    let mut actions = Vec::new();
+    for diagnostic in diagnostics {
+        if diagnostic.span.id() != Some(source.id()) {
+            continue;
+        }
+        let Some(span_range) = source.range(diagnostic.span) else {
+            continue;
+        };
+        if span_range.end < range.start || span_range.start > range.end {
+            continue;
+        }
+
+        let mut push = |title: String, kind, edits| {
+            actions.push(CodeAction {
+                title,
+                kind,
+                diagnostic: diagnostic.message.to_string(),
+                edits,
+            })
+        };
+
+        let message = diagnostic.message.as_str();
+        if let Some(name) = message.strip_prefix("unknown variable: ") {
+            for (title, kind, edits) in unknown_variable(world, source, symbols, &span_range, name)
+            {
+                push(title, kind, edits);
+            }
+        }
+        if message.starts_with("expected content, found") {
+            if let Some(edit) = wrap_expr(source, diagnostic.span) {
+                push(
+                    "Wrap in content block".into(),
+                    CodeActionKind::WrapContent,
+                    vec![edit],
+                );
+            }
+        }
+        if message == "expected comma" || message.starts_with("unknown variable: ") {
+            if let Some(edit) = bracket_args(source, diagnostic.span) {
+                push(
+                    "Pass as content block".into(),
+                    CodeActionKind::WrapContent,
+                    vec![edit],
+                );
+            }
+        }
+        if let Some((c, edit)) = escape(source, &span_range, message) {
+            push(format!("Escape `{c}`"), CodeActionKind::Escape, vec![edit]);
+        }
+    }
+    actions
+}
+
+/// Fixes for an unknown identifier: similarly named identifiers in scope,
+/// imports from project files defining it and, in math, text alternatives.
+fn unknown_variable(
+    world: &dyn World,
+    source: &Source,
+    symbols: &SymbolIndex,
+    range: &Range<usize>,
+    name: &str,
+) -> Vec<(String, CodeActionKind, Vec<TextEdit>)> {
+    let mut fixes = Vec::new();
+
+    let in_math = LinkedNode::new(source.root())
+        .leaf_at(range.end, Side::Before)
+        .is_some_and(|leaf| leaf.kind() == SyntaxKind::MathIdent);
+
+    let mut candidates: Vec<(usize, String)> = scope_names(world, source, range.start, in_math)
+        .into_iter()
+        .filter_map(|candidate| {
+            let distance = edit_distance(name, &candidate);
+            (candidate != name && distance <= max_distance(name)).then_some((distance, candidate))
+        })
+        .collect();
+    candidates.sort();
+    candidates.dedup_by(|a, b| a.1 == b.1);
+    for (_, label) in candidates.into_iter().take(MAX_SUGGESTIONS) {
+        fixes.push((
+            format!("Change to `{label}`"),
+            CodeActionKind::FixTypo,
+            vec![TextEdit::new(source, range.clone(), label)],
+        ));
+    }
+
+    let current = source.id().vpath().as_rooted_path();
+    for symbol in symbols.definitions(name) {
+        if symbol.location.path == current {
+            continue;
+        }
+        let path = import_path(current, &symbol.location.path);
+        let (offset, prefix) = import_offset(source);
+        fixes.push((
+            format!("Import `{name}` from `{path}`"),
+            CodeActionKind::AddImport,
+            vec![TextEdit::new(
+                source,
+                offset..offset,
+                format!("{prefix}#import \"{path}\": {name}\n"),
+            )],
+        ));
+    }
+
+    if in_math && name.chars().count() > 1 {
+        let letters = name.chars().map(String::from).collect::<Vec<_>>().join(" ");
+        fixes.push((
+            format!("Display as text `\"{name}\"`"),
+            CodeActionKind::MathText,
+            vec![TextEdit::new(source, range.clone(), format!("\"{name}\""))],
+        ));
+        fixes.push((
+            format!("Separate letters `{letters}`"),
+            CodeActionKind::MathText,
+            vec![TextEdit::new(source, range.clone(), letters)],
+        ));
+    }
+
+    fixes
+}
+
+/// The names a misspelled identifier may have meant: those of the standard
+/// library and those bound in the file before the offset. Bindings are
+/// collected regardless of their scope, which is good enough for suggestions.
+fn scope_names(world: &dyn World, source: &Source, offset: usize, math: bool) -> Vec<String> {
+    let library = world.library();
+    let mut names: Vec<String> = library
+        .global
+        .scope()
+        .iter()
+        .chain(
+            math.then(|| library.math.scope().iter())
+                .into_iter()
+                .flatten(),
+        )
+        .map(|(name, _, _)| name.to_string())
+        .collect();
+    collect_bindings(&LinkedNode::new(source.root()), offset, &mut names);
+    names
+}
+
+fn collect_bindings(node: &LinkedNode, offset: usize, names: &mut Vec<String>) {
+    if node.offset() >= offset {
+        return;
+    }
+
+    let idents = match node.kind() {
+        SyntaxKind::LetBinding => node
+            .cast::<ast::LetBinding>()
+            .map(|binding| binding.kind().bindings()),
+        SyntaxKind::ForLoop => node
+            .cast::<ast::ForLoop>()
+            .map(|for_loop| for_loop.pattern().bindings()),
+        SyntaxKind::Params => node.cast::<ast::Params>().map(|params| {
+            params
+                .children()
+                .flat_map(|param| match param {
+                    ast::Param::Pos(pattern) => pattern.bindings(),
+                    ast::Param::Named(named) => vec![named.name()],
+                    ast::Param::Spread(spread) => spread.sink_ident().into_iter().collect(),
+                })
+                .collect()
+        }),
+        SyntaxKind::ModuleImport => node.cast::<ast::ModuleImport>().map(|import| {
+            let mut idents: Vec<_> = import.new_name().into_iter().collect();
+            if let Some(ast::Imports::Items(items)) = import.imports() {
+                idents.extend(items.iter().map(|item| item.bound_name()));
+            }
+            idents
+        }),
+        _ => None,
+    };
+    names.extend(
+        idents
+            .into_iter()
+            .flatten()
+            .map(|ident| ident.as_str().to_string()),
+    );
+
+    for child in node.children() {
+        collect_bindings(&child, offset, names);
+    }
+}
+
+/// Wraps an expression that was passed where content is expected.
+fn wrap_expr(source: &Source, span: Span) -> Option<TextEdit> {
+    let node = source.find(span)?;
+    let expr = node.cast::<ast::Expr>()?;
+    let range = node.range();
+    let text = &source.text()[range.clone()];
+    let new_text = if expr.hash() {
+        format!("[#{text}]")
+    } else {
+        format!("[#({text})]")
+    };
+    Some(TextEdit::new(source, range, new_text))
+}
+
+/// Turns the parenthesized arguments of a call into a trailing content block,
+/// as in `emph(hello world)` to `emph[hello world]`. Only applies to calls
+/// with a single argument and without trailing content.
+fn bracket_args(source: &Source, span: Span) -> Option<TextEdit> {
+    let node = source.find(span)?;
+    let args = std::iter::successors(Some(node), |node| node.parent().cloned())
+        .find(|node| node.kind() == SyntaxKind::Args)?;
+    if args.parent_kind() != Some(SyntaxKind::FuncCall) {
+        return None;
+    }
+
+    let children: Vec<_> = args.children().collect();
+    let (first, last) = (children.first()?, children.last()?);
+    if first.kind() != SyntaxKind::LeftParen
+        || last.kind() != SyntaxKind::RightParen
+        || children
+            .iter()
+            .any(|child| matches!(child.kind(), SyntaxKind::Named | SyntaxKind::Comma))
+    {
+        return None;
+    }
+
+    let inner = &source.text()[first.range().end..last.offset()];
+    Some(TextEdit::new(
+        source,
+        args.range(),
+        format!("[{}]", inner.trim()),
+    ))
+}
+
+/// Escapes a markup character that starts unintended markup, such as the `$`
+/// in `Cost: $5` or the `@` in an email address.
+fn escape(source: &Source, range: &Range<usize>, message: &str) -> Option<(char, TextEdit)> {
+    let applies = message == "unclosed delimiter"
+        || message == "unclosed label"
+        || (message.starts_with("label `") && message.ends_with("does not exist in the document"));
+    if !applies {
+        return None;
+    }
+
+    let c = source.text()[range.start..].chars().next()?;
+    if !matches!(c, '*' | '_' | '$' | '`' | '<' | '@') {
+        return None;
+    }
+
+    let leaf = LinkedNode::new(source.root()).leaf_at(range.start, Side::After)?;
+    if !in_markup(&leaf) {
+        return None;
+    }
+
+    let edit = TextEdit::new(source, range.start..range.start, "\\".into());
+    Some((c, edit))
+}
+
+/// Whether the node sits in markup rather than in code or math.
+fn in_markup(node: &LinkedNode) -> bool {
+    let mut parent = node.parent();
+    while let Some(node) = parent {
+        match node.kind() {
+            SyntaxKind::Markup => return true,
+            SyntaxKind::Code
+            | SyntaxKind::CodeBlock
+            | SyntaxKind::Math
+            | SyntaxKind::Args
+            | SyntaxKind::Array
+            | SyntaxKind::Dict
+            | SyntaxKind::Parenthesized => return false,
+            _ => parent = node.parent(),
+        }
+    }
+    false
+}
+
+/// The path to import `target` with from `current`, both rooted project
+/// paths. Files in the same directory or below are imported relatively,
+/// others relative to the project root.
+fn import_path(current: &Path, target: &Path) -> String {
+    let relative = current
+        .parent()
+        .and_then(|dir| target.strip_prefix(dir).ok());
+    match relative {
+        Some(relative) => relative.to_string_lossy().replace('\\', "/"),
+        None => target.to_string_lossy().replace('\\', "/"),
+    }
+}
+
+/// Where a new import goes: after the last top-level import, or at the start
+/// of the file. Also returns a line break to put in front of the import if
+/// the last import is not followed by one.
+fn import_offset(source: &Source) -> (usize, &'static str) {
+    let root = LinkedNode::new(source.root());
+    let Some(import) = root
+        .children()
+        .filter(|child| child.kind() == SyntaxKind::ModuleImport)
+        .last()
+    else {
+        return (0, "");
+    };
+
+    let end = import.range().end;
+    match source.text()[end..].find('\n') {
+        Some(newline) => (end + newline + 1, ""),
+        None => (source.len_bytes(), "\n"),
+    }
+}
+
+/// The largest edit distance at which a name still counts as a misspelling.
+fn max_distance(name: &str) -> usize {
+    (name.chars().count() / 3).clamp(1, 3)
+}
+
+/// The edit distance between two strings in characters, where swapping two
+/// adjacent characters counts as a single edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let width = b.len() + 1;
+    let mut d = vec![0; (a.len() + 1) * width];
+    for i in 0..=a.len() {
+        d[i * width] = i;
+    }
+    for (j, cell) in d[..width].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (d[(i - 1) * width + j] + 1)
+                .min(d[i * width + j - 1] + 1)
+                .min(d[(i - 1) * width + j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(d[(i - 2) * width + j - 2] + 1);
+            }
+            d[i * width + j] = best;
+        }
+    }
+    d[a.len() * width + b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::test_world;
+
+    const UTIL: &str = "#let shout(x) = upper(x)\n#let whisper(x) = lower(x)\n";
+
+    fn actions_for(text: &str) -> Vec<CodeAction> {
+        let (dir, world) = test_world(&[("main.typ", text), ("lib/util.typ", UTIL)]);
+        let source = world.source(world.main()).unwrap();
+        let diagnostics = typst::compile(&world).output.unwrap_err();
+        let symbols = SymbolIndex::build(dir.path());
+        code_actions(&world, &source, &diagnostics, &symbols, 0..text.len())
+    }
+
+    fn fixes(actions: &[CodeAction], kind: CodeActionKind) -> Vec<&str> {
+        actions
+            .iter()
+            .filter(|action| action.kind == kind)
+            .map(|action| action.edits[0].new_text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("heading", "heading"), 0);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        // A swap of adjacent characters is a single edit.
+        assert_eq!(edit_distance("haeding", "heading"), 1);
+        assert_eq!(edit_distance("größe", "grösse"), 2);
+
+        assert_eq!(max_distance("ab"), 1);
+        assert_eq!(max_distance("heading"), 2);
+        assert_eq!(max_distance("a-very-long-name"), 3);
+    }
+
+    #[test]
+    fn test_import_path() {
+        let current = Path::new("/chapters/intro.typ");
+        assert_eq!(
+            import_path(current, Path::new("/chapters/lib/util.typ")),
+            "lib/util.typ"
+        );
+        assert_eq!(
+            import_path(current, Path::new("/template.typ")),
+            "/template.typ"
+        );
+    }
+
+    #[test]
+    fn test_fix_typo() {
+        let actions = actions_for("#let size = 1pt\n#siez\n");
+        assert_eq!(fixes(&actions, CodeActionKind::FixTypo), ["size"]);
+        assert_eq!(actions[0].diagnostic, "unknown variable: siez");
+        let actions = actions_for("#haeding[Intro]\n");
+        assert_eq!(fixes(&actions, CodeActionKind::FixTypo), ["heading"]);
+    }
+
+    #[test]
+    fn test_add_import() {
+        let actions = actions_for("#import \"lib/util.typ\": whisper\n#shout[hi]\n");
+        let action = actions
+            .iter()
+            .find(|action| action.kind == CodeActionKind::AddImport)
+            .unwrap();
+        assert_eq!(action.title, "Import `shout` from `lib/util.typ`");
+        // The import goes below the existing one.
+        assert_eq!(action.edits[0].range, 32..32);
+        assert_eq!(
+            action.edits[0].new_text,
+            "#import \"lib/util.typ\": shout\n"
+        );
+    }
+
+    #[test]
+    fn test_math_text() {
+        let actions = actions_for("$ area = pi r^2 $\n");
+        assert_eq!(
+            fixes(&actions, CodeActionKind::MathText),
+            ["\"area\"", "a r e a"]
+        );
+    }
+
+    #[test]
+    fn test_escape() {
+        let actions = actions_for("Cost: $5\n");
+        let action = actions
+            .iter()
+            .find(|action| action.kind == CodeActionKind::Escape)
+            .unwrap();
+        assert_eq!(action.title, "Escape `$`");
+        assert_eq!(action.edits[0].range, 6..6);
+        assert_eq!(action.edits[0].new_text, "\\");
+    }
+
+    #[test]
+    fn test_wrap_content() {
+        let actions = actions_for("#emph(hello world)\n");
+        assert_eq!(
+            fixes(&actions, CodeActionKind::WrapContent),
+            ["[hello world]"]
+        );
+    }
+}
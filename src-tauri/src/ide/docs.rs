@@ -0,0 +1,234 @@
+use super::signature::native_param;
+use super::{describe_cast, SignatureParam};
+use serde::Serialize;
+use typst::foundations::{Func, Repr, Scope, Value};
+use typst::Library;
+
+/// Modules whose entries are symbols rather than definitions.
+const SYMBOL_MODULES: &[&str] = &["sym", "emoji"];
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DocKind {
+    Function,
+    Type,
+    Module,
+    Constant,
+}
+
+/// The documentation of a definition in the standard library.
+#[derive(Serialize, Debug)]
+pub struct DocItem {
+    /// The path under which the definition is reachable, e.g. `calc.pow` or
+    /// `str.len` for a method.
+    pub path: String,
+    pub name: String,
+    pub kind: DocKind,
+    /// The title case name, e.g. `Power` for `calc.pow`.
+    pub title: Option<String>,
+    /// The category the definition is listed under, e.g. `text` or `layout`.
+    pub category: Option<String>,
+    /// Markdown documentation.
+    pub docs: Option<String>,
+    /// The parameters of a function or of a type's constructor.
+    pub params: Vec<SignatureParam>,
+    pub returns: Option<String>,
+    /// The value of a constant.
+    pub value: Option<String>,
+    /// Extra search terms.
+    pub keywords: Vec<String>,
+}
+
+/// A variant of a symbol in the `sym` or `emoji` module.
+#[derive(Serialize, Debug)]
+pub struct SymbolDoc {
+    /// The full name to insert, e.g. `sym.arrow.r`.
+    pub name: String,
+    pub symbol: String,
+    /// The codepoint in `U+XXXX` notation.
+    pub codepoint: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LibraryDocs {
+    pub items: Vec<DocItem>,
+    pub symbols: Vec<SymbolDoc>,
+}
+
+/// Collects the documentation of all functions, types, methods, modules and
+/// symbols of the standard library. Everything is compiled into typst, so
+/// this works offline.
+pub fn library_docs(library: &Library) -> LibraryDocs {
+    let mut docs = LibraryDocs {
+        items: Vec::new(),
+        symbols: Vec::new(),
+    };
+    walk_scope(library.global.scope(), None, None, &mut docs);
+    docs.items.sort_by(|a, b| a.path.cmp(&b.path));
+    docs
+}
+
+/// Documents the definitions in a scope. Definitions without a category of
+/// their own, such as methods, inherit the one of their parent.
+fn walk_scope(
+    scope: &Scope,
+    prefix: Option<&str>,
+    parent_category: Option<&str>,
+    docs: &mut LibraryDocs,
+) {
+    for (name, value, _) in scope.iter() {
+        let path = match prefix {
+            Some(prefix) => format!("{prefix}.{name}"),
+            None => name.to_string(),
+        };
+        let category = scope
+            .get_category(name)
+            .map(|category| category.name())
+            .or(parent_category);
+
+        match value {
+            Value::Func(func) => {
+                docs.items.push(func_item(&path, name, func, category));
+                if let Some(scope) = func.scope() {
+                    walk_scope(scope, Some(&path), category, docs);
+                }
+            }
+            Value::Type(ty) => {
+                let constructor = ty.constructor().ok();
+                docs.items.push(DocItem {
+                    path: path.clone(),
+                    name: name.to_string(),
+                    kind: DocKind::Type,
+                    title: Some(ty.title().into()),
+                    category: category.map(Into::into),
+                    docs: Some(ty.docs().into()),
+                    params: constructor
+                        .as_ref()
+                        .and_then(Func::params)
+                        .map_or_else(Vec::new, |params| params.iter().map(native_param).collect()),
+                    returns: None,
+                    value: None,
+                    keywords: ty.keywords().iter().map(|k| k.to_string()).collect(),
+                });
+                walk_scope(ty.scope(), Some(&path), category, docs);
+            }
+            Value::Module(module) if SYMBOL_MODULES.contains(&name.as_str()) => {
+                walk_symbols(module.scope(), &path, docs);
+            }
+            Value::Module(module) => {
+                docs.items.push(DocItem {
+                    path: path.clone(),
+                    name: name.to_string(),
+                    kind: DocKind::Module,
+                    title: None,
+                    category: category.map(Into::into),
+                    docs: None,
+                    params: vec![],
+                    returns: None,
+                    value: None,
+                    keywords: vec![],
+                });
+                walk_scope(module.scope(), Some(&path), category, docs);
+            }
+            // Symbols outside of the symbol modules, e.g. in `math`, are
+            // re-exports.
+            Value::Symbol(_) => {}
+            value => docs.items.push(DocItem {
+                path,
+                name: name.to_string(),
+                kind: DocKind::Constant,
+                title: None,
+                category: category.map(Into::into),
+                docs: None,
+                params: vec![],
+                returns: None,
+                value: Some(value.repr().to_string()),
+                keywords: vec![],
+            }),
+        }
+    }
+}
+
+fn func_item(path: &str, name: &str, func: &Func, category: Option<&str>) -> DocItem {
+    DocItem {
+        path: path.into(),
+        name: name.into(),
+        kind: DocKind::Function,
+        title: func.title().map(Into::into),
+        category: category.map(Into::into),
+        docs: func.docs().map(Into::into),
+        params: func
+            .params()
+            .map_or_else(Vec::new, |params| params.iter().map(native_param).collect()),
+        returns: func.returns().map(describe_cast),
+        value: None,
+        keywords: func.keywords().iter().map(|k| k.to_string()).collect(),
+    }
+}
+
+/// Lists every variant of the symbols in a symbol module under its full
+/// name, e.g. `sym.arrow.r.double`.
+fn walk_symbols(scope: &Scope, prefix: &str, docs: &mut LibraryDocs) {
+    for (name, value, _) in scope.iter() {
+        let Value::Symbol(symbol) = value else {
+            continue;
+        };
+        for (modifiers, c) in symbol.variants() {
+            let c = c.char();
+            docs.symbols.push(SymbolDoc {
+                name: if modifiers.is_empty() {
+                    format!("{prefix}.{name}")
+                } else {
+                    format!("{prefix}.{name}.{modifiers}")
+                },
+                symbol: c.to_string(),
+                codepoint: format!("U+{:04X}", c as u32),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_library_docs() {
+        let docs = library_docs(&Library::default());
+        let item = |path: &str| docs.items.iter().find(|item| item.path == path).unwrap();
+
+        assert!(docs.items.windows(2).all(|w| w[0].path <= w[1].path));
+
+        let pow = item("calc.pow");
+        assert_eq!(pow.kind, DocKind::Function);
+        assert_eq!(pow.title.as_deref(), Some("Power"));
+        assert_eq!(pow.category.as_deref(), Some("foundations"));
+        assert_eq!(item("calc").kind, DocKind::Module);
+        assert_eq!(item("calc.pi").kind, DocKind::Constant);
+
+        let string = item("str");
+        assert_eq!(string.kind, DocKind::Type);
+        assert!(!string.params.is_empty());
+        // Methods inherit the category of their type.
+        let len = item("str.len");
+        assert_eq!(len.kind, DocKind::Function);
+        assert_eq!(len.category, string.category);
+
+        let text = item("text");
+        assert_eq!(text.returns.as_deref(), Some("content"));
+        assert!(text.params.iter().any(|param| param.name == "fill"));
+    }
+
+    #[test]
+    fn test_symbol_docs() {
+        let docs = library_docs(&Library::default());
+        assert!(docs.items.iter().all(|item| !item.path.starts_with("sym.")));
+
+        let symbol = |name: &str| docs.symbols.iter().find(|symbol| symbol.name == name);
+        let arrow = symbol("sym.arrow.r").unwrap();
+        assert_eq!(arrow.symbol, "→");
+        assert_eq!(arrow.codepoint, "U+2192");
+        assert!(symbol("sym.plus").is_some());
+        assert!(symbol("emoji.cat").is_some());
+    }
+}
@@ -0,0 +1,395 @@
+use crate::export::check_render_size;
+use crate::project::ProjectWorld;
+use base64::Engine as _;
+use comemo::Track;
+use serde::Serialize;
+use std::ops::Range;
+use std::path::Path;
+use typst::diag::{FileResult, SourceDiagnostic};
+use typst::engine::{Engine, Route, Sink, Traced};
+use typst::foundations::{Content, Repr, Smart, StyleChain, Styles, Value};
+use typst::introspection::{Introspector, Locator};
+use typst::layout::{layout_frame, Abs, Axes, Page, Region, Size};
+use typst::model::Document;
+use typst::syntax::{LinkedNode, Side, Source, SyntaxKind};
+use typst::World;
+
+/// At most this many values are reported for an expression that is
+/// evaluated repeatedly, e.g. in a loop or a function called several times.
+const MAX_VALUES: usize = 10;
+
+/// The width available to content results when they are rendered.
+const CONTENT_WIDTH: f64 = 480.0;
+
+#[derive(Serialize, Debug, Default)]
+pub struct Evaluation {
+    /// The values the expression took, one for each time it was evaluated.
+    pub values: Vec<EvalValue>,
+    pub errors: Vec<EvalError>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EvalValue {
+    /// The name of the value's type.
+    pub ty: String,
+    pub repr: String,
+    /// A rendering of the value if it is content.
+    pub image: Option<EvalImage>,
+}
+
+/// A PNG image, encoded as base64.
+#[derive(Serialize, Debug)]
+pub struct EvalImage {
+    pub image: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EvalError {
+    pub message: String,
+    pub hints: Vec<String>,
+}
+
+impl From<&SourceDiagnostic> for EvalError {
+    fn from(diagnostic: &SourceDiagnostic) -> Self {
+        Self {
+            message: diagnostic.message.to_string(),
+            hints: diagnostic.hints.iter().map(ToString::to_string).collect(),
+        }
+    }
+}
+
+/// Evaluates an expression in the scope of the given byte offset of a file.
+///
+/// The expression is inserted into the file in front of the statement or
+/// markup at the cursor and its values are traced during a compilation of the
+/// project, so it sees all bindings at that point. Afterwards the file is
+/// restored. Expressions that need context, such as `state("x").final()`,
+/// are retried in a `context` block and rendered against the introspector of
+/// the last document.
+pub fn evaluate(
+    world: &mut ProjectWorld,
+    path: &Path,
+    source: &Source,
+    cursor: usize,
+    expression: &str,
+    document: Option<&Document>,
+    scale: f32,
+) -> FileResult<Evaluation> {
+    let restore = Restore {
+        world,
+        path,
+        text: source.text(),
+    };
+    let mut evaluation = Evaluation::default();
+    for contextual in [false, true] {
+        let (text, range) = probe(source, cursor, expression, contextual);
+        let id = restore.world.slot_update(path, Some(text))?;
+        let probe = restore.world.source(id)?;
+        evaluation = trace(restore.world, &probe, range, document, scale);
+
+        let needs_context = evaluation
+            .errors
+            .iter()
+            .any(|error| error.message.contains("context is known"));
+        if !needs_context {
+            break;
+        }
+    }
+
+    Ok(evaluation)
+}
+
+/// Puts the original text back into a file's slot when dropped, so that the
+/// probe is removed however the evaluation ends.
+struct Restore<'a> {
+    world: &'a mut ProjectWorld,
+    path: &'a Path,
+    text: &'a str,
+}
+
+impl Drop for Restore<'_> {
+    fn drop(&mut self) {
+        let _ = self
+            .world
+            .slot_update(self.path, Some(self.text.to_string()));
+    }
+}
+
+/// Inserts a binding of the expression into the source. Returns the new text
+/// and the byte range of the parenthesized expression in it.
+fn probe(
+    source: &Source,
+    cursor: usize,
+    expression: &str,
+    contextual: bool,
+) -> (String, Range<usize>) {
+    let (offset, markup) = insertion_point(source, cursor);
+    // The line break keeps a trailing line comment from swallowing the
+    // closing parenthesis. Binding the value keeps it from being joined with
+    // the surrounding content.
+    let expression = if contextual {
+        format!("(context ({expression}\n))")
+    } else {
+        format!("({expression}\n)")
+    };
+    let prefix = if markup { "#let _ = " } else { "let _ = " };
+
+    let text = source.text();
+    let start = offset + prefix.len();
+    let range = start..start + expression.len();
+    let text = format!(
+        "{}{prefix}{expression};{}",
+        &text[..offset],
+        &text[offset..]
+    );
+    (text, range)
+}
+
+/// Finds the start of the statement or markup element containing the cursor
+/// and whether it is in markup.
+fn insertion_point(source: &Source, cursor: usize) -> (usize, bool) {
+    let root = LinkedNode::new(source.root());
+    let Some(mut node) = root.leaf_at(cursor, Side::Before) else {
+        return (0, true);
+    };
+
+    while let Some(parent) = node.parent() {
+        let markup = match parent.kind() {
+            SyntaxKind::Markup => true,
+            SyntaxKind::Code => false,
+            _ => {
+                node = parent.clone();
+                continue;
+            }
+        };
+        // Between elements, insert in front of the next one.
+        let offset = if node.kind().is_trivia() {
+            node.range().end
+        } else if node.prev_sibling_kind() == Some(SyntaxKind::Hash) {
+            node.prev_sibling().map_or(0, |n| n.offset())
+        } else {
+            node.offset()
+        };
+        return (offset, markup);
+    }
+    (0, true)
+}
+
+/// Traces the values of the probe expression and renders content results.
+/// If the expression is never evaluated, compiles the project for the errors
+/// that prevented it.
+fn trace(
+    world: &dyn World,
+    source: &Source,
+    range: Range<usize>,
+    document: Option<&Document>,
+    scale: f32,
+) -> Evaluation {
+    let mut evaluation = Evaluation::default();
+    let node = LinkedNode::new(source.root())
+        .leaf_at(range.start, Side::After)
+        .and_then(|leaf| leaf.parent().cloned())
+        .filter(|node| node.range() == range);
+    let Some(node) = node else {
+        evaluation.errors.push(EvalError {
+            message: "the expression could not be parsed".into(),
+            hints: vec![],
+        });
+        return evaluation;
+    };
+
+    let values = typst_ide::analyze_expr(world, &node);
+    if values.is_empty() {
+        evaluation.errors = compile_errors(world, source, &range);
+        return evaluation;
+    }
+
+    for (value, styles) in values.into_iter().take(MAX_VALUES) {
+        let image = match &value {
+            Value::Content(content) => {
+                match render(world, content, styles.as_ref(), document, scale) {
+                    Ok(image) => image,
+                    Err(errors) => {
+                        evaluation.errors.extend(errors);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        evaluation.values.push(EvalValue {
+            ty: value.ty().to_string(),
+            repr: value.repr().to_string(),
+            image,
+        });
+    }
+    evaluation
+}
+
+/// Collects the compilation errors inside the probe expression.
+fn compile_errors(world: &dyn World, source: &Source, range: &Range<usize>) -> Vec<EvalError> {
+    let errors: Vec<EvalError> = match typst::compile(world).output {
+        Ok(_) => vec![],
+        Err(diagnostics) => diagnostics
+            .iter()
+            .filter(|diagnostic| {
+                diagnostic.span.id() == Some(source.id())
+                    && source
+                        .range(diagnostic.span)
+                        .is_some_and(|r| range.start <= r.start && r.end <= range.end)
+            })
+            .map(EvalError::from)
+            .collect(),
+    };
+
+    if errors.is_empty() {
+        return vec![EvalError {
+            message: "the expression was not evaluated".into(),
+            hints: vec!["the file may not be reachable from the main file".into()],
+        }];
+    }
+    errors
+}
+
+/// Lays out content on its own and renders it to a PNG.
+fn render(
+    world: &dyn World,
+    content: &Content,
+    styles: Option<&Styles>,
+    document: Option<&Document>,
+    scale: f32,
+) -> Result<Option<EvalImage>, Vec<EvalError>> {
+    let library = world.library();
+    let base = StyleChain::new(&library.styles);
+    let styles = match styles {
+        Some(styles) => base.chain(styles),
+        None => base,
+    };
+
+    let introspector = document.map_or_else(Introspector::default, |doc| doc.introspector.clone());
+    let traced = Traced::default();
+    let mut sink = Sink::new();
+    let mut engine = Engine {
+        world: world.track(),
+        introspector: introspector.track(),
+        traced: traced.track(),
+        sink: sink.track_mut(),
+        route: Route::default(),
+    };
+
+    let region = Region::new(
+        Size::new(Abs::pt(CONTENT_WIDTH), Abs::inf()),
+        Axes::splat(false),
+    );
+    let frame = layout_frame(&mut engine, content, Locator::root(), styles, region)
+        .map_err(|diagnostics| diagnostics.iter().map(EvalError::from).collect::<Vec<_>>())?;
+
+    // The region is unlimited in height, so the content may be too large to
+    // be rendered at all.
+    check_render_size(frame.size(), scale).map_err(|e| {
+        vec![EvalError {
+            message: format!("the value {e}"),
+            hints: vec![],
+        }]
+    })?;
+
+    let page = Page {
+        frame,
+        fill: Smart::Auto,
+        numbering: None,
+        number: 1,
+    };
+    let pixmap = typst_render::render(&page, scale);
+    Ok(pixmap.encode_png().ok().map(|png| EvalImage {
+        image: base64::engine::general_purpose::STANDARD.encode(png),
+        width: pixmap.width(),
+        height: pixmap.height(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::test_world;
+    use tempfile::TempDir;
+    use typst::syntax::{FileId, VirtualPath};
+
+    const TEXT: &str = "#let x = 1\n= Title\n#let y = x + 1\nDone\n";
+
+    fn world() -> (TempDir, ProjectWorld) {
+        let (dir, world) = test_world(&[("main.typ", TEXT)]);
+        // Loads the file like a compilation before the evaluation does.
+        world.source(world.main()).unwrap();
+        (dir, world)
+    }
+
+    fn eval(world: &mut ProjectWorld, cursor: usize, expression: &str, scale: f32) -> Evaluation {
+        let source = Source::new(FileId::new(None, VirtualPath::new("main.typ")), TEXT.into());
+        evaluate(
+            world,
+            Path::new("/main.typ"),
+            &source,
+            cursor,
+            expression,
+            None,
+            scale,
+        )
+        .unwrap()
+    }
+
+    fn text(world: &ProjectWorld) -> String {
+        let id = FileId::new(None, VirtualPath::new("main.typ"));
+        world.source(id).unwrap().text().to_string()
+    }
+
+    #[test]
+    fn test_probe() {
+        let source = Source::detached(TEXT);
+        let cursor = TEXT.find("Done").unwrap();
+        let (text, range) = probe(&source, cursor, "y * 2", false);
+        assert_eq!(&text[range], "(y * 2\n)");
+        assert!(text.contains("#let _ = (y * 2\n);Done"));
+
+        let (text, range) = probe(&source, cursor, "y", true);
+        assert_eq!(&text[range], "(context (y\n))");
+    }
+
+    #[test]
+    fn test_evaluate_restores_the_source() {
+        let (_dir, mut world) = world();
+
+        let evaluation = eval(&mut world, TEXT.find("Done").unwrap(), "y * 2", 1.0);
+        assert!(evaluation.errors.is_empty(), "{:?}", evaluation.errors);
+        assert_eq!(evaluation.values.len(), 1);
+        assert_eq!(evaluation.values[0].repr, "4");
+        assert_eq!(text(&world), TEXT);
+
+        let evaluation = eval(&mut world, TEXT.find("Done").unwrap(), "z", 1.0);
+        assert!(!evaluation.errors.is_empty());
+        assert_eq!(text(&world), TEXT);
+    }
+
+    #[test]
+    fn test_evaluate_renders_content() {
+        let (_dir, mut world) = world();
+        let cursor = TEXT.find("Done").unwrap();
+
+        let evaluation = eval(&mut world, cursor, "[Hi]", 2.0);
+        assert!(evaluation.errors.is_empty(), "{:?}", evaluation.errors);
+        let image = evaluation.values[0].image.as_ref().unwrap();
+        assert!(image.width > 0 && image.height > 0);
+
+        // Content too large to be rendered is reported instead.
+        for (expression, scale) in [
+            ("[Hi]", 0.0),
+            ("[Hi]", f32::NAN),
+            ("block(width: 100%, height: 100000pt)", 2.0),
+        ] {
+            let evaluation = eval(&mut world, cursor, expression, scale);
+            assert!(evaluation.values[0].image.is_none());
+            assert!(evaluation.errors[0].message.starts_with("the value "));
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use serde::Serialize;
+use std::ops::Range;
+use typst::syntax::ast::{self, AstNode};
+use typst::syntax::{LinkedNode, Side, Source, SyntaxKind};
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FoldingKind {
+    /// A heading together with the content up to the next heading of the
+    /// same or a higher level.
+    Section,
+    /// A code block, content block, argument list or collection.
+    Block,
+    /// A run of list, enumeration or term items.
+    List,
+    Comment,
+    Raw,
+}
+
+/// A foldable range of zero-based lines. The start line stays visible.
+#[derive(Serialize, Debug)]
+pub struct FoldingRange {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub kind: FoldingKind,
+}
+
+/// Computes the foldable regions of a source file from its syntax tree.
+pub fn folding_ranges(source: &Source) -> Vec<FoldingRange> {
+    let mut ranges = Vec::new();
+    visit(source, &LinkedNode::new(source.root()), &mut ranges);
+    ranges.sort_by_key(|range| (range.start_line, std::cmp::Reverse(range.end_line)));
+    ranges
+}
+
+fn visit(source: &Source, node: &LinkedNode, ranges: &mut Vec<FoldingRange>) {
+    match node.kind() {
+        SyntaxKind::Markup => {
+            sections(source, node, ranges);
+            runs(source, node, ranges, is_list_item, FoldingKind::List);
+            runs(source, node, ranges, is_line_comment, FoldingKind::Comment);
+        }
+        SyntaxKind::Code => {
+            runs(source, node, ranges, is_line_comment, FoldingKind::Comment);
+        }
+        SyntaxKind::CodeBlock
+        | SyntaxKind::ContentBlock
+        | SyntaxKind::Args
+        | SyntaxKind::Params
+        | SyntaxKind::Array
+        | SyntaxKind::Dict => push(source, node.range(), FoldingKind::Block, true, ranges),
+        SyntaxKind::Raw => push(source, node.range(), FoldingKind::Raw, true, ranges),
+        SyntaxKind::BlockComment => push(source, node.range(), FoldingKind::Comment, false, ranges),
+        _ => {}
+    }
+
+    for child in node.children() {
+        visit(source, &child, ranges);
+    }
+}
+
+/// Folds each heading up to the next heading of the same or a higher level
+/// within the same markup.
+fn sections(source: &Source, markup: &LinkedNode, ranges: &mut Vec<FoldingRange>) {
+    // Open headings as pairs of depth and start offset.
+    let mut open: Vec<(usize, usize)> = Vec::new();
+    let mut last_end = markup.offset();
+
+    for child in markup.children() {
+        if let Some(heading) = child.cast::<ast::Heading>() {
+            let depth = heading.depth().get();
+            while matches!(open.last(), Some(&(d, _)) if d >= depth) {
+                let (_, start) = open.pop().unwrap();
+                push(source, start..last_end, FoldingKind::Section, false, ranges);
+            }
+            open.push((depth, child.offset()));
+        }
+        if !child.kind().is_trivia() && child.kind() != SyntaxKind::Parbreak {
+            last_end = child.range().end;
+        }
+    }
+
+    for (_, start) in open {
+        push(source, start..last_end, FoldingKind::Section, false, ranges);
+    }
+}
+
+/// Folds runs of consecutive children of the given kind, which may only be
+/// separated by single line breaks.
+fn runs(
+    source: &Source,
+    parent: &LinkedNode,
+    ranges: &mut Vec<FoldingRange>,
+    matches: impl Fn(SyntaxKind) -> bool,
+    kind: FoldingKind,
+) {
+    let mut run: Option<Range<usize>> = None;
+    for child in parent.children() {
+        if matches(child.kind()) {
+            let range = child.range();
+            run = Some(match run {
+                Some(run) => run.start..range.end,
+                None => range,
+            });
+        } else if child.kind() == SyntaxKind::Space && !child.get().text().contains("\n\n") {
+            continue;
+        } else if let Some(run) = run.take() {
+            push(source, run, kind, false, ranges);
+        }
+    }
+    if let Some(run) = run {
+        push(source, run, kind, false, ranges);
+    }
+}
+
+fn is_line_comment(kind: SyntaxKind) -> bool {
+    kind == SyntaxKind::LineComment
+}
+
+fn is_list_item(kind: SyntaxKind) -> bool {
+    matches!(
+        kind,
+        SyntaxKind::ListItem | SyntaxKind::EnumItem | SyntaxKind::TermItem
+    )
+}
+
+/// Adds a folding range for a byte range spanning at least two lines. With
+/// `keep_closing`, a closing delimiter on its own line stays visible.
+fn push(
+    source: &Source,
+    range: Range<usize>,
+    kind: FoldingKind,
+    keep_closing: bool,
+    ranges: &mut Vec<FoldingRange>,
+) {
+    let end = range.end.saturating_sub(1).max(range.start);
+    let (Some(start_line), Some(mut end_line)) =
+        (source.byte_to_line(range.start), source.byte_to_line(end))
+    else {
+        return;
+    };
+
+    if keep_closing {
+        let line_start = source.line_to_byte(end_line).unwrap_or(end);
+        let before_closing = source.get(line_start..end).unwrap_or_default();
+        if before_closing.trim().is_empty() {
+            end_line = end_line.saturating_sub(1);
+        }
+    }
+
+    if end_line > start_line {
+        ranges.push(FoldingRange {
+            start_line,
+            end_line,
+            kind,
+        });
+    }
+}
+
+/// Returns the ranges to step through when expanding the selection from the
+/// cursor, from the innermost syntax node outwards. Ranges are character
+/// ranges and strictly grow.
+pub fn selection_ranges(source: &Source, cursor: usize) -> Vec<Range<usize>> {
+    let root = LinkedNode::new(source.root());
+    let leaf = root
+        .leaf_at(cursor, Side::After)
+        .filter(|leaf| !leaf.kind().is_trivia())
+        .or_else(|| root.leaf_at(cursor, Side::Before));
+
+    let text = source.text();
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    let mut node = leaf;
+    while let Some(current) = node {
+        let range = current.range();
+        let range = super::byte_to_char(text, range.start)..super::byte_to_char(text, range.end);
+        if ranges.last() != Some(&range) && !range.is_empty() {
+            ranges.push(range);
+        }
+        node = current.parent().cloned();
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "\
+= Intro
+Text.
+== Scope
+- one
+- two
+
+= Body
+#let f(
+  x,
+) = {
+  x
+}
+// A
+// comment
+```
+raw
+```
+";
+
+    #[test]
+    fn test_folding_ranges() {
+        let source = Source::detached(TEXT);
+        let ranges: Vec<_> = folding_ranges(&source)
+            .into_iter()
+            .map(|range| (range.start_line, range.end_line, range.kind))
+            .collect();
+        assert_eq!(
+            ranges,
+            [
+                // The sections end at the last content, not the blank line.
+                (0, 4, FoldingKind::Section),
+                (2, 4, FoldingKind::Section),
+                (3, 4, FoldingKind::List),
+                (6, 16, FoldingKind::Section),
+                // The closing delimiters stay visible.
+                (7, 8, FoldingKind::Block),
+                (9, 10, FoldingKind::Block),
+                (12, 13, FoldingKind::Comment),
+                (14, 16, FoldingKind::Raw),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_selection_ranges() {
+        let text = "#f(über, (1, 2))";
+        let source = Source::detached(text);
+        let ranges = selection_ranges(&source, text.find('2').unwrap());
+        let texts: Vec<String> = ranges
+            .iter()
+            .map(|range| text.chars().skip(range.start).take(range.len()).collect())
+            .collect();
+        assert_eq!(
+            texts,
+            ["2", "(1, 2)", "(über, (1, 2))", "f(über, (1, 2))", text]
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use super::TextEdit;
+use crate::project::FormatConfig;
+use std::ops::Range;
+use thiserror::Error;
+use typst::syntax::Source;
+use typstyle_core::{Config, Typstyle};
+
+/// Above this many compared line pairs, the diff gives up on finding a
+/// minimal set of edits and replaces the changed region as a whole.
+const DIFF_LIMIT: usize = 4_000_000;
+
+#[derive(Error, Debug)]
+pub enum FormatError {
+    #[error("the source contains syntax errors")]
+    SyntaxErrors,
+    #[error("formatting failed: {0}")]
+    Failed(String),
+}
+
+/// Formats a whole source file and returns the edits that turn the current
+/// text into the formatted one.
+pub fn format_document(
+    source: &Source,
+    config: &FormatConfig,
+) -> Result<Vec<TextEdit>, FormatError> {
+    let formatted = format_text(source, config)?;
+    Ok(text_edits(source, &formatted))
+}
+
+/// Formats the smallest markup, expression or pattern covering the given
+/// byte range, so that syntax errors elsewhere in the file don't prevent it.
+pub fn format_range(
+    source: &Source,
+    range: Range<usize>,
+    config: &FormatConfig,
+) -> Result<Vec<TextEdit>, FormatError> {
+    let (node, formatted) = Typstyle::new(typstyle_config(config))
+        .format_source_range(source, range)
+        .map_err(|_| FormatError::SyntaxErrors)?;
+    let text = source.text();
+    let new = format!("{}{}{}", &text[..node.start], formatted, &text[node.end..]);
+    Ok(text_edits(source, &new))
+}
+
+/// Runs typstyle over the source.
+pub fn format_text(source: &Source, config: &FormatConfig) -> Result<String, FormatError> {
+    Typstyle::new(typstyle_config(config))
+        .format_source(source)
+        .map_err(|e| match e {
+            typstyle_core::Error::SyntaxError => FormatError::SyntaxErrors,
+        })
+}
+
+fn typstyle_config(config: &FormatConfig) -> Config {
+    Config {
+        max_width: config.line_width,
+        tab_spaces: config.indent,
+        ..Default::default()
+    }
+}
+
+/// Computes line-based edits from the source's text to `new`. Unchanged lines
+/// at the start and end are skipped; the remaining lines are matched up with a
+/// longest common subsequence, so that each changed run of lines becomes one
+/// edit.
+pub fn text_edits(source: &Source, new: &str) -> Vec<TextEdit> {
+    let old_lines: Vec<&str> = source.text().split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+
+    let prefix = old_lines
+        .iter()
+        .zip(&new_lines)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old_lines[prefix..]
+        .iter()
+        .rev()
+        .zip(new_lines[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old_lines[prefix..old_lines.len() - suffix];
+    let new_mid = &new_lines[prefix..new_lines.len() - suffix];
+
+    // Byte offsets of the old lines, so that hunks can be turned into ranges.
+    let mut offsets = Vec::with_capacity(old_lines.len() + 1);
+    let mut offset = 0;
+    for line in &old_lines {
+        offsets.push(offset);
+        offset += line.len();
+    }
+    offsets.push(offset);
+
+    let hunks = if old_mid.len().saturating_mul(new_mid.len()) > DIFF_LIMIT {
+        vec![(0..old_mid.len(), 0..new_mid.len())]
+    } else {
+        hunks(old_mid, new_mid)
+    };
+
+    hunks
+        .into_iter()
+        .filter(|(old, new)| !old.is_empty() || !new.is_empty())
+        .map(|(old, new)| {
+            let range = offsets[prefix + old.start]..offsets[prefix + old.end];
+            TextEdit::new(source, range, new_mid[new].concat())
+        })
+        .collect()
+}
+
+/// Splits two line sequences into pairs of differing line ranges, using a
+/// longest common subsequence table.
+fn hunks(old: &[&str], new: &[&str]) -> Vec<(Range<usize>, Range<usize>)> {
+    let (n, m) = (old.len(), new.len());
+    let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+    let idx = |i: usize, j: usize| i * (m + 1) + j;
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[idx(i, j)] = if old[i] == new[j] {
+                lcs[idx(i + 1, j + 1)] + 1
+            } else {
+                lcs[idx(i + 1, j)].max(lcs[idx(i, j + 1)])
+            };
+        }
+    }
+
+    let mut hunks = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let (mut old_start, mut new_start) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old[i] == new[j] {
+            if old_start < i || new_start < j {
+                hunks.push((old_start..i, new_start..j));
+            }
+            i += 1;
+            j += 1;
+            old_start = i;
+            new_start = j;
+        } else if j < m && (i == n || lcs[idx(i, j + 1)] >= lcs[idx(i + 1, j)]) {
+            j += 1;
+        } else {
+            i += 1;
+        }
+    }
+    if old_start < n || new_start < m {
+        hunks.push((old_start..n, new_start..m));
+    }
+    hunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(old: &str, new: &str) -> String {
+        let source = Source::detached(old);
+        let mut text: Vec<char> = old.chars().collect();
+        for edit in text_edits(&source, new).into_iter().rev() {
+            text.splice(edit.range, edit.new_text.chars());
+        }
+        text.into_iter().collect()
+    }
+
+    #[test]
+    fn test_hunks() {
+        assert_eq!(hunks(&[], &[]), vec![]);
+        assert_eq!(hunks(&["a\n"], &[]), vec![(0..1, 0..0)]);
+        assert_eq!(hunks(&[], &["a\n"]), vec![(0..0, 0..1)]);
+        assert_eq!(
+            hunks(&["a\n", "b\n", "c\n"], &["a\n", "x\n", "c\n"]),
+            vec![(1..2, 1..2)]
+        );
+        assert_eq!(
+            hunks(&["a\n", "b\n", "c\n"], &["x\n", "b\n", "y\n"]),
+            vec![(0..1, 0..1), (2..3, 2..3)]
+        );
+        assert_eq!(
+            hunks(&["a\n", "c\n"], &["a\n", "b\n", "c\n"]),
+            vec![(1..1, 1..2)]
+        );
+    }
+
+    #[test]
+    fn test_text_edits() {
+        assert!(text_edits(&Source::detached("a\nb\n"), "a\nb\n").is_empty());
+
+        let edits = text_edits(&Source::detached("a\nb\nc\n"), "a\nB\nc\n");
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].range, 2..4);
+        assert_eq!(edits[0].new_text, "B\n");
+
+        for (old, new) in [
+            ("", "a\n"),
+            ("a\n", ""),
+            ("a\nb", "a\nb\n"),
+            ("x\na\nb\ny", "a\nz\nb"),
+            ("ä\nö\nü\n", "ä\nü\nö\n"),
+            ("#let x = 1\n\n\n= Title\n", "#let x = 1\n\n= Title\n"),
+        ] {
+            assert_eq!(apply(old, new), new, "{old:?} -> {new:?}");
+        }
+    }
+
+    #[test]
+    fn test_format_range_ignores_errors_elsewhere() {
+        let source = Source::detached("#let x = (1,2)\n\n#let y = (");
+        let config = FormatConfig::default();
+        assert!(format_text(&source, &config).is_err());
+        let edits = format_range(&source, 10..13, &config).unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].new_text, "#let x = (1, 2)\n");
+    }
+}
@@ -0,0 +1,192 @@
+use serde::Serialize;
+use std::ops::Range;
+use typst::syntax::{highlight, LinkedNode, Source, Tag};
+
+/// Semantic tokens in the delta-encoded layout of the language server
+/// protocol: five integers per token, namely the line delta, the start column
+/// (relative to the previous token if on the same line), the length, the
+/// token type as an index into [`semantic_token_legend`] and the modifiers.
+#[derive(Serialize, Debug)]
+pub struct SemanticTokens {
+    pub data: Vec<u32>,
+}
+
+/// The names of the token types, indexed by the token type of each token.
+pub fn semantic_token_legend() -> Vec<&'static str> {
+    Tag::LIST
+        .iter()
+        .map(|tag| tag.css_class().trim_start_matches("typ-"))
+        .collect()
+}
+
+/// Computes the semantic tokens of a source file. If a byte range is given,
+/// only tokens overlapping it are produced, which keeps requests for the
+/// visible part of large files cheap.
+pub fn semantic_tokens(source: &Source, range: Option<Range<usize>>) -> SemanticTokens {
+    let range = range.unwrap_or(0..source.len_bytes());
+    let mut tokens = Vec::new();
+    collect(&LinkedNode::new(source.root()), None, &range, &mut tokens);
+
+    let mut encoder = Encoder::new(source);
+    for (range, tag) in merge(tokens) {
+        encoder.push(range, tag);
+    }
+    SemanticTokens { data: encoder.data }
+}
+
+/// Collects the highlighted leaves. Tags of inner nodes, e.g. of a heading or
+/// strong emphasis, apply to their leaves unless those have their own tag.
+fn collect(
+    node: &LinkedNode,
+    inherited: Option<Tag>,
+    range: &Range<usize>,
+    tokens: &mut Vec<(Range<usize>, Tag)>,
+) {
+    let node_range = node.range();
+    if node_range.end < range.start || node_range.start > range.end {
+        return;
+    }
+
+    let tag = highlight(node).or(inherited);
+    if node.children().len() == 0 {
+        if let Some(tag) = tag {
+            if !node_range.is_empty() {
+                tokens.push((node_range, tag));
+            }
+        }
+        return;
+    }
+
+    for child in node.children() {
+        collect(&child, tag, range, tokens);
+    }
+}
+
+/// Joins directly adjacent tokens with the same tag.
+fn merge(tokens: Vec<(Range<usize>, Tag)>) -> Vec<(Range<usize>, Tag)> {
+    let mut merged: Vec<(Range<usize>, Tag)> = Vec::with_capacity(tokens.len());
+    for (range, tag) in tokens {
+        match merged.last_mut() {
+            Some((last, last_tag)) if *last_tag == tag && last.end == range.start => {
+                last.end = range.end;
+            }
+            _ => merged.push((range, tag)),
+        }
+    }
+    merged
+}
+
+/// Delta-encodes tokens, splitting those spanning several lines since editors
+/// expect single-line tokens.
+struct Encoder<'a> {
+    source: &'a Source,
+    data: Vec<u32>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Encoder<'a> {
+    fn new(source: &'a Source) -> Self {
+        Self {
+            source,
+            data: Vec::new(),
+            line: 0,
+            column: 0,
+        }
+    }
+
+    fn push(&mut self, range: Range<usize>, tag: Tag) {
+        let token_type = Tag::LIST.iter().position(|t| *t == tag).unwrap_or(0) as u32;
+        let text = &self.source.text()[range.clone()];
+        let mut offset = range.start;
+        for part in text.split_inclusive('\n') {
+            let content = part.trim_end_matches(['\n', '\r']);
+            let length = content.chars().count();
+            if length > 0 {
+                let line = self.source.byte_to_line(offset).unwrap_or(0);
+                let column = self.source.byte_to_column(offset).unwrap_or(0);
+                let delta_line = line - self.line;
+                let delta_start = if delta_line == 0 {
+                    column - self.column
+                } else {
+                    column
+                };
+                self.data.extend([
+                    delta_line as u32,
+                    delta_start as u32,
+                    length as u32,
+                    token_type,
+                    0,
+                ]);
+                self.line = line;
+                self.column = column;
+            }
+            offset += part.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes the tokens into absolute lines and columns with their text and
+    /// type name.
+    fn decode(source: &Source, tokens: &SemanticTokens) -> Vec<(usize, usize, String, String)> {
+        let legend = semantic_token_legend();
+        let lines: Vec<&str> = source.text().lines().collect();
+        let (mut line, mut column) = (0, 0);
+        tokens
+            .data
+            .chunks(5)
+            .map(|token| {
+                if token[0] > 0 {
+                    column = 0;
+                }
+                line += token[0] as usize;
+                column += token[1] as usize;
+                let text: String = lines[line]
+                    .chars()
+                    .skip(column)
+                    .take(token[2] as usize)
+                    .collect();
+                (line, column, text, legend[token[3] as usize].to_string())
+            })
+            .collect()
+    }
+
+    fn token(line: usize, column: usize, text: &str, name: &str) -> (usize, usize, String, String) {
+        (line, column, text.into(), name.into())
+    }
+
+    #[test]
+    fn test_semantic_token_legend() {
+        let legend = semantic_token_legend();
+        assert_eq!(legend.len(), Tag::LIST.len());
+        assert!(legend.contains(&"heading"));
+        assert!(legend.iter().all(|name| !name.starts_with("typ-")));
+    }
+
+    #[test]
+    fn test_semantic_tokens() {
+        let source = Source::detached("= Über *all*\n#let x = \"a\nb\"\n");
+        let tokens = decode(&source, &semantic_tokens(&source, None));
+        // Columns count characters, multi-line strings are split per line.
+        assert_eq!(
+            tokens,
+            [
+                token(0, 0, "= Über ", "heading"),
+                token(0, 7, "*all*", "strong"),
+                token(1, 0, "#let", "key"),
+                token(1, 7, "=", "op"),
+                token(1, 9, "\"a", "str"),
+                token(2, 0, "b\"", "str"),
+            ]
+        );
+
+        let start = source.text().find("#let").unwrap();
+        let tokens = decode(&source, &semantic_tokens(&source, Some(start..start + 4)));
+        assert_eq!(tokens[0], token(1, 0, "#let", "key"));
+        assert!(tokens.iter().all(|(line, ..)| *line == 1));
+    }
+}
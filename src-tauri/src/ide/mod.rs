@@ -0,0 +1,107 @@
+mod actions;
+mod docs;
+mod eval;
+mod folding;
+mod format;
+mod highlight;
+mod outline;
+mod query;
+mod references;
+mod resolve;
+mod signature;
+mod symbols;
+
+pub use actions::*;
+pub use docs::*;
+pub use eval::*;
+pub use folding::*;
+pub use format::*;
+pub use highlight::*;
+pub use outline::*;
+pub use query::*;
+pub use references::*;
+pub use signature::*;
+pub use symbols::*;
+
+use serde::Serialize;
+use std::ops::Range;
+use std::path::PathBuf;
+use typst::syntax::{Source, Span};
+use typst::World;
+
+/// A resolved position in one of the project's source files. Ranges and
+/// columns count characters, like the offsets the editor sends.
+#[derive(Serialize, Clone, Debug)]
+pub struct SourceLocation {
+    /// The file's path relative to the project root, e.g. `/chapters/intro.typ`.
+    pub path: PathBuf,
+    pub range: Range<usize>,
+    /// Zero-based line of the range start.
+    pub line: usize,
+    /// Zero-based column of the range start.
+    pub column: usize,
+}
+
+impl SourceLocation {
+    /// Resolves a span to a location, if it points into a project file.
+    /// Spans in packages or detached spans yield `None`.
+    pub fn from_span(world: &dyn World, span: Span) -> Option<Self> {
+        let id = span.id()?;
+        if id.package().is_some() {
+            return None;
+        }
+        let source = world.source(id).ok()?;
+        let range = source.range(span)?;
+        Some(Self::from_range(&source, range))
+    }
+
+    /// Describes a byte range of the given source.
+    pub fn from_range(source: &Source, range: Range<usize>) -> Self {
+        let text = source.text();
+        Self {
+            path: source.id().vpath().as_rooted_path().to_path_buf(),
+            line: source.byte_to_line(range.start).unwrap_or(0),
+            column: source.byte_to_column(range.start).unwrap_or(0),
+            range: byte_to_char(text, range.start)..byte_to_char(text, range.end),
+        }
+    }
+}
+
+/// A replacement of a range of text, given in characters.
+#[derive(Serialize, Clone, Debug)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub new_text: String,
+}
+
+impl TextEdit {
+    /// Creates an edit that replaces a byte range of the given source.
+    pub fn new(source: &Source, range: Range<usize>, new_text: String) -> Self {
+        let text = source.text();
+        Self {
+            range: byte_to_char(text, range.start)..byte_to_char(text, range.end),
+            new_text,
+        }
+    }
+}
+
+/// The edits to apply to one file, in ascending order.
+#[derive(Serialize, Clone, Debug)]
+pub struct FileEdit {
+    pub path: PathBuf,
+    pub edits: Vec<TextEdit>,
+}
+
+/// Converts a character offset, as sent by the editor, into a byte offset
+/// into `text`. Offsets past the end are clamped to the text length.
+pub fn char_to_byte(text: &str, offset: usize) -> usize {
+    text.char_indices()
+        .nth(offset)
+        .map(|a| a.0)
+        .unwrap_or(text.len())
+}
+
+/// Converts a byte offset into `text` back into a character offset.
+pub fn byte_to_char(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())].chars().count()
+}
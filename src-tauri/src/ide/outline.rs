@@ -0,0 +1,157 @@
+use super::SourceLocation;
+use comemo::Track;
+use serde::Serialize;
+use typst::engine::{Engine, Route, Sink, Traced};
+use typst::foundations::{NativeElement, StyleChain};
+use typst::introspection::Counter;
+use typst::model::{Document, HeadingElem};
+use typst::World;
+
+#[derive(Serialize, Debug)]
+pub struct OutlineItem {
+    pub level: usize,
+    pub body: String,
+    /// The displayed heading number, e.g. `1.2`, if the heading is numbered.
+    pub numbering: Option<String>,
+    pub outlined: bool,
+    /// The page the heading is on, starting at 1.
+    pub page: usize,
+    /// Distance of the heading from the top of its page in points.
+    pub y: f64,
+    /// Where the heading is written. Headings from packages or synthesized
+    /// without a source position have none.
+    pub source: Option<SourceLocation>,
+    pub children: Vec<OutlineItem>,
+}
+
+/// Builds the heading tree of a compiled document. Headings are read from the
+/// introspector, so headings from included files and headings produced by
+/// show rules are covered as well.
+pub fn outline(world: &dyn World, document: &Document) -> Vec<OutlineItem> {
+    let introspector = &document.introspector;
+    let traced = Traced::default();
+    let mut sink = Sink::new();
+    let mut engine = Engine {
+        world: world.track(),
+        introspector: introspector.track(),
+        traced: traced.track(),
+        sink: sink.track_mut(),
+        route: Route::default(),
+    };
+
+    let counter = Counter::of(HeadingElem::elem());
+    let mut items = Vec::new();
+    for elem in introspector.query(&HeadingElem::elem().select()) {
+        let Some(heading) = elem.to_packed::<HeadingElem>() else {
+            continue;
+        };
+        let Some(location) = elem.location() else {
+            continue;
+        };
+
+        // After layout, the heading's fields are materialized, so the default
+        // style chain yields the effective values.
+        let styles = StyleChain::default();
+        let numbering = heading.numbering(styles).as_ref().and_then(|numbering| {
+            counter
+                .display_at_loc(&mut engine, location, styles, numbering)
+                .ok()
+                .map(|content| content.plain_text().trim().to_string())
+        });
+        let position = introspector.position(location);
+
+        items.push(OutlineItem {
+            level: heading.resolve_level(styles).get(),
+            body: heading.body.plain_text().trim().to_string(),
+            numbering,
+            outlined: heading.outlined(styles),
+            page: position.page.get(),
+            y: position.point.y.to_pt(),
+            source: SourceLocation::from_span(world, elem.span()),
+            children: Vec::new(),
+        });
+    }
+
+    nest(items)
+}
+
+/// Turns a flat list of headings into a tree. A heading becomes a child of the
+/// closest preceding heading with a lower level.
+fn nest(items: Vec<OutlineItem>) -> Vec<OutlineItem> {
+    let mut roots: Vec<OutlineItem> = Vec::new();
+    let mut stack: Vec<OutlineItem> = Vec::new();
+
+    for item in items {
+        while matches!(stack.last(), Some(last) if last.level >= item.level) {
+            let done = stack.pop().unwrap();
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(done),
+                None => roots.push(done),
+            }
+        }
+        stack.push(item);
+    }
+
+    while let Some(done) = stack.pop() {
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(done),
+            None => roots.push(done),
+        }
+    }
+
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::test_world;
+    use std::path::Path;
+
+    #[test]
+    fn test_outline() {
+        let text = "#set heading(numbering: \"1.1\")\n= Intro\n== Scope\n\
+                    #pagebreak()\n== Terms\n#heading(outlined: false)[Aside]\n= End\n";
+        let (_dir, world) = test_world(&[("main.typ", text)]);
+        let document = typst::compile(&world).output.unwrap();
+
+        let items = outline(&world, &document);
+        let top: Vec<_> = items.iter().map(|item| item.body.as_str()).collect();
+        assert_eq!(top, ["Intro", "Aside", "End"]);
+
+        let intro = &items[0];
+        assert_eq!(intro.numbering.as_deref(), Some("1"));
+        assert_eq!(intro.page, 1);
+        let source = intro.source.as_ref().unwrap();
+        assert_eq!(source.path, Path::new("/main.typ"));
+        assert_eq!(source.line, 1);
+
+        let terms = &intro.children[1];
+        assert_eq!(terms.body, "Terms");
+        assert_eq!(terms.level, 2);
+        assert_eq!(terms.numbering.as_deref(), Some("1.2"));
+        assert_eq!(terms.page, 2);
+        assert!(!items[1].outlined);
+    }
+
+    #[test]
+    fn test_nest() {
+        let item = |level| OutlineItem {
+            level,
+            body: level.to_string(),
+            numbering: None,
+            outlined: true,
+            page: 1,
+            y: 0.0,
+            source: None,
+            children: vec![],
+        };
+        let tree = nest(vec![item(2), item(1), item(3), item(2), item(1)]);
+        let shape: Vec<_> = tree
+            .iter()
+            .map(|item| (item.level, item.children.len()))
+            .collect();
+        assert_eq!(shape, [(2, 0), (1, 2), (1, 0)]);
+        assert_eq!(tree[1].children[0].level, 3);
+    }
+}
@@ -0,0 +1,113 @@
+use comemo::Track;
+use thiserror::Error;
+use typst::eval::{eval_string, EvalMode};
+use typst::foundations::{IntoValue, LocatableSelector, Scope, Value};
+use typst::model::Document;
+use typst::syntax::Span;
+use typst::World;
+
+#[derive(Error, Debug)]
+pub enum QueryError {
+    #[error("invalid selector: {0}")]
+    Selector(String),
+    #[error("expected exactly one element, found {0}")]
+    NotOne(usize),
+    #[error("unable to serialize the query result: {0}")]
+    Serialize(#[from] serde_json::Error),
+}
+
+/// Queries the document like `typst query` does. The selector is typst code
+/// evaluated to a selector, e.g. `heading`, `<intro>` or
+/// `figure.where(kind: table)`. If a field is given, only that field of each
+/// element is returned and elements without it are skipped. With `one`, exactly
+/// one element must remain after that, which is returned on its own.
+pub fn query(
+    world: &dyn World,
+    document: &Document,
+    selector: &str,
+    field: Option<&str>,
+    one: bool,
+) -> Result<serde_json::Value, QueryError> {
+    let selector = eval_string(
+        world.track(),
+        selector,
+        Span::detached(),
+        EvalMode::Code,
+        Scope::default(),
+    )
+    .map_err(|errors| {
+        let messages: Vec<String> = errors.iter().map(|e| e.message.to_string()).collect();
+        QueryError::Selector(messages.join(", "))
+    })?
+    .cast::<LocatableSelector>()
+    .map_err(|e| QueryError::Selector(e.message().to_string()))?;
+
+    let values: Vec<Value> = document
+        .introspector
+        .query(&selector.0)
+        .into_iter()
+        .filter_map(|element| match field {
+            Some(field) => element.get_by_name(field).ok(),
+            None => Some(element.into_value()),
+        })
+        .collect();
+
+    if one {
+        if values.len() != 1 {
+            return Err(QueryError::NotOne(values.len()));
+        }
+        Ok(serde_json::to_value(&values[0])?)
+    } else {
+        Ok(serde_json::to_value(values)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::{test_world, ProjectWorld};
+    use tempfile::TempDir;
+
+    const TEXT: &str = "= Intro <intro>\n#metadata(\"a\") <data>\n= Body\n#metadata(\"b\")";
+
+    fn document() -> (TempDir, ProjectWorld, Document) {
+        let (dir, world) = test_world(&[("main.typ", TEXT)]);
+        let document = typst::compile(&world).output.unwrap();
+        (dir, world, document)
+    }
+
+    #[test]
+    fn test_query() {
+        let (_dir, world, document) = document();
+
+        let headings = query(&world, &document, "heading", None, false).unwrap();
+        assert_eq!(headings.as_array().unwrap().len(), 2);
+        let values = query(&world, &document, "metadata", Some("value"), false).unwrap();
+        assert_eq!(values, serde_json::json!(["a", "b"]));
+        let intro = query(&world, &document, "<intro>", None, true).unwrap();
+        assert_eq!(intro["func"], "heading");
+
+        assert!(matches!(
+            query(&world, &document, "heading", None, true),
+            Err(QueryError::NotOne(2))
+        ));
+        assert!(matches!(
+            query(&world, &document, "1 +", None, false),
+            Err(QueryError::Selector(_))
+        ));
+    }
+
+    #[test]
+    fn test_query_one_counts_projected_fields() {
+        let (_dir, world, document) = document();
+
+        // Only one of the labelled elements has a value.
+        let selector = "selector(<intro>).or(<data>)";
+        let value = query(&world, &document, selector, Some("value"), true).unwrap();
+        assert_eq!(value, serde_json::json!("a"));
+        assert!(matches!(
+            query(&world, &document, "heading", Some("value"), true),
+            Err(QueryError::NotOne(0))
+        ));
+    }
+}
@@ -0,0 +1,479 @@
+use super::resolve::{resolve, FileBindings, Resolution};
+use super::{project_files, FileEdit, SourceLocation, TextEdit};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+use thiserror::Error;
+use typst::syntax::ast::{self, AstNode};
+use typst::syntax::{
+    is_valid_label_literal_id, FileId, LinkedNode, Side, Source, SyntaxKind, VirtualPath,
+};
+use typst::World;
+
+/// The name a reference search or rename is about.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "kind", content = "name", rename_all = "snake_case")]
+pub enum RefTarget {
+    /// A label, referenced through `<name>`, `@name` or `label("name")`.
+    Label(String),
+    /// An identifier in code or math.
+    Ident(String),
+}
+
+#[derive(Serialize, Debug)]
+pub struct Reference {
+    pub location: SourceLocation,
+    /// Whether this occurrence defines the name, i.e. a label attached to
+    /// content or a `let` binding.
+    pub definition: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct References {
+    pub target: RefTarget,
+    pub references: Vec<Reference>,
+}
+
+#[derive(Error, Debug)]
+pub enum RenameError {
+    #[error("there is nothing to rename at this position")]
+    NoTarget,
+    #[error("`{0}` is not a valid name")]
+    InvalidName(String),
+    #[error("the label `<{0}>` already exists")]
+    LabelExists(String),
+    #[error("the identifier `{0}` is already defined")]
+    IdentExists(String),
+    #[error("`{0}` is not defined in the project")]
+    NotDefined(String),
+}
+
+/// Lists every occurrence of the label or identifier at `cursor` across all
+/// project files.
+///
+/// Identifiers are resolved through their scopes, so only the ones that refer
+/// to the same binding are reported. Names that aren't defined in the project,
+/// like those of the standard library, are matched by name.
+pub fn references(
+    world: &dyn World,
+    root: &Path,
+    source: &Source,
+    cursor: usize,
+) -> Option<References> {
+    let target = target_at(source, cursor)?;
+    let sources = project_sources(world, root, source);
+    let occurrences = match &target {
+        RefTarget::Label(_) => label_occurrences(&sources, &target),
+        RefTarget::Ident(_) => {
+            let files = resolve(&sources);
+            let (_, found) = ident_occurrences(&sources, &files, source.id(), cursor)?;
+            found
+        }
+    };
+    let references = occurrences
+        .into_iter()
+        .map(|(index, range, definition)| Reference {
+            location: SourceLocation::from_range(&sources[index], range),
+            definition,
+        })
+        .collect();
+    Some(References { target, references })
+}
+
+/// Produces the edits that rename the label or identifier at `cursor` in all
+/// project files. Refuses names that are invalid or already taken.
+pub fn rename(
+    world: &dyn World,
+    root: &Path,
+    source: &Source,
+    cursor: usize,
+    new_name: &str,
+) -> Result<Vec<FileEdit>, RenameError> {
+    let target = target_at(source, cursor).ok_or(RenameError::NoTarget)?;
+    let sources = project_sources(world, root, source);
+
+    let renamed = match &target {
+        RefTarget::Label(_) => {
+            let new_name = new_name.trim_start_matches('<').trim_end_matches('>');
+            if !is_valid_label_literal_id(new_name) {
+                return Err(RenameError::InvalidName(new_name.into()));
+            }
+            RefTarget::Label(new_name.into())
+        }
+        RefTarget::Ident(_) => {
+            if !is_plain_ident(new_name) {
+                return Err(RenameError::InvalidName(new_name.into()));
+            }
+            RefTarget::Ident(new_name.into())
+        }
+    };
+    if renamed == target {
+        return Ok(vec![]);
+    }
+
+    let (found, new_text) = match (&target, renamed) {
+        (_, RefTarget::Label(name)) => {
+            let taken = label_occurrences(&sources, &RefTarget::Label(name.clone()))
+                .into_iter()
+                .any(|(_, _, definition)| definition);
+            if taken {
+                return Err(RenameError::LabelExists(name));
+            }
+            (label_occurrences(&sources, &target), name)
+        }
+        (old, RefTarget::Ident(name)) => {
+            let files = resolve(&sources);
+            let (resolution, found) = ident_occurrences(&sources, &files, source.id(), cursor)
+                .ok_or(RenameError::NoTarget)?;
+            if resolution == Resolution::External {
+                return Err(RenameError::NotDefined(target_name(old).into()));
+            }
+            if !keeps_bindings(&sources, &files, &found, &name) {
+                return Err(RenameError::IdentExists(name));
+            }
+            (found, name)
+        }
+    };
+
+    Ok(sources
+        .iter()
+        .enumerate()
+        .filter_map(|(index, source)| {
+            let edits: Vec<TextEdit> = found
+                .iter()
+                .filter(|(i, _, _)| *i == index)
+                .map(|(_, range, _)| TextEdit::new(source, range.clone(), new_text.clone()))
+                .collect();
+            (!edits.is_empty()).then(|| FileEdit {
+                path: source.id().vpath().as_rooted_path().to_path_buf(),
+                edits,
+            })
+        })
+        .collect())
+}
+
+fn target_name(target: &RefTarget) -> &str {
+    match target {
+        RefTarget::Label(name) | RefTarget::Ident(name) => name,
+    }
+}
+
+/// Determines the label or identifier under the cursor.
+pub fn target_at(source: &Source, cursor: usize) -> Option<RefTarget> {
+    let root = LinkedNode::new(source.root());
+    [Side::Before, Side::After]
+        .into_iter()
+        .filter_map(|side| root.leaf_at(cursor, side))
+        .find_map(|leaf| match leaf.kind() {
+            SyntaxKind::Label => leaf
+                .cast::<ast::Label>()
+                .map(|label| RefTarget::Label(label.get().into())),
+            SyntaxKind::RefMarker => leaf
+                .parent()?
+                .cast::<ast::Ref>()
+                .map(|reference| RefTarget::Label(reference.target().into())),
+            SyntaxKind::Str if is_label_call_arg(&leaf) => leaf
+                .cast::<ast::Str>()
+                .map(|s| RefTarget::Label(s.get().into())),
+            SyntaxKind::Ident | SyntaxKind::MathIdent if !is_member_name(&leaf) => {
+                Some(RefTarget::Ident(leaf.get().text().to_string()))
+            }
+            _ => None,
+        })
+}
+
+/// An occurrence of a name, given by the index of its source, the byte range
+/// of just the name, without sigils or quotes, and whether it defines the
+/// name.
+type Occurrence = (usize, Range<usize>, bool);
+
+/// Finds the occurrences of a label in the sources.
+fn label_occurrences(sources: &[Source], target: &RefTarget) -> Vec<Occurrence> {
+    let mut found = Vec::new();
+    for (index, source) in sources.iter().enumerate() {
+        let mut ranges = Vec::new();
+        visit(&LinkedNode::new(source.root()), target, &mut ranges);
+        found.extend(
+            ranges
+                .into_iter()
+                .map(|(range, definition)| (index, range, definition)),
+        );
+    }
+    found
+}
+
+fn visit(node: &LinkedNode, target: &RefTarget, found: &mut Vec<(Range<usize>, bool)>) {
+    let range = node.range();
+    match (node.kind(), target) {
+        // Labels in markup attach to the preceding content, labels in code
+        // (e.g. `ref(<name>)`) refer to it.
+        (SyntaxKind::Label, RefTarget::Label(name))
+            if node.cast::<ast::Label>().is_some_and(|l| l.get() == name) =>
+        {
+            let definition = node.parent_kind() == Some(SyntaxKind::Markup);
+            found.push((range.start + 1..range.end - 1, definition));
+        }
+        (SyntaxKind::Ref, RefTarget::Label(name))
+            if node.cast::<ast::Ref>().is_some_and(|r| r.target() == name) =>
+        {
+            let start = range.start + 1;
+            found.push((start..start + name.len(), false));
+        }
+        // Only plain strings can be renamed in place, escapes would shift the
+        // range.
+        (SyntaxKind::Str, RefTarget::Label(name))
+            if is_label_call_arg(node) && node.get().text().trim_matches('"') == name =>
+        {
+            found.push((range.start + 1..range.end - 1, false));
+        }
+        _ => {}
+    }
+
+    for child in node.children() {
+        visit(&child, target, found);
+    }
+}
+
+/// Finds the identifiers that refer to the same binding as the one at the
+/// cursor. Returns what they refer to and their occurrences, in the order of
+/// the sources.
+fn ident_occurrences(
+    sources: &[Source],
+    files: &HashMap<FileId, FileBindings>,
+    id: FileId,
+    cursor: usize,
+) -> Option<(Resolution, Vec<Occurrence>)> {
+    let ident = files.get(&id)?.ident_at(cursor)?;
+    let resolution = ident.resolution;
+    let found = sources
+        .iter()
+        .enumerate()
+        .filter_map(|(index, source)| Some((index, files.get(&source.id())?)))
+        .flat_map(|(index, file)| file.idents.iter().map(move |other| (index, other)))
+        .filter(|(_, other)| {
+            other.resolution == resolution
+                && (resolution != Resolution::External || other.name == ident.name)
+        })
+        .map(|(index, other)| (index, other.range.clone(), other.definition))
+        .collect();
+    Some((resolution, found))
+}
+
+/// Whether renaming the occurrences leaves every identifier referring to what
+/// it did before. This fails if the new name is captured by another binding
+/// in scope, or if the renamed binding captures uses of the new name.
+fn keeps_bindings(
+    sources: &[Source],
+    files: &HashMap<FileId, FileBindings>,
+    found: &[Occurrence],
+    new_name: &str,
+) -> bool {
+    let renamed: Vec<Source> = sources
+        .iter()
+        .enumerate()
+        .map(|(index, source)| {
+            let mut text = source.text().to_string();
+            for (_, range, _) in found.iter().filter(|(i, _, _)| *i == index).rev() {
+                text.replace_range(range.clone(), new_name);
+            }
+            Source::new(source.id(), text)
+        })
+        .collect();
+
+    let after = resolve(&renamed);
+    files.iter().all(|(id, before)| {
+        let Some(after) = after.get(id) else {
+            return false;
+        };
+        before.idents.len() == after.idents.len()
+            && before.idents.iter().zip(&after.idents).all(|(a, b)| {
+                a.resolution == b.resolution
+                    && (a.resolution != Resolution::External || a.name == b.name)
+            })
+    })
+}
+
+/// Whether a string is the argument of a `label("...")` call.
+fn is_label_call_arg(node: &LinkedNode) -> bool {
+    let Some(args) = node.parent().filter(|p| p.kind() == SyntaxKind::Args) else {
+        return false;
+    };
+    args.parent()
+        .and_then(|call| call.cast::<ast::FuncCall>())
+        .is_some_and(|call| match call.callee() {
+            ast::Expr::Ident(ident) => ident.as_str() == "label",
+            _ => false,
+        })
+}
+
+/// Whether an identifier names a field or an argument rather than a variable,
+/// as `b` in `a.b` or `fill` in `text(fill: red)`.
+fn is_member_name(node: &LinkedNode) -> bool {
+    match node.parent_kind() {
+        Some(SyntaxKind::FieldAccess) => node.index() > 0,
+        Some(SyntaxKind::Named) => node.prev_sibling().is_none(),
+        _ => false,
+    }
+}
+
+/// Whether the name parses as a single identifier, which excludes keywords.
+fn is_plain_ident(name: &str) -> bool {
+    let code = typst::syntax::parse_code(name);
+    let mut children = code.children();
+    matches!(
+        (children.next(), children.next()),
+        (Some(node), None) if node.kind() == SyntaxKind::Ident && node.text() == name
+    )
+}
+
+/// Loads all `.typ` files of the project through the world, so that unsaved
+/// editor content is taken into account. The given source takes the place of
+/// its file.
+fn project_sources(world: &dyn World, root: &Path, current: &Source) -> Vec<Source> {
+    let mut sources: Vec<Source> = project_files(root)
+        .iter()
+        .filter_map(|path| {
+            let vpath = VirtualPath::within_root(path, root)?;
+            let id = FileId::new(None, vpath);
+            if id == current.id() {
+                return None;
+            }
+            world.source(id).ok()
+        })
+        .collect();
+    sources.push(current.clone());
+    sources
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::{test_world, ProjectWorld};
+    use tempfile::TempDir;
+
+    struct Fixture {
+        dir: TempDir,
+        world: ProjectWorld,
+    }
+
+    impl Fixture {
+        /// A project with the given files, the first being the main file.
+        fn new(files: &[(&str, &str)]) -> Self {
+            let (dir, world) = test_world(files);
+            Self { dir, world }
+        }
+
+        fn source(&self, name: &str) -> Source {
+            let id = FileId::new(None, VirtualPath::new(name));
+            self.world.source(id).unwrap()
+        }
+
+        /// Renames the `n`th occurrence of `old` in a file.
+        fn rename(
+            &self,
+            name: &str,
+            old: &str,
+            n: usize,
+            new_name: &str,
+        ) -> Result<Vec<FileEdit>, RenameError> {
+            let source = self.source(name);
+            let cursor = source.text().match_indices(old).nth(n).unwrap().0;
+            rename(&self.world, self.dir.path(), &source, cursor, new_name)
+        }
+    }
+
+    /// The text of a file after applying the edits for it.
+    fn apply(text: &str, edits: &[FileEdit], path: &str) -> String {
+        let mut chars: Vec<char> = text.chars().collect();
+        let Some(file) = edits.iter().find(|edit| edit.path == Path::new(path)) else {
+            return text.into();
+        };
+        for edit in file.edits.iter().rev() {
+            chars.splice(edit.range.clone(), edit.new_text.chars());
+        }
+        chars.into_iter().collect()
+    }
+
+    #[test]
+    fn test_rename_skips_shadowed_bindings() {
+        let text = "#let x = 1\n#{ let x = x + 1; x }\n#x";
+        let fixture = Fixture::new(&[("main.typ", text)]);
+        let edits = fixture.rename("main.typ", "x", 0, "y").unwrap();
+        assert_eq!(
+            apply(text, &edits, "/main.typ"),
+            "#let y = 1\n#{ let x = y + 1; x }\n#y"
+        );
+    }
+
+    #[test]
+    fn test_rename_parameters() {
+        let text = "#let a = 1\n#let f(a) = a + 1\n#f(a)";
+        let fixture = Fixture::new(&[("main.typ", text)]);
+        let edits = fixture.rename("main.typ", "a", 2, "n").unwrap();
+        assert_eq!(
+            apply(text, &edits, "/main.typ"),
+            "#let a = 1\n#let f(n) = n + 1\n#f(a)"
+        );
+
+        let source = fixture.source("main.typ");
+        let cursor = text.rfind('a').unwrap();
+        let found = references(&fixture.world, fixture.dir.path(), &source, cursor).unwrap();
+        let definitions: Vec<_> = found
+            .references
+            .iter()
+            .map(|reference| reference.definition)
+            .collect();
+        assert_eq!(definitions, vec![true, false]);
+    }
+
+    #[test]
+    fn test_rename_across_files() {
+        let main = "#import \"lib.typ\": f\n#import \"lib.typ\" as m\n#f(1) #m.f(2)";
+        let lib = "#let f(x) = x\n#let g = f";
+        let fixture = Fixture::new(&[("main.typ", main), ("lib.typ", lib)]);
+        let edits = fixture.rename("lib.typ", "f", 0, "h").unwrap();
+        assert_eq!(
+            apply(main, &edits, "/main.typ"),
+            "#import \"lib.typ\": h\n#import \"lib.typ\" as m\n#h(1) #m.h(2)"
+        );
+        assert_eq!(apply(lib, &edits, "/lib.typ"), "#let h(x) = x\n#let g = h");
+    }
+
+    #[test]
+    fn test_rename_refuses_captures() {
+        let text = "#let x = 1\n#let f(y) = x + y\n#text[#x]";
+        let fixture = Fixture::new(&[("main.typ", text)]);
+        // The parameter would capture the renamed use.
+        assert!(matches!(
+            fixture.rename("main.typ", "x", 0, "y"),
+            Err(RenameError::IdentExists(_))
+        ));
+        // The renamed binding would capture the standard library's `text`.
+        assert!(matches!(
+            fixture.rename("main.typ", "x", 0, "text"),
+            Err(RenameError::IdentExists(_))
+        ));
+        // The renamed parameter would shadow the use of `x` in the body.
+        assert!(matches!(
+            fixture.rename("main.typ", "y", 0, "x"),
+            Err(RenameError::IdentExists(_))
+        ));
+        assert!(fixture.rename("main.typ", "x", 0, "z").is_ok());
+        assert!(matches!(
+            fixture.rename("main.typ", "text", 0, "txt"),
+            Err(RenameError::NotDefined(_))
+        ));
+    }
+
+    #[test]
+    fn test_rename_labels() {
+        let text = "= Intro <intro>\nSee @intro and #ref(label(\"intro\")).";
+        let fixture = Fixture::new(&[("main.typ", text)]);
+        let edits = fixture.rename("main.typ", "intro", 0, "start").unwrap();
+        assert_eq!(
+            apply(text, &edits, "/main.typ"),
+            "= Intro <start>\nSee @start and #ref(label(\"start\"))."
+        );
+    }
+}
@@ -0,0 +1,584 @@
+use ecow::EcoString;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::Path;
+use typst::syntax::ast::{self, AstNode};
+use typst::syntax::{FileId, LinkedNode, Source, SyntaxKind};
+
+/// The identifier that introduces a binding, given by its file and its index
+/// among the identifiers of the file. Unlike a byte range, the index doesn't
+/// change when identifiers are renamed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Binding {
+    pub file: FileId,
+    pub index: usize,
+}
+
+/// What an identifier refers to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Resolution {
+    /// A binding in one of the given files.
+    Binding(Binding),
+    /// A name of the standard library, a package or a file that wasn't
+    /// given, which isn't followed.
+    External,
+}
+
+/// An identifier that refers to a variable, as opposed to field names and
+/// the names of named arguments.
+#[derive(Clone, Debug)]
+pub struct Ident {
+    pub range: Range<usize>,
+    pub name: EcoString,
+    pub resolution: Resolution,
+    /// Whether the identifier introduces the binding, e.g. in a `let` binding
+    /// or as a parameter.
+    pub definition: bool,
+}
+
+/// The identifiers of a file and the bindings it exports.
+#[derive(Default)]
+pub struct FileBindings {
+    pub idents: Vec<Ident>,
+    exports: HashMap<EcoString, Entry>,
+}
+
+impl FileBindings {
+    /// The identifier at a byte offset.
+    pub fn ident_at(&self, cursor: usize) -> Option<&Ident> {
+        self.idents
+            .iter()
+            .find(|ident| ident.range.start <= cursor && cursor <= ident.range.end)
+    }
+}
+
+/// A name in scope.
+#[derive(Clone, Copy, Debug)]
+struct Entry {
+    resolution: Resolution,
+    /// The file if the name is bound to a module, e.g. by `import "a.typ"`.
+    module: Option<FileId>,
+}
+
+/// Binds the identifiers of the files to their definitions, following the
+/// lexical scopes of `let` bindings, closures, loops and blocks, and imports
+/// between the files.
+///
+/// This is purely syntactic, so it doesn't know the names a module defines
+/// dynamically, e.g. through `eval`.
+pub fn resolve(sources: &[Source]) -> HashMap<FileId, FileBindings> {
+    let mut resolver = Resolver {
+        sources: sources.iter().map(|source| (source.id(), source)).collect(),
+        files: HashMap::new(),
+        active: HashSet::new(),
+    };
+    for source in sources {
+        resolver.file(source.id());
+    }
+    resolver.files
+}
+
+struct Resolver<'a> {
+    sources: HashMap<FileId, &'a Source>,
+    files: HashMap<FileId, FileBindings>,
+    /// The files being resolved, to break import cycles.
+    active: HashSet<FileId>,
+}
+
+impl<'a> Resolver<'a> {
+    /// Resolves a file unless it was already.
+    fn file(&mut self, id: FileId) {
+        if self.files.contains_key(&id) || !self.active.insert(id) {
+            return;
+        }
+        let Some(source) = self.sources.get(&id).copied() else {
+            return;
+        };
+        let mut walker = Walker {
+            resolver: self,
+            source,
+            idents: vec![],
+            scopes: vec![HashMap::new()],
+        };
+        walker.walk(&LinkedNode::new(source.root()));
+        let Walker { idents, scopes, .. } = walker;
+        let exports = scopes.into_iter().next().unwrap_or_default();
+        self.active.remove(&id);
+        self.files.insert(id, FileBindings { idents, exports });
+    }
+
+    /// The names a file binds at its top level.
+    fn exports(&mut self, id: FileId) -> HashMap<EcoString, Entry> {
+        self.file(id);
+        self.files
+            .get(&id)
+            .map(|file| file.exports.clone())
+            .unwrap_or_default()
+    }
+}
+
+struct Walker<'r, 'a> {
+    resolver: &'r mut Resolver<'a>,
+    source: &'a Source,
+    idents: Vec<Ident>,
+    scopes: Vec<HashMap<EcoString, Entry>>,
+}
+
+impl Walker<'_, '_> {
+    fn walk(&mut self, node: &LinkedNode) {
+        match node.kind() {
+            SyntaxKind::Ident | SyntaxKind::MathIdent => {
+                self.reference(node, None);
+            }
+            SyntaxKind::FieldAccess => self.field_access(node),
+            // The name of a named argument or dictionary entry.
+            SyntaxKind::Named => {
+                for child in node.children().skip(1) {
+                    self.walk(&child);
+                }
+            }
+            SyntaxKind::LetBinding => self.let_binding(node),
+            SyntaxKind::Closure => self.closure(node),
+            SyntaxKind::ForLoop => self.for_loop(node),
+            SyntaxKind::CodeBlock | SyntaxKind::ContentBlock => {
+                self.scoped(|walker| walker.walk_children(node));
+            }
+            SyntaxKind::ModuleImport => self.import(node),
+            _ => self.walk_children(node),
+        }
+    }
+
+    fn walk_children(&mut self, node: &LinkedNode) {
+        for child in node.children() {
+            self.walk(&child);
+        }
+    }
+
+    fn scoped(&mut self, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(HashMap::new());
+        f(self);
+        self.scopes.pop();
+    }
+
+    fn lookup(&self, name: &str) -> Option<Entry> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    /// Records a use of a name. Returns what it refers to.
+    fn reference(&mut self, node: &LinkedNode, entry: Option<Entry>) -> Option<Entry> {
+        let name: EcoString = node.text().into();
+        let entry = entry.or_else(|| self.lookup(&name));
+        self.idents.push(Ident {
+            range: node.range(),
+            name,
+            resolution: entry.map_or(Resolution::External, |entry| entry.resolution),
+            definition: false,
+        });
+        entry
+    }
+
+    /// Records an identifier that introduces a binding and brings it into
+    /// scope.
+    fn define(&mut self, node: &LinkedNode, module: Option<FileId>) {
+        let binding = Binding {
+            file: self.source.id(),
+            index: self.idents.len(),
+        };
+        let name: EcoString = node.text().into();
+        self.idents.push(Ident {
+            range: node.range(),
+            name: name.clone(),
+            resolution: Resolution::Binding(binding),
+            definition: true,
+        });
+        self.bind(name, Resolution::Binding(binding), module);
+    }
+
+    fn bind(&mut self, name: EcoString, resolution: Resolution, module: Option<FileId>) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, Entry { resolution, module });
+        }
+    }
+
+    /// A field of a module imported from one of the files refers to the
+    /// module's binding, other fields aren't variables.
+    fn field_access(&mut self, node: &LinkedNode) {
+        let mut children = node.children().filter(|child| !child.kind().is_trivia());
+        let Some(target) = children.next() else {
+            return;
+        };
+        let module = match target.kind() {
+            SyntaxKind::Ident | SyntaxKind::MathIdent => {
+                self.reference(&target, None).and_then(|entry| entry.module)
+            }
+            _ => {
+                self.walk(&target);
+                None
+            }
+        };
+        let Some(field) = children
+            .last()
+            .filter(|field| field.kind() == SyntaxKind::Ident)
+        else {
+            return;
+        };
+        if let Some(module) = module {
+            let entry = self.resolver.exports(module).get(field.text()).copied();
+            self.reference(
+                &field,
+                Some(entry.unwrap_or(Entry {
+                    resolution: Resolution::External,
+                    module: None,
+                })),
+            );
+        }
+    }
+
+    /// The value of a `let` binding can't see the names it binds, except for
+    /// the name of a function, which may call itself.
+    fn let_binding(&mut self, node: &LinkedNode) {
+        let Some(binding) = node.cast::<ast::LetBinding>() else {
+            return;
+        };
+        let mut children = node
+            .children()
+            .filter(|child| !child.kind().is_trivia() && child.kind() != SyntaxKind::Let);
+        let Some(pattern) = children.next() else {
+            return;
+        };
+        match binding.kind() {
+            ast::LetBindingKind::Closure(_) => {
+                if let Some(name) = pattern.children().next() {
+                    self.define(&name, None);
+                }
+                self.closure(&pattern);
+            }
+            ast::LetBindingKind::Normal(_) => {
+                for child in children {
+                    self.walk(&child);
+                }
+                self.pattern(&pattern);
+            }
+        }
+    }
+
+    /// Binds the identifiers of a pattern, e.g. `x` or `(a, b: c, ..rest)`.
+    fn pattern(&mut self, node: &LinkedNode) {
+        match node.kind() {
+            SyntaxKind::Ident => self.define(node, None),
+            SyntaxKind::Underscore => {}
+            SyntaxKind::Parenthesized | SyntaxKind::Destructuring => {
+                for child in node.children() {
+                    match child.kind() {
+                        // In `(a: b)`, `a` is the key and `b` the binding.
+                        SyntaxKind::Named => {
+                            if let Some(pattern) = child
+                                .children()
+                                .skip_while(|c| c.kind() != SyntaxKind::Colon)
+                                .find(|c| !c.kind().is_trivia() && c.kind() != SyntaxKind::Colon)
+                            {
+                                self.pattern(&pattern);
+                            }
+                        }
+                        SyntaxKind::Spread => self.sink(&child),
+                        SyntaxKind::Ident
+                        | SyntaxKind::Underscore
+                        | SyntaxKind::Parenthesized
+                        | SyntaxKind::Destructuring => self.pattern(&child),
+                        _ => {}
+                    }
+                }
+            }
+            _ => self.walk(node),
+        }
+    }
+
+    /// Binds the name of an argument or destructuring sink, `rest` in
+    /// `..rest`.
+    fn sink(&mut self, node: &LinkedNode) {
+        if let Some(ident) = node.children().find(|c| c.kind() == SyntaxKind::Ident) {
+            self.define(&ident, None);
+        }
+    }
+
+    /// Binds the parameters in the closure's body. Default values are
+    /// evaluated where the closure is defined.
+    fn closure(&mut self, node: &LinkedNode) {
+        let params = node.children().find(|c| c.kind() == SyntaxKind::Params);
+        let params: Vec<_> = params.iter().flat_map(|params| params.children()).collect();
+        for param in &params {
+            if param.kind() == SyntaxKind::Named {
+                for child in param.children().skip(1) {
+                    self.walk(&child);
+                }
+            }
+        }
+
+        self.scoped(|walker| {
+            for param in &params {
+                match param.kind() {
+                    SyntaxKind::Named => {
+                        if let Some(name) = param.children().next() {
+                            walker.define(&name, None);
+                        }
+                    }
+                    SyntaxKind::Spread => walker.sink(param),
+                    SyntaxKind::Ident
+                    | SyntaxKind::Underscore
+                    | SyntaxKind::Parenthesized
+                    | SyntaxKind::Destructuring => walker.pattern(param),
+                    _ => {}
+                }
+            }
+            if let Some(body) = node
+                .children()
+                .rev()
+                .find(|c| !c.kind().is_trivia() && c.kind() != SyntaxKind::Arrow)
+                .filter(|body| body.kind() != SyntaxKind::Params)
+            {
+                walker.walk(&body);
+            }
+        });
+    }
+
+    fn for_loop(&mut self, node: &LinkedNode) {
+        let children: Vec<_> = node
+            .children()
+            .filter(|c| !c.kind().is_trivia() && c.kind() != SyntaxKind::For)
+            .collect();
+        let [pattern, _, iterable, body] = children.as_slice() else {
+            return self.walk_children(node);
+        };
+        self.walk(iterable);
+        self.scoped(|walker| {
+            walker.pattern(pattern);
+            walker.walk(body);
+        });
+    }
+
+    /// Brings the imported names into scope. Names imported from one of the
+    /// files refer to the bindings of that file.
+    fn import(&mut self, node: &LinkedNode) {
+        let Some(import) = node.cast::<ast::ModuleImport>() else {
+            return;
+        };
+        let mut module = None;
+        let mut default_name = None;
+        if let Some(source) = node
+            .children()
+            .find(|c| !c.kind().is_trivia() && c.kind() != SyntaxKind::Import)
+        {
+            match import.source() {
+                ast::Expr::Str(path) => {
+                    let path = path.get();
+                    default_name = module_name(&path);
+                    if !path.starts_with('@') {
+                        let id = FileId::new(None, self.source.id().vpath().join(path.as_str()));
+                        module = self.resolver.sources.contains_key(&id).then_some(id);
+                    }
+                }
+                _ => {
+                    if source.kind() == SyntaxKind::Ident {
+                        module = self.reference(&source, None).and_then(|entry| entry.module);
+                    } else {
+                        self.walk(&source);
+                    }
+                }
+            }
+        }
+
+        let new_name = node
+            .children()
+            .skip_while(|c| c.kind() != SyntaxKind::As)
+            .find(|c| c.kind() == SyntaxKind::Ident);
+        match (&new_name, import.imports()) {
+            (Some(name), _) => self.define(name, module),
+            (None, None) => {
+                if let Some(name) = default_name {
+                    self.bind(name, Resolution::External, module);
+                }
+            }
+            _ => {}
+        }
+
+        match import.imports() {
+            Some(ast::Imports::Wildcard) => {
+                if let Some(module) = module {
+                    for (name, entry) in self.resolver.exports(module) {
+                        self.bind(name, entry.resolution, entry.module);
+                    }
+                }
+            }
+            Some(ast::Imports::Items(_)) => {
+                let items = node
+                    .children()
+                    .find(|c| c.kind() == SyntaxKind::ImportItems);
+                for item in items.iter().flat_map(|items| items.children()) {
+                    self.import_item(&item, module);
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// An item like `a`, `a.b` or `a as b`. The path refers to the module's
+    /// bindings and a plain item binds the same name in this file.
+    fn import_item(&mut self, node: &LinkedNode, module: Option<FileId>) {
+        let path = match node.kind() {
+            SyntaxKind::ImportItemPath => node.clone(),
+            SyntaxKind::RenamedImportItem => match node.children().next() {
+                Some(path) => path,
+                None => return,
+            },
+            _ => return,
+        };
+
+        let mut module = module;
+        let mut entry = None;
+        for ident in path.children().filter(|c| c.kind() == SyntaxKind::Ident) {
+            let found = module.map(|module| {
+                self.resolver
+                    .exports(module)
+                    .get(ident.text())
+                    .copied()
+                    .unwrap_or(Entry {
+                        resolution: Resolution::External,
+                        module: None,
+                    })
+            });
+            let found = found.unwrap_or(Entry {
+                resolution: Resolution::External,
+                module: None,
+            });
+            self.reference(&ident, Some(found));
+            module = found.module;
+            entry = Some((ident.text().clone(), found));
+        }
+
+        if node.kind() == SyntaxKind::RenamedImportItem {
+            if let Some(name) = node
+                .children()
+                .filter(|c| c.kind() == SyntaxKind::Ident)
+                .last()
+            {
+                self.define(&name, entry.and_then(|(_, entry)| entry.module));
+            }
+        } else if let Some((name, entry)) = entry {
+            self.bind(name, entry.resolution, entry.module);
+        }
+    }
+}
+
+/// The name `import` binds a module to, `b` for `a/b.typ` and `cetz` for
+/// `@preview/cetz:0.3.1`.
+fn module_name(path: &str) -> Option<EcoString> {
+    if let Some(spec) = path.strip_prefix('@') {
+        let name = spec.split_once('/')?.1;
+        return Some(name.split(':').next()?.into());
+    }
+    Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().as_ref().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use typst::syntax::VirtualPath;
+
+    fn source(path: &str, text: &str) -> Source {
+        Source::new(FileId::new(None, VirtualPath::new(path)), text.into())
+    }
+
+    /// The identifiers of the first file that refer to the same thing as the
+    /// one at `cursor`, as their offsets and whether they define it.
+    fn uses(sources: &[Source], cursor: usize) -> Vec<(usize, bool)> {
+        let files = resolve(sources);
+        let file = &files[&sources[0].id()];
+        let target = file.ident_at(cursor).unwrap();
+        file.idents
+            .iter()
+            .filter(|i| {
+                i.resolution == target.resolution
+                    && (i.resolution != Resolution::External || i.name == target.name)
+            })
+            .map(|i| (i.range.start, i.definition))
+            .collect()
+    }
+
+    /// The offsets of the identifier `name` in the text.
+    fn offsets(text: &str, name: &str) -> Vec<usize> {
+        text.match_indices(name).map(|(i, _)| i).collect()
+    }
+
+    #[test]
+    fn test_resolve_shadowing() {
+        let text = "#let x = 1\n#{ let x = x + 1; x }\n#x";
+        let sources = [source("main.typ", text)];
+        let x = offsets(text, "x");
+        let outer = vec![(x[0], true), (x[2], false), (x[4], false)];
+        assert_eq!(uses(&sources, x[0]), outer);
+        assert_eq!(uses(&sources, x[4]), outer);
+        assert_eq!(uses(&sources, x[3]), vec![(x[1], true), (x[3], false)]);
+    }
+
+    #[test]
+    fn test_resolve_closures() {
+        let text = "#let a = 0\n#let f(a, b: a, ..c) = a + b + c + d\n#let g = a => a\n#f(b: 1)";
+        let sources = [source("main.typ", text)];
+        let a = offsets(text, "a");
+        // Default values see the outer scope, the body sees the parameters.
+        assert_eq!(uses(&sources, a[0]), vec![(a[0], true), (a[2], false)]);
+        assert_eq!(uses(&sources, a[1]), vec![(a[1], true), (a[3], false)]);
+        assert_eq!(uses(&sources, a[5]), vec![(a[4], true), (a[5], false)]);
+
+        let b = offsets(text, "b");
+        assert_eq!(uses(&sources, b[0]), vec![(b[0], true), (b[1], false)]);
+        let c = offsets(text, "c");
+        assert_eq!(uses(&sources, c[1]), vec![(c[0], true), (c[1], false)]);
+        let d = text.find('d').unwrap();
+        assert_eq!(uses(&sources, d), vec![(d, false)]);
+        let f = offsets(text, "f");
+        assert_eq!(uses(&sources, f[1]), vec![(f[0], true), (f[1], false)]);
+    }
+
+    #[test]
+    fn test_resolve_patterns() {
+        let text = "#let (k, v: w) = d\n#for (k, x) in d { k + w + x }\n#k";
+        let sources = [source("main.typ", text)];
+        let k = offsets(text, "k");
+        assert_eq!(uses(&sources, k[3]), vec![(k[0], true), (k[3], false)]);
+        assert_eq!(uses(&sources, k[2]), vec![(k[1], true), (k[2], false)]);
+        let w = offsets(text, "w");
+        assert_eq!(uses(&sources, w[1]), vec![(w[0], true), (w[1], false)]);
+
+        // The key of a destructured entry isn't a variable.
+        let files = resolve(&sources);
+        let v = text.find('v').unwrap();
+        assert!(files[&sources[0].id()].ident_at(v).is_none());
+    }
+
+    #[test]
+    fn test_resolve_imports() {
+        let main = "#import \"lib.typ\": f\n#import \"lib.typ\" as m\n#f(1) #m.f(2)";
+        let lib = "#let f(x) = x";
+        let sources = [source("main.typ", main), source("lib.typ", lib)];
+        let files = resolve(&sources);
+        let definition = Resolution::Binding(Binding {
+            file: sources[1].id(),
+            index: 0,
+        });
+        let fs: Vec<_> = files[&sources[0].id()]
+            .idents
+            .iter()
+            .filter(|i| i.name == "f")
+            .collect();
+        assert_eq!(fs.len(), 3);
+        assert!(fs
+            .iter()
+            .all(|i| i.resolution == definition && !i.definition));
+    }
+}
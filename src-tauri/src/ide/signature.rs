@@ -0,0 +1,432 @@
+use serde::Serialize;
+use typst::foundations::{CastInfo, Func, ParamInfo, Repr, Value};
+use typst::syntax::ast::AstNode;
+use typst::syntax::{ast, LinkedNode, Side, Source, SyntaxKind};
+use typst::World;
+
+#[derive(Serialize, Debug)]
+pub struct SignatureHelp {
+    /// The full signature, e.g. `text(fill: color, ..body) -> content`.
+    pub label: String,
+    pub name: String,
+    pub docs: Option<String>,
+    pub params: Vec<SignatureParam>,
+    pub returns: Option<String>,
+    /// Index into `params` of the parameter the cursor is currently on.
+    pub active_parameter: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SignatureParam {
+    /// The parameter as it appears in the signature label.
+    pub label: String,
+    pub name: String,
+    pub types: Option<String>,
+    pub default: Option<String>,
+    pub docs: Option<String>,
+    pub positional: bool,
+    pub named: bool,
+    pub variadic: bool,
+    pub required: bool,
+}
+
+/// Finds the function call surrounding `cursor` (a byte offset) and describes
+/// the signature of its callee.
+pub fn signature_help(world: &dyn World, source: &Source, cursor: usize) -> Option<SignatureHelp> {
+    let root = LinkedNode::new(source.root());
+    let leaf = root.leaf_at(cursor, Side::Before)?;
+    let (callee, args) = surrounding_call(leaf, cursor)?;
+
+    let func = resolve_callee(world, &callee)?;
+    let params = match func.params() {
+        Some(params) => params.iter().map(native_param).collect(),
+        None => closure_params(world, &func)?,
+    };
+    let returns = func.returns().map(describe_cast);
+    let active_parameter = active_parameter(&args, &params, cursor);
+
+    let name = func
+        .name()
+        .map(Into::into)
+        .unwrap_or_else(|| callee.get().clone().into_text().to_string());
+    let mut label = format!(
+        "{}({})",
+        name,
+        params
+            .iter()
+            .map(|p| p.label.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    if let Some(returns) = &returns {
+        label.push_str(" -> ");
+        label.push_str(returns);
+    }
+
+    Some(SignatureHelp {
+        label,
+        name,
+        docs: func
+            .docs()
+            .map(Into::into)
+            .or_else(|| closure_docs(world, &func)),
+        params,
+        returns,
+        active_parameter,
+    })
+}
+
+/// Walks up from the leaf at the cursor to the innermost argument list whose
+/// parentheses contain the cursor. Returns the callee and the argument list.
+fn surrounding_call<'a>(
+    leaf: LinkedNode<'a>,
+    cursor: usize,
+) -> Option<(LinkedNode<'a>, LinkedNode<'a>)> {
+    let mut node = leaf;
+    loop {
+        if node.kind() == SyntaxKind::Args {
+            if let Some(parent) = node.parent() {
+                let callee = match parent.kind() {
+                    SyntaxKind::FuncCall => parent
+                        .cast::<ast::FuncCall>()
+                        .and_then(|call| parent.find(call.callee().span())),
+                    SyntaxKind::SetRule => parent
+                        .cast::<ast::SetRule>()
+                        .and_then(|rule| parent.find(rule.target().span())),
+                    _ => None,
+                };
+                if let Some(callee) = callee {
+                    if in_parens(&node, cursor) {
+                        return Some((callee, node));
+                    }
+                }
+            }
+        }
+        node = node.parent()?.clone();
+    }
+}
+
+/// Whether the cursor lies between the parentheses of an argument list, as
+/// opposed to inside a trailing content block. While typing, the opening
+/// parenthesis is often still unclosed and parsed as an error.
+fn in_parens(args: &LinkedNode, cursor: usize) -> bool {
+    let mut children = args.children();
+    let Some(open) = children.find(|c| {
+        c.kind() == SyntaxKind::LeftParen
+            || (c.kind() == SyntaxKind::Error && c.get().text() == "(")
+    }) else {
+        return false;
+    };
+    if cursor < open.range().end {
+        return false;
+    }
+    match children.find(|c| c.kind() == SyntaxKind::RightParen) {
+        Some(close) => cursor <= close.offset(),
+        None => true,
+    }
+}
+
+/// Evaluates the callee to a function, first through the traced compilation
+/// and then, for callees outside the compiled document, through the global
+/// scope of the standard library.
+fn resolve_callee(world: &dyn World, callee: &LinkedNode) -> Option<Func> {
+    let traced = typst_ide::analyze_expr(world, callee)
+        .into_iter()
+        .find_map(|(value, _)| as_func(value));
+    traced.or_else(|| {
+        let value = resolve_global(world, callee.cast::<ast::Expr>()?)?;
+        as_func(value)
+    })
+}
+
+fn resolve_global(world: &dyn World, expr: ast::Expr) -> Option<Value> {
+    match expr {
+        ast::Expr::Ident(ident) => world.library().global.scope().get(&ident).cloned(),
+        ast::Expr::FieldAccess(access) => resolve_global(world, access.target())?
+            .scope()?
+            .get(&access.field())
+            .cloned(),
+        _ => None,
+    }
+}
+
+fn as_func(value: Value) -> Option<Func> {
+    match value {
+        Value::Func(func) => Some(func),
+        Value::Type(ty) => ty.constructor().ok(),
+        _ => None,
+    }
+}
+
+pub(super) fn native_param(info: &ParamInfo) -> SignatureParam {
+    let types = describe_cast(&info.input);
+    SignatureParam {
+        label: param_label(info.name, info.variadic, Some(&types)),
+        name: info.name.into(),
+        types: Some(types),
+        default: info.default.map(|default| default().repr().to_string()),
+        docs: Some(info.docs.into()).filter(|docs: &String| !docs.is_empty()),
+        positional: info.positional,
+        named: info.named,
+        variadic: info.variadic,
+        required: info.required,
+    }
+}
+
+/// Reads the parameters of a user-defined closure from its definition. The
+/// span of a closure value points at its parameter list.
+fn closure_params(world: &dyn World, func: &Func) -> Option<Vec<SignatureParam>> {
+    let span = func.span();
+    let source = world.source(span.id()?).ok()?;
+    let node = source.find(span)?;
+    let params = node.cast::<ast::Params>()?;
+
+    Some(
+        params
+            .children()
+            .map(|param| match param {
+                ast::Param::Pos(pattern) => {
+                    let name = pattern_name(&pattern);
+                    SignatureParam {
+                        label: param_label(&name, false, None),
+                        name,
+                        types: None,
+                        default: None,
+                        docs: None,
+                        positional: true,
+                        named: false,
+                        variadic: false,
+                        required: true,
+                    }
+                }
+                ast::Param::Named(named) => {
+                    let name = named.name().as_str().to_string();
+                    SignatureParam {
+                        label: param_label(&name, false, None),
+                        name,
+                        types: None,
+                        default: Some(named.expr().to_untyped().clone().into_text().to_string()),
+                        docs: None,
+                        positional: false,
+                        named: true,
+                        variadic: false,
+                        required: false,
+                    }
+                }
+                ast::Param::Spread(spread) => {
+                    let name = spread
+                        .sink_ident()
+                        .map(|ident| ident.as_str().to_string())
+                        .unwrap_or_default();
+                    SignatureParam {
+                        label: param_label(&name, true, None),
+                        name,
+                        types: None,
+                        default: None,
+                        docs: None,
+                        positional: true,
+                        named: false,
+                        variadic: true,
+                        required: false,
+                    }
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Collects the line comments directly above the `let` binding that defines a
+/// closure, which is where templates conventionally document their functions.
+fn closure_docs(world: &dyn World, func: &Func) -> Option<String> {
+    let span = func.span();
+    let source = world.source(span.id()?).ok()?;
+    let node = source.find(span)?;
+    let binding = node.parent()?.parent()?.clone();
+    if binding.kind() != SyntaxKind::LetBinding {
+        return None;
+    }
+
+    let mut lines = Vec::new();
+    let mut prev = binding.prev_sibling_or_hash();
+    while let Some(node) = prev {
+        match node.kind() {
+            SyntaxKind::LineComment => {
+                let text = node.get().text();
+                lines.push(text.trim_start_matches('/').trim().to_string());
+            }
+            SyntaxKind::Space if node.get().text().matches('\n').count() <= 1 => {}
+            _ => break,
+        }
+        prev = node.prev_sibling_or_hash();
+    }
+
+    lines.reverse();
+    Some(lines.join("\n")).filter(|docs| !docs.is_empty())
+}
+
+trait PrevSibling<'a> {
+    fn prev_sibling_or_hash(&self) -> Option<LinkedNode<'a>>;
+}
+
+impl<'a> PrevSibling<'a> for LinkedNode<'a> {
+    /// Like [`LinkedNode::prev_sibling`], but does not skip trivia, since the
+    /// comments are exactly what we are looking for. A leading `#` in markup
+    /// is stepped over.
+    fn prev_sibling_or_hash(&self) -> Option<LinkedNode<'a>> {
+        let parent = self.parent()?;
+        let index = self.index().checked_sub(1)?;
+        let node = parent.children().nth(index)?;
+        if node.kind() == SyntaxKind::Hash {
+            return node.prev_sibling_or_hash();
+        }
+        Some(node)
+    }
+}
+
+/// Determines which parameter the argument under the cursor is bound to.
+fn active_parameter(args: &LinkedNode, params: &[SignatureParam], cursor: usize) -> Option<usize> {
+    // Split the arguments at the commas before the cursor. The last argument
+    // before the cursor is the one being edited.
+    let mut positional = 0;
+    let mut current: Option<LinkedNode> = None;
+    for child in args.children() {
+        if child.offset() >= cursor {
+            break;
+        }
+        match child.kind() {
+            SyntaxKind::Comma => {
+                if let Some(arg) = current.take() {
+                    if arg.kind() != SyntaxKind::Named && arg.kind() != SyntaxKind::Spread {
+                        positional += 1;
+                    }
+                }
+            }
+            SyntaxKind::LeftParen | SyntaxKind::RightParen => {}
+            kind if kind.is_trivia() => {}
+            _ => current = Some(child),
+        }
+    }
+
+    if let Some(named) = current.as_ref().and_then(|arg| arg.cast::<ast::Named>()) {
+        let name = named.name();
+        return params
+            .iter()
+            .position(|p| p.named && p.name == name.as_str());
+    }
+
+    let mut remaining = positional;
+    for (i, param) in params.iter().enumerate() {
+        if !param.positional {
+            continue;
+        }
+        if param.variadic || remaining == 0 {
+            return Some(i);
+        }
+        remaining -= 1;
+    }
+    None
+}
+
+fn param_label(name: &str, variadic: bool, types: Option<&str>) -> String {
+    let mut label = String::new();
+    if variadic {
+        label.push_str("..");
+    }
+    label.push_str(name);
+    if let Some(types) = types {
+        label.push_str(": ");
+        label.push_str(types);
+    }
+    label
+}
+
+fn pattern_name(pattern: &ast::Pattern) -> String {
+    match pattern {
+        ast::Pattern::Normal(ast::Expr::Ident(ident)) => ident.as_str().to_string(),
+        ast::Pattern::Placeholder(_) => "_".into(),
+        other => other.to_untyped().clone().into_text().to_string(),
+    }
+}
+
+/// Describes the values a parameter accepts, e.g. `auto | length`.
+pub fn describe_cast(info: &CastInfo) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    info.walk(|info| {
+        let part = match info {
+            CastInfo::Any => "any".to_string(),
+            CastInfo::Value(value, _) => value.repr().to_string(),
+            CastInfo::Type(ty) => ty.short_name().to_string(),
+            CastInfo::Union(_) => return,
+        };
+        if !parts.contains(&part) {
+            parts.push(part);
+        }
+    });
+    parts.join(" | ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::{test_world, ProjectWorld};
+
+    const TEXT: &str = "// Greets someone.\n\
+                        #let greet(name, greeting: \"Hi\", ..rest) = [#greeting #name]\n\
+                        #greet(\"Ada\", greeting: \"Hello\", [!])\n\
+                        #text(fill: red, size: 12pt)[Done]\n";
+
+    fn help(world: &ProjectWorld, after: &str) -> Option<SignatureHelp> {
+        let source = world.source(world.main()).unwrap();
+        let cursor = TEXT.find(after).unwrap() + after.len();
+        signature_help(world, &source, cursor)
+    }
+
+    fn active(help: &SignatureHelp) -> Option<&str> {
+        help.active_parameter.map(|i| help.params[i].name.as_str())
+    }
+
+    #[test]
+    fn test_signature_help_for_closures() {
+        let (_dir, world) = test_world(&[("main.typ", TEXT)]);
+        let signature = help(&world, "#greet(").unwrap();
+        assert_eq!(signature.label, "greet(name, greeting, ..rest)");
+        assert_eq!(signature.docs.as_deref(), Some("Greets someone."));
+        assert_eq!(signature.params[1].default.as_deref(), Some("\"Hi\""));
+        assert!(signature.params[2].variadic);
+        assert_eq!(active(&signature), Some("name"));
+
+        let signature = help(&world, "greeting: \"Hel").unwrap();
+        assert_eq!(active(&signature), Some("greeting"));
+        // Named arguments don't count as positional ones.
+        let signature = help(&world, "\"Hello\", ").unwrap();
+        assert_eq!(active(&signature), Some("rest"));
+    }
+
+    #[test]
+    fn test_signature_help_for_native_functions() {
+        let (_dir, world) = test_world(&[("main.typ", TEXT)]);
+        let signature = help(&world, "fill: r").unwrap();
+        assert_eq!(signature.name, "text");
+        assert_eq!(signature.returns.as_deref(), Some("content"));
+        assert_eq!(active(&signature), Some("fill"));
+        let signature = help(&world, "size: 12pt").unwrap();
+        assert_eq!(active(&signature), Some("size"));
+
+        // Inside the trailing content block, the call isn't being edited.
+        assert!(help(&world, "[Do").is_none());
+        assert!(help(&world, "// Gree").is_none());
+    }
+
+    #[test]
+    fn test_describe_cast() {
+        let library = typst::Library::default();
+        let Some(Value::Func(text)) = library.global.scope().get("text").cloned() else {
+            panic!("text is a function");
+        };
+        let size = text.param("size").unwrap();
+        assert_eq!(describe_cast(&size.input), "length");
+        let param = native_param(size);
+        assert_eq!(param.label, "size: length");
+        assert!(param.named && !param.positional);
+    }
+}
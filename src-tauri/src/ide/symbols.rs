@@ -0,0 +1,407 @@
+use super::SourceLocation;
+use log::{debug, warn};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use typst::syntax::ast::{self, AstNode};
+use typst::syntax::{FileId, LinkedNode, Source, SyntaxKind, VirtualPath};
+use walkdir::WalkDir;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolKind {
+    Heading,
+    Label,
+    Function,
+    Variable,
+    Import,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct WorkspaceSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Extra information for display, e.g. the heading level or the
+    /// parameter list of a function.
+    pub detail: Option<String>,
+    pub location: SourceLocation,
+}
+
+/// An index of the symbols declared in every `.typ` file of a project.
+///
+/// Unlike the outline of the compiled document, this also covers files that
+/// are not (yet) reachable from the main file.
+#[derive(Default)]
+pub struct SymbolIndex {
+    /// Symbols per file, keyed by the file's rooted project path.
+    files: HashMap<PathBuf, Vec<WorkspaceSymbol>>,
+    /// The files and directories updated since the last merge, whose entries
+    /// are newer than those of an index that is still being built.
+    updated: HashSet<PathBuf>,
+}
+
+impl SymbolIndex {
+    /// Indexes all `.typ` files below the project root.
+    pub fn build(root: &Path) -> Self {
+        let mut index = Self::default();
+        for path in project_files(root) {
+            index.update_file(root, &path);
+        }
+        index.updated.clear();
+        debug!(
+            "indexed {} files with {} symbols in {:?}",
+            index.files.len(),
+            index.files.values().map(Vec::len).sum::<usize>(),
+            root
+        );
+        index
+    }
+
+    /// Adds the files of an index that was built in the background. Files
+    /// updated in the meantime keep their newer entries.
+    pub fn merge(&mut self, built: Self) {
+        for (key, symbols) in built.files {
+            if !self.updated.iter().any(|path| key.starts_with(path)) {
+                self.files.insert(key, symbols);
+            }
+        }
+        self.updated.clear();
+    }
+
+    /// Re-indexes a file or directory given by its absolute path. Files that
+    /// no longer exist are dropped from the index, as are all files below a
+    /// directory that no longer exists.
+    pub fn update(&mut self, root: &Path, path: &Path) {
+        let Ok(relative) = path.strip_prefix(root) else {
+            return;
+        };
+        if relative.components().any(|c| is_hidden(Path::new(&c))) {
+            return;
+        }
+
+        let prefix = VirtualPath::new(relative).as_rooted_path().to_path_buf();
+        if path.is_dir() {
+            for file in project_files(path) {
+                self.update_file(root, &file);
+            }
+        } else if !path.exists() {
+            self.files.retain(|key, _| !key.starts_with(&prefix));
+            self.updated.insert(prefix);
+        } else {
+            self.update_file(root, path);
+        }
+    }
+
+    fn update_file(&mut self, root: &Path, path: &Path) {
+        let Ok(relative) = path.strip_prefix(root) else {
+            return;
+        };
+        if !is_typst_file(path) {
+            return;
+        }
+
+        let vpath = VirtualPath::new(relative);
+        let key = vpath.as_rooted_path().to_path_buf();
+        self.updated.insert(key.clone());
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                let source = Source::new(FileId::new(None, vpath), text);
+                self.files.insert(key, file_symbols(&source));
+            }
+            Err(e) => {
+                if path.exists() {
+                    warn!("unable to index {:?}: {:?}", path, e);
+                }
+                self.files.remove(&key);
+            }
+        }
+    }
+
+    /// Finds the symbols whose name fuzzily matches the query, best matches
+    /// first. An empty query lists all symbols.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<WorkspaceSymbol> {
+        let mut matches: Vec<(i64, &WorkspaceSymbol)> = self
+            .files
+            .values()
+            .flatten()
+            .filter_map(|symbol| fuzzy_score(query, &symbol.name).map(|score| (score, symbol)))
+            .collect();
+
+        matches.sort_by(|(a_score, a), (b_score, b)| {
+            b_score
+                .cmp(a_score)
+                .then_with(|| a.name.len().cmp(&b.name.len()))
+                .then_with(|| a.location.path.cmp(&b.location.path))
+                .then_with(|| a.location.range.start.cmp(&b.location.range.start))
+        });
+
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(_, symbol)| symbol.clone())
+            .collect()
+    }
+
+    /// Lists the top-level functions and variables with exactly the given
+    /// name.
+    pub fn definitions<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a WorkspaceSymbol> {
+        self.files.values().flatten().filter(move |symbol| {
+            symbol.name == name
+                && matches!(symbol.kind, SymbolKind::Function | SymbolKind::Variable)
+        })
+    }
+}
+
+/// Lists the absolute paths of all `.typ` files below the project root.
+/// Hidden directories, such as `.git` or `.typster`, are skipped.
+pub fn project_files(root: &Path) -> Vec<PathBuf> {
+    WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|entry| entry.depth() == 0 || !is_hidden(entry.path()))
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file() && is_typst_file(entry.path()))
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+/// Collects the headings and labels of a file, as well as its top-level
+/// `let` bindings and imports.
+pub fn file_symbols(source: &Source) -> Vec<WorkspaceSymbol> {
+    let mut symbols = Vec::new();
+    let root = LinkedNode::new(source.root());
+    collect(source, &root, true, &mut symbols);
+    symbols
+}
+
+fn collect(
+    source: &Source,
+    node: &LinkedNode,
+    top_level: bool,
+    symbols: &mut Vec<WorkspaceSymbol>,
+) {
+    let mut push = |name: String, kind, detail, range| {
+        symbols.push(WorkspaceSymbol {
+            name,
+            kind,
+            detail,
+            location: SourceLocation::from_range(source, range),
+        })
+    };
+
+    match node.kind() {
+        SyntaxKind::Heading => {
+            if let Some(heading) = node.cast::<ast::Heading>() {
+                let name = heading.body().to_untyped().clone().into_text();
+                push(
+                    name.trim().to_string(),
+                    SymbolKind::Heading,
+                    Some(format!("level {}", heading.depth())),
+                    node.range(),
+                );
+            }
+        }
+        SyntaxKind::Label => {
+            if let Some(label) = node.cast::<ast::Label>() {
+                push(
+                    label.get().to_string(),
+                    SymbolKind::Label,
+                    None,
+                    node.range(),
+                );
+            }
+        }
+        SyntaxKind::LetBinding if top_level => {
+            if let Some(binding) = node.cast::<ast::LetBinding>() {
+                match binding.kind() {
+                    ast::LetBindingKind::Closure(ident) => {
+                        let detail = match binding.init() {
+                            Some(ast::Expr::Closure(closure)) => Some(
+                                closure
+                                    .params()
+                                    .to_untyped()
+                                    .clone()
+                                    .into_text()
+                                    .to_string(),
+                            ),
+                            _ => None,
+                        };
+                        let range = node.find(ident.span()).map_or(node.range(), |n| n.range());
+                        push(ident.as_str().into(), SymbolKind::Function, detail, range);
+                    }
+                    ast::LetBindingKind::Normal(pattern) => {
+                        for ident in pattern.bindings() {
+                            let range = node.find(ident.span()).map_or(node.range(), |n| n.range());
+                            push(ident.as_str().into(), SymbolKind::Variable, None, range);
+                        }
+                    }
+                }
+            }
+        }
+        SyntaxKind::ModuleImport if top_level => {
+            if let Some(import) = node.cast::<ast::ModuleImport>() {
+                if let ast::Expr::Str(path) = import.source() {
+                    let detail = import
+                        .new_name()
+                        .map(|name| format!("as {}", name.as_str()));
+                    push(
+                        path.get().to_string(),
+                        SymbolKind::Import,
+                        detail,
+                        node.range(),
+                    );
+                }
+            }
+        }
+        _ => {}
+    }
+
+    // Top-level bindings sit directly in the root markup behind a `#`. Those
+    // in a code or content block are local to the block.
+    let children_top_level = top_level && node.kind() == SyntaxKind::Markup;
+    for child in node.children() {
+        collect(source, &child, children_top_level, symbols);
+    }
+}
+
+/// Scores how well the query matches the candidate as a case-insensitive
+/// subsequence. Consecutive matches and matches at word starts score higher.
+/// Returns `None` if the candidate does not contain the query.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let mut score = 0;
+    let mut qi = 0;
+    let mut prev_matched = false;
+    let mut prev: Option<char> = None;
+    for (i, c) in candidate.chars().enumerate() {
+        if qi == query.len() {
+            break;
+        }
+        let matched = c.to_lowercase().eq(std::iter::once(query[qi]));
+        if matched {
+            score += 1;
+            if prev_matched {
+                score += 5;
+            }
+            if i == 0 {
+                score += 10;
+            } else if prev.is_some_and(|p| !p.is_alphanumeric()) || c.is_uppercase() {
+                score += 3;
+            }
+            qi += 1;
+        }
+        prev_matched = matched;
+        prev = Some(c);
+    }
+
+    (qi == query.len()).then_some(score)
+}
+
+fn is_typst_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "typ")
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn names(index: &SymbolIndex) -> Vec<String> {
+        let mut names: Vec<String> = index
+            .search("", usize::MAX)
+            .into_iter()
+            .map(|symbol| symbol.name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+        assert_eq!(fuzzy_score("xyz", "template"), None);
+        assert!(fuzzy_score("TPL", "template").is_some());
+        // Prefixes beat matches at word starts, which beat scattered ones.
+        let prefix = fuzzy_score("res", "resume").unwrap();
+        let word = fuzzy_score("res", "my-resume").unwrap();
+        let scattered = fuzzy_score("res", "treasures").unwrap();
+        assert!(prefix > word && word > scattered);
+    }
+
+    #[test]
+    fn test_file_symbols() {
+        let text = "= Intro <intro>\n#let f(x) = x\n#let (a, b) = (1, 2)\n\
+                    #{ let local = 1 }\n#[#let inner = 2]\n#import \"lib.typ\" as lib";
+        let source = Source::detached(text);
+        let symbols: Vec<_> = file_symbols(&source)
+            .into_iter()
+            .map(|symbol| (symbol.name, symbol.kind))
+            .collect();
+        assert_eq!(
+            symbols,
+            vec![
+                ("Intro".into(), SymbolKind::Heading),
+                ("intro".into(), SymbolKind::Label),
+                ("f".into(), SymbolKind::Function),
+                ("a".into(), SymbolKind::Variable),
+                ("b".into(), SymbolKind::Variable),
+                ("lib.typ".into(), SymbolKind::Import),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_update_directories() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("chapters")).unwrap();
+        fs::write(root.join("main.typ"), "#let main = 1").unwrap();
+        fs::write(root.join("chapters/one.typ"), "#let one = 1").unwrap();
+        let mut index = SymbolIndex::build(root);
+        assert_eq!(names(&index), ["main", "one"]);
+
+        fs::rename(root.join("chapters"), root.join("parts")).unwrap();
+        index.update(root, &root.join("chapters"));
+        index.update(root, &root.join("parts"));
+        assert_eq!(names(&index), ["main", "one"]);
+        assert_eq!(
+            index.definitions("one").next().unwrap().location.path,
+            Path::new("/parts/one.typ")
+        );
+
+        fs::remove_dir_all(root.join("parts")).unwrap();
+        index.update(root, &root.join("parts"));
+        assert_eq!(names(&index), ["main"]);
+    }
+
+    #[test]
+    fn test_merge_keeps_newer_entries() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("old")).unwrap();
+        fs::write(root.join("main.typ"), "#let before = 1").unwrap();
+        fs::write(root.join("other.typ"), "#let other = 1").unwrap();
+        fs::write(root.join("old/gone.typ"), "#let gone = 1").unwrap();
+        let built = SymbolIndex::build(root);
+
+        // Changes the watcher reports while the index is being built.
+        let mut index = SymbolIndex::default();
+        fs::write(root.join("main.typ"), "#let after = 1").unwrap();
+        index.update(root, &root.join("main.typ"));
+        fs::remove_dir_all(root.join("old")).unwrap();
+        index.update(root, &root.join("old"));
+
+        index.merge(built);
+        assert_eq!(names(&index), ["after", "other"]);
+    }
+}
@@ -20,7 +20,7 @@ pub struct ClipboardPasteResponse {
 #[tauri::command]
 pub async fn clipboard_paste<R: Runtime>(
     window: tauri::Window<R>,
-    project_manager: tauri::State<'_, Arc<ProjectManager<R>>>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
 ) -> Result<ClipboardPasteResponse> {
     let now = Local::now();
     let (_, path) = project_path(&window, &project_manager, PathBuf::from("assets"))?;
@@ -35,14 +35,14 @@ pub async fn clipboard_paste<R: Runtime>(
     let data = clipboard.get_image().map_err(|_| Error::Unknown)?;
 
     let file = File::create(&path).map_err(Into::<Error>::into)?;
-    let ref mut w = BufWriter::new(file);
+    let w = &mut BufWriter::new(file);
     let mut encoder = png::Encoder::new(w, data.width as u32, data.height as u32);
     encoder.set_color(png::ColorType::Rgba);
     encoder.set_depth(png::BitDepth::Eight);
 
     let mut writer = encoder.write_header().map_err(|_| Error::Unknown)?;
     writer
-        .write_image_data(&*data.bytes)
+        .write_image_data(&data.bytes)
         .map_err(|_| Error::Unknown)?;
 
     info!(
@@ -0,0 +1,229 @@
+use super::Result;
+use crate::export::{
+    self, BatchExportOptions, BatchReport, ExportDiagnostic, ExportError, ImageExportOptions,
+    ImageFormat, PdfExportOptions, PreflightOptions, PreflightReport,
+};
+use crate::ipc::commands::{project, session};
+use crate::ipc::BatchExportProgressEvent;
+use crate::project::{Project, ProjectEvent, ProjectManager, SessionId};
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tauri::Runtime;
+use typst::model::Document;
+use typst::World;
+
+#[derive(Serialize, Debug)]
+pub struct ExportResponse {
+    /// The size of the written file in bytes.
+    pub size: u64,
+    /// The revision of the sources the file was exported from.
+    pub revision: String,
+    pub warnings: Vec<ExportDiagnostic>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImageExportResponse {
+    /// The written files, one for each exported page.
+    pub files: Vec<PathBuf>,
+    /// The revision of the sources the files were exported from.
+    pub revision: String,
+    pub warnings: Vec<ExportDiagnostic>,
+}
+
+/// Compiles the project and exports the document to a PDF file. If the
+/// document has errors, nothing is written and the errors are returned.
+/// The export options are taken from `options` if given, otherwise from the
+/// project's preset of the given name, otherwise the defaults are used.
+#[tauri::command]
+pub async fn export_pdf<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    path: PathBuf,
+    preset: Option<String>,
+    options: Option<PdfExportOptions>,
+) -> Result<ExportResponse> {
+    let project = project(&window, &project_manager)?;
+    let options = match (options, preset) {
+        (Some(options), _) => options,
+        (None, Some(preset)) => project
+            .config
+            .read()
+            .unwrap()
+            .export
+            .pdf_presets
+            .get(&preset)
+            .cloned()
+            .ok_or(ExportError::UnknownPreset(preset))?,
+        (None, None) => PdfExportOptions::default(),
+    };
+
+    let world = project.world.lock().unwrap();
+    let compiled = export::compile(&world)?;
+    let buffer = export::pdf(&*world, &compiled.document, &options)?;
+    fs::write(&path, &buffer)?;
+    info!(
+        "exported PDF of revision {} to {}",
+        compiled.revision,
+        path.display()
+    );
+
+    cache_document(&project, compiled.document);
+    Ok(ExportResponse {
+        size: buffer.len() as u64,
+        revision: compiled.revision,
+        warnings: compiled.warnings,
+    })
+}
+
+/// Saves PDF export options as a named preset of the project, replacing a
+/// preset of the same name. The preset is written to the project
+/// configuration, so that it can be used with `export_pdf` and the CLI.
+#[tauri::command]
+pub async fn export_save_pdf_preset<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    name: String,
+    options: PdfExportOptions,
+) -> Result<()> {
+    let project = project(&window, &project_manager)?;
+    project.save_pdf_preset(name.clone(), options)?;
+    info!("saved PDF preset {} for {:?}", name, project);
+    Ok(())
+}
+
+/// Compiles the project and exports the selected pages to PNG images in the
+/// given directory.
+#[tauri::command]
+pub async fn export_png<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    dir: PathBuf,
+    options: Option<ImageExportOptions>,
+) -> Result<ImageExportResponse> {
+    let project = project(&window, &project_manager)?;
+    export_images(
+        &project,
+        ImageFormat::Png,
+        &dir,
+        &options.unwrap_or_default(),
+    )
+}
+
+/// Compiles the project and exports the selected pages to SVG images in the
+/// given directory.
+#[tauri::command]
+pub async fn export_svg<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    dir: PathBuf,
+    options: Option<ImageExportOptions>,
+) -> Result<ImageExportResponse> {
+    let project = project(&window, &project_manager)?;
+    export_images(
+        &project,
+        ImageFormat::Svg,
+        &dir,
+        &options.unwrap_or_default(),
+    )
+}
+
+/// Exports a PDF for each row of a CSV or JSON data file to the given
+/// directory, with the row as `sys.inputs`. Progress is reported with a
+/// `batch_export_progress` event after each row.
+#[tauri::command]
+pub async fn export_batch<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    data: PathBuf,
+    dir: PathBuf,
+    options: Option<BatchExportOptions>,
+) -> Result<BatchReport> {
+    let project = project(&window, &project_manager)?;
+    let session = session(&window);
+    let rows = export::read_rows(&data)?;
+
+    let mut world = project.world.lock().unwrap();
+    let report = export::batch(
+        &mut world,
+        &rows,
+        &dir,
+        &options.unwrap_or_default(),
+        |result, total| {
+            let event = BatchExportProgressEvent {
+                result: result.clone(),
+                total,
+            };
+            project_manager.emit(&session, ProjectEvent::BatchExportProgress(event));
+        },
+    )?;
+    info!(
+        "exported {} of {} rows of {} to {}",
+        report.written,
+        rows.len(),
+        data.display(),
+        dir.display()
+    );
+    Ok(report)
+}
+
+fn export_images(
+    project: &Project,
+    format: ImageFormat,
+    dir: &Path,
+    options: &ImageExportOptions,
+) -> Result<ImageExportResponse> {
+    let world = project.world.lock().unwrap();
+    let compiled = export::compile(&world)?;
+    let name = export::document_name(&*world);
+    let images = export::images(&compiled.document, format, &name, options)?;
+
+    fs::create_dir_all(dir)?;
+    let mut files = Vec::with_capacity(images.len());
+    for image in images {
+        let path = dir.join(&image.file_name);
+        fs::write(&path, &image.data)?;
+        files.push(path);
+    }
+    info!(
+        "exported {} {} images of revision {} to {}",
+        files.len(),
+        format.extension(),
+        compiled.revision,
+        dir.display()
+    );
+
+    cache_document(project, compiled.document);
+    Ok(ImageExportResponse {
+        files,
+        revision: compiled.revision,
+        warnings: compiled.warnings,
+    })
+}
+
+/// Keeps a freshly compiled document for the preview and later queries.
+fn cache_document(project: &Project, document: Document) {
+    let mut cache = project.cache.write().unwrap();
+    cache.document = Some(document);
+    cache.diagnostics.clear();
+}
+
+/// Compiles the project like an export and checks the document for print
+/// problems, such as low resolution images or fonts that may not be
+/// embedded, so that the report matches what would be exported.
+#[tauri::command]
+pub async fn preflight_pdf<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    options: Option<PreflightOptions>,
+) -> Result<PreflightReport> {
+    let project = project(&window, &project_manager)?;
+    let world = project.world.lock().unwrap();
+    let compiled = export::compile(&world)?;
+    let report = export::preflight(&*world, &compiled.document, &options.unwrap_or_default());
+
+    cache_document(&project, compiled.document);
+    Ok(report)
+}
@@ -1,9 +1,10 @@
 use super::{Error, Result};
-use crate::ipc::commands::project_path;
+use crate::ide::{self, TextEdit};
+use crate::ipc::commands::{project_path, session};
 use crate::project::{Project, ProjectManager};
 use ecow::eco_format;
 use enumset::EnumSetType;
-use log::info;
+use log::{debug, info};
 use serde::Serialize;
 use std::cmp::Ordering;
 use std::fs;
@@ -11,23 +12,9 @@ use std::fs::{File, OpenOptions};
 use std::io::Write;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use tauri::{Runtime, State, Window};
-use typst::foundations::Smart;
-use typst_pdf::{PdfOptions, PdfStandard, PdfStandards};
-use chrono::{DateTime, Datelike, Utc, Timelike};
-use typst::foundations::Datetime;
-
-/// Convert [`chrono::DateTime`] to [`Datetime`]
-pub fn convert_datetime(date_time:DateTime<Utc>) -> Option<Datetime> {
-    Datetime::from_ymd_hms(
-        date_time.year(),
-        date_time.month().try_into().ok()?,
-        date_time.day().try_into().ok()?,
-        date_time.hour().try_into().ok()?,
-        date_time.minute().try_into().ok()?,
-        date_time.second().try_into().ok()?,
-    )
-}
+use typst::syntax::Source;
 
 #[derive(EnumSetType, Serialize, Debug)]
 #[serde(rename_all = "snake_case")]
@@ -46,64 +33,24 @@ pub struct FileItem {
 #[tauri::command]
 pub async fn load_project_from_path<R: Runtime>(
     window: Window<R>,
-    project_manager: State<'_, Arc<ProjectManager<R>>>,
+    project_manager: State<'_, Arc<ProjectManager>>,
     path: String,
 ) -> std::result::Result<(), Error> {
     let path_buf = PathBuf::from(&path);
-    let project = Arc::new(Project::load_from_path(path_buf));
-    project_manager.set_project(&window, Some(project));
+    let project = Arc::new(Project::load_from_path(path_buf)?);
+    project_manager.set_project(&session(&window), Some(project));
     info!("succeed load_project_from_path {}", &path);
     
     Ok(())
 }
 
-#[tauri::command]
-pub async fn export_pdf<R: Runtime>(
-    window: Window<R>,
-    project_manager: State<'_, Arc<ProjectManager<R>>>,
-    path: String,
-) -> std::result::Result<u64, Error> {
-    info!("Starting export of PDF to path: {}", &path);
-
-    let path_buf = PathBuf::from(&path);
-
-    // 获取项目
-    if let Some(project) = project_manager.get_project(&window) {
-        let cache = project.cache.read().expect("Failed to read cache");
-
-        if let Some(doc) = &cache.document {
-            // 创建 CompileCommand
-
-            let options = PdfOptions {
-                ident: Smart::Auto,
-                timestamp: convert_datetime(Utc::now()),
-                page_ranges: None,
-                standards:  PdfStandards::new(&[PdfStandard::A_2b, PdfStandard::V_1_7]).unwrap(),
-            };
-
-            // 生成 PDF
-            let buffer = typst_pdf::pdf(doc, &options).map_err(|e| Error::Unknown)?;
-
-            // 写入 PDF 文件
-            fs::write(&path_buf, &buffer)?;
-
-            info!("PDF successfully exported to {}", path_buf.display());
-            return Ok(buffer.len() as u64);
-        } else {
-            return Err(Error::UnknownProject);
-        }
-    } else {
-        return Err(Error::UnknownProject);
-    }
-}
-
 /// Reads raw bytes from a specified path.
 /// Note that this command is slow compared to the text API due to Wry's
 /// messaging system in v1. See: https://github.com/tauri-apps/tauri/issues/1817
 #[tauri::command]
 pub async fn fs_read_file_binary<R: Runtime>(
     window: Window<R>,
-    project_manager: State<'_, Arc<ProjectManager<R>>>,
+    project_manager: State<'_, Arc<ProjectManager>>,
     path: PathBuf,
 ) -> std::result::Result<Vec<u8>, Error> {
     let (_, path) = project_path(&window, &project_manager, path)?;
@@ -113,7 +60,7 @@ pub async fn fs_read_file_binary<R: Runtime>(
 #[tauri::command]
 pub async fn fs_read_file_text<R: Runtime>(
     window: Window<R>,
-    project_manager: State<'_, Arc<ProjectManager<R>>>,
+    project_manager: State<'_, Arc<ProjectManager>>,
     path: PathBuf,
 ) -> std::result::Result<String, Error> {
     if path.is_absolute() {
@@ -126,7 +73,7 @@ pub async fn fs_read_file_text<R: Runtime>(
 #[tauri::command]
 pub async fn fs_create_file<R: Runtime>(
     window: Window<R>,
-    project_manager: State<'_, Arc<ProjectManager<R>>>,
+    project_manager: State<'_, Arc<ProjectManager>>,
     path: PathBuf,
 ) -> std::result::Result<(), Error> {
     let (_, path) = project_path(&window, &project_manager, path)?;
@@ -149,7 +96,7 @@ pub async fn fs_create_file<R: Runtime>(
 #[tauri::command]
 pub async fn fs_write_file_binary<R: Runtime>(
     window: Window<R>,
-    project_manager: State<'_, Arc<ProjectManager<R>>>,
+    project_manager: State<'_, Arc<ProjectManager>>,
     path: PathBuf,
     content: Vec<u8>,
 ) -> std::result::Result<(), Error> {
@@ -157,14 +104,32 @@ pub async fn fs_write_file_binary<R: Runtime>(
     fs::write(path, content).map_err(Into::into)
 }
 
+/// Writes a text file. If format-on-save is enabled for the project, typst
+/// sources are formatted first and the edits applied to `content` are
+/// returned, so that the editor can follow along.
 #[tauri::command]
 pub async fn fs_write_file_text<R: Runtime>(
     window: Window<R>,
-    project_manager: State<'_, Arc<ProjectManager<R>>>,
+    project_manager: State<'_, Arc<ProjectManager>>,
     path: PathBuf,
     content: String,
-) -> std::result::Result<(), Error> {
+) -> std::result::Result<Vec<TextEdit>, Error> {
     let (project, absolute_path) = project_path(&window, &project_manager, &path)?;
+
+    let mut content = content;
+    let mut edits = vec![];
+    let format = project.config.read().unwrap().format.clone();
+    if format.on_save && path.extension().is_some_and(|ext| ext == "typ") {
+        let source = Source::detached(content.clone());
+        match ide::format_text(&source, &format) {
+            Ok(formatted) => {
+                edits = ide::text_edits(&source, &formatted);
+                content = formatted;
+            }
+            Err(e) => debug!("skipped formatting {:?} on save: {}", path, e),
+        }
+    }
+
     let _ = File::create(absolute_path)
         .map(|mut f| f.write_all(content.as_bytes()))
         .map_err(Into::<Error>::into)?;
@@ -173,13 +138,18 @@ pub async fn fs_write_file_text<R: Runtime>(
     let _ = world
         .slot_update(&path, Some(content))
         .map_err(Into::<Error>::into)?;
-    Ok(())
+    drop(world);
+
+    // Saving writes the watch mode exports right away instead of waiting for
+    // the edits to pause.
+    project_manager.request_watch_export(&session(&window), &project, Duration::ZERO);
+    Ok(edits)
 }
 
 #[tauri::command]
 pub async fn fs_list_dir<R: Runtime>(
     window: Window<R>,
-    project_manager: State<'_, Arc<ProjectManager<R>>>,
+    project_manager: State<'_, Arc<ProjectManager>>,
     path: PathBuf,
 ) -> std::result::Result<Vec<FileItem>, Error> {
     let (_, path) = project_path(&window, &project_manager, path)?;
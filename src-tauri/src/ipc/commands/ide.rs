@@ -0,0 +1,318 @@
+use super::{Error, Result};
+use crate::ide::{
+    self, CodeAction, Evaluation, FileEdit, FoldingRange, LibraryDocs, OutlineItem,
+    References, SemanticTokens, SignatureHelp, TextEdit, WorkspaceSymbol,
+};
+use crate::ipc::commands::project;
+use crate::project::ProjectManager;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tauri::Runtime;
+use typst::syntax::Source;
+use typst::World;
+
+/// Describes the signature of the function call surrounding `offset`, which
+/// is a character offset into `content`.
+#[tauri::command]
+pub async fn typst_signature_help<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    path: PathBuf,
+    content: String,
+    offset: usize,
+) -> Result<Option<SignatureHelp>> {
+    let project = project(&window, &project_manager)?;
+    let mut world = project.world.lock().unwrap();
+
+    let offset = ide::char_to_byte(&content, offset);
+    let source_id = world
+        .slot_update(&*path, Some(content))
+        .map_err(Into::<Error>::into)?;
+    let source = world.source(source_id).map_err(Into::<Error>::into)?;
+
+    Ok(ide::signature_help(&*world, &source, offset))
+}
+
+/// Returns the heading tree of the last compiled document.
+#[tauri::command]
+pub async fn typst_outline<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+) -> Result<Vec<OutlineItem>> {
+    let project = project(&window, &project_manager)?;
+    let world = project.world.lock().unwrap();
+    let cache = project.cache.read().unwrap();
+
+    let document = cache.document.as_ref().ok_or(Error::NoDocument)?;
+    Ok(ide::outline(&*world, document))
+}
+
+/// Searches the symbols of all project files by fuzzy name.
+#[tauri::command]
+pub async fn typst_workspace_symbols<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    query: String,
+    limit: Option<usize>,
+) -> Result<Vec<WorkspaceSymbol>> {
+    let project = project(&window, &project_manager)?;
+    let symbols = project.symbols.read().unwrap();
+    Ok(symbols.search(&query, limit.unwrap_or(100)))
+}
+
+/// Lists all occurrences of the label or identifier at `offset` across the
+/// project's files.
+#[tauri::command]
+pub async fn typst_references<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    path: PathBuf,
+    content: String,
+    offset: usize,
+) -> Result<Option<References>> {
+    let project = project(&window, &project_manager)?;
+    let mut world = project.world.lock().unwrap();
+
+    let offset = ide::char_to_byte(&content, offset);
+    let source_id = world
+        .slot_update(&*path, Some(content))
+        .map_err(Into::<Error>::into)?;
+    let source = world.source(source_id).map_err(Into::<Error>::into)?;
+
+    Ok(ide::references(&*world, &project.root, &source, offset))
+}
+
+/// Computes the edits renaming the label or identifier at `offset` to
+/// `new_name` in all project files. The edits are not applied.
+#[tauri::command]
+pub async fn typst_rename<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    path: PathBuf,
+    content: String,
+    offset: usize,
+    new_name: String,
+) -> Result<Vec<FileEdit>> {
+    let project = project(&window, &project_manager)?;
+    let mut world = project.world.lock().unwrap();
+
+    let offset = ide::char_to_byte(&content, offset);
+    let source_id = world
+        .slot_update(&*path, Some(content))
+        .map_err(Into::<Error>::into)?;
+    let source = world.source(source_id).map_err(Into::<Error>::into)?;
+
+    ide::rename(&*world, &project.root, &source, offset, &new_name).map_err(Into::into)
+}
+
+/// Returns the names of the semantic token types used by
+/// [`typst_semantic_tokens`].
+#[tauri::command]
+pub fn typst_semantic_token_legend() -> Vec<&'static str> {
+    ide::semantic_token_legend()
+}
+
+/// Computes semantic tokens for a file, optionally limited to a character
+/// range. If `content` is given, the file's slot is updated first.
+#[tauri::command]
+pub async fn typst_semantic_tokens<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    path: PathBuf,
+    content: Option<String>,
+    range: Option<Range<usize>>,
+) -> Result<SemanticTokens> {
+    let project = project(&window, &project_manager)?;
+    let mut world = project.world.lock().unwrap();
+
+    let source_id = world
+        .slot_update(&*path, content)
+        .map_err(Into::<Error>::into)?;
+    let source = world.source(source_id).map_err(Into::<Error>::into)?;
+
+    let range = range.map(|range| {
+        ide::char_to_byte(source.text(), range.start)..ide::char_to_byte(source.text(), range.end)
+    });
+    Ok(ide::semantic_tokens(&source, range))
+}
+
+/// Formats a document with the project's formatting options and returns the
+/// edits to apply to `content`.
+#[tauri::command]
+pub async fn typst_format<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    content: String,
+) -> Result<Vec<TextEdit>> {
+    let project = project(&window, &project_manager)?;
+    let config = project.config.read().unwrap().format.clone();
+
+    let source = Source::detached(content);
+    ide::format_document(&source, &config).map_err(Into::into)
+}
+
+/// Like [`typst_format`], but only returns the edits touching the given
+/// character range.
+#[tauri::command]
+pub async fn typst_format_range<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    content: String,
+    range: Range<usize>,
+) -> Result<Vec<TextEdit>> {
+    let project = project(&window, &project_manager)?;
+    let config = project.config.read().unwrap().format.clone();
+
+    let range = ide::char_to_byte(&content, range.start)..ide::char_to_byte(&content, range.end);
+    let source = Source::detached(content);
+    ide::format_range(&source, range, &config).map_err(Into::into)
+}
+
+/// Computes the foldable regions of a file. If `content` is given, the file's
+/// slot is updated first.
+#[tauri::command]
+pub async fn typst_folding_ranges<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    path: PathBuf,
+    content: Option<String>,
+) -> Result<Vec<FoldingRange>> {
+    let project = project(&window, &project_manager)?;
+    let mut world = project.world.lock().unwrap();
+
+    let source_id = world
+        .slot_update(&*path, content)
+        .map_err(Into::<Error>::into)?;
+    let source = world.source(source_id).map_err(Into::<Error>::into)?;
+
+    Ok(ide::folding_ranges(&source))
+}
+
+/// Computes the expanding selection ranges for each of the given character
+/// offsets, innermost first. The editor steps through them to expand or
+/// shrink the selection.
+#[tauri::command]
+pub async fn typst_selection_ranges<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    path: PathBuf,
+    content: Option<String>,
+    offsets: Vec<usize>,
+) -> Result<Vec<Vec<Range<usize>>>> {
+    let project = project(&window, &project_manager)?;
+    let mut world = project.world.lock().unwrap();
+
+    let source_id = world
+        .slot_update(&*path, content)
+        .map_err(Into::<Error>::into)?;
+    let source = world.source(source_id).map_err(Into::<Error>::into)?;
+
+    Ok(offsets
+        .into_iter()
+        .map(|offset| ide::selection_ranges(&source, ide::char_to_byte(source.text(), offset)))
+        .collect())
+}
+
+/// Proposes quick fixes for the diagnostics of the last compilation that
+/// overlap the given character range of `content`.
+#[tauri::command]
+pub async fn typst_code_actions<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    path: PathBuf,
+    content: String,
+    range: Range<usize>,
+) -> Result<Vec<CodeAction>> {
+    let project = project(&window, &project_manager)?;
+    let mut world = project.world.lock().unwrap();
+
+    let range = ide::char_to_byte(&content, range.start)..ide::char_to_byte(&content, range.end);
+    let source_id = world
+        .slot_update(&*path, Some(content))
+        .map_err(Into::<Error>::into)?;
+    let source = world.source(source_id).map_err(Into::<Error>::into)?;
+
+    let cache = project.cache.read().unwrap();
+    let symbols = project.symbols.read().unwrap();
+    Ok(ide::code_actions(
+        &*world,
+        &source,
+        &cache.diagnostics,
+        &symbols,
+        range,
+    ))
+}
+
+/// Evaluates `expression` in the scope of the character `offset` of a file,
+/// for inspecting values without editing the document. Content results are
+/// rendered at `scale` pixels per point.
+#[tauri::command]
+pub async fn typst_evaluate<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    path: PathBuf,
+    content: String,
+    offset: usize,
+    expression: String,
+    scale: Option<f32>,
+) -> Result<Evaluation> {
+    let project = project(&window, &project_manager)?;
+    let mut world = project.world.lock().unwrap();
+
+    let offset = ide::char_to_byte(&content, offset);
+    let source_id = world
+        .slot_update(&*path, Some(content))
+        .map_err(Into::<Error>::into)?;
+    let source = world.source(source_id).map_err(Into::<Error>::into)?;
+
+    let cache = project.cache.read().unwrap();
+    ide::evaluate(
+        &mut world,
+        &path,
+        &source,
+        offset,
+        &expression,
+        cache.document.as_ref(),
+        scale.unwrap_or(2.0),
+    )
+    .map_err(Into::into)
+}
+
+/// Lists the documentation of the standard library and its symbols, for an
+/// offline reference and symbol picker.
+#[tauri::command]
+pub async fn typst_library_docs<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+) -> Result<LibraryDocs> {
+    let project = project(&window, &project_manager)?;
+    let world = project.world.lock().unwrap();
+
+    Ok(ide::library_docs(world.library()))
+}
+
+/// Queries the last compiled document for the elements matching `selector`,
+/// like `typst query`, and returns them as JSON.
+#[tauri::command]
+pub async fn typst_query<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    selector: String,
+    field: Option<String>,
+    one: Option<bool>,
+) -> Result<serde_json::Value> {
+    let project = project(&window, &project_manager)?;
+    let world = project.world.lock().unwrap();
+    let cache = project.cache.read().unwrap();
+    let document = cache.document.as_ref().ok_or(Error::NoDocument)?;
+
+    ide::query(
+        &*world,
+        document,
+        &selector,
+        field.as_deref(),
+        one.unwrap_or(false),
+    )
+    .map_err(Into::into)
+}
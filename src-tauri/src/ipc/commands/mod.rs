@@ -1,13 +1,24 @@
 mod clipboard;
+mod export;
 mod fs;
+mod ide;
+mod package;
 mod typst;
 
 pub use self::typst::*;
 pub use clipboard::*;
+pub use export::*;
 pub use fs::*;
+pub use ide::*;
+pub use package::*;
 
-use crate::project::{Project, ProjectManager};
-use ::typst::diag::FileError;
+use crate::export::ExportError;
+use crate::ide::{FormatError, QueryError, RenameError};
+use crate::project::{
+    CompileError, Project, ProjectConfigError, ProjectManager, PublishError, SessionId,
+    WorldCreationError,
+};
+use ::typst::diag::{FileError, PackageError};
 use serde::{Serialize, Serializer};
 use std::io;
 use std::path::{Component, Path, PathBuf};
@@ -22,12 +33,36 @@ pub enum Error {
     Unknown,
     #[error("unknown project")]
     UnknownProject,
+    #[error("unable to load the project: {0}")]
+    LoadProject(#[from] WorldCreationError),
+    #[error(transparent)]
+    Compile(#[from] CompileError),
+    #[error("unable to save the project configuration")]
+    Config(#[from] ProjectConfigError),
     #[error("io error occurred")]
     IO(#[from] io::Error),
     #[error("typst file error occurred")]
     TypstFile(#[from] FileError),
     #[error("the provided path does not belong to the project")]
     UnrelatedPath,
+    #[error("the project has not been compiled yet")]
+    NoDocument,
+    #[error(transparent)]
+    Rename(#[from] RenameError),
+    #[error(transparent)]
+    Format(#[from] FormatError),
+    #[error(transparent)]
+    Query(#[from] QueryError),
+    #[error(transparent)]
+    Export(#[from] ExportError),
+    #[error(transparent)]
+    Package(#[from] PackageError),
+    #[error("invalid package specification: {0}")]
+    PackageSpec(String),
+    #[error("unable to fetch the package index: {0}")]
+    PackageIndex(String),
+    #[error(transparent)]
+    Publish(#[from] PublishError),
 }
 
 impl Serialize for Error {
@@ -35,21 +70,29 @@ impl Serialize for Error {
     where
         S: Serializer,
     {
-        serializer.serialize_str(self.to_string().as_ref())
+        match self {
+            Error::Export(e) => e.serialize(serializer),
+            _ => serializer.serialize_str(self.to_string().as_ref()),
+        }
     }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// The session of a window, which is identified by its label.
+pub fn session<R: Runtime>(window: &Window<R>) -> SessionId {
+    SessionId::new(window.label())
+}
+
 /// Retrieves the project and resolves the path. Furthermore,
 /// this function will resolve the path relative to project's root
 /// and checks whether the path belongs to the project root.
 pub fn project<R: Runtime>(
     window: &Window<R>,
-    project_manager: &State<Arc<ProjectManager<R>>>,
+    project_manager: &State<Arc<ProjectManager>>,
 ) -> Result<Arc<Project>> {
     project_manager
-        .get_project(window)
+        .get_project(&session(window))
         .ok_or(Error::UnknownProject)
 }
 
@@ -58,11 +101,11 @@ pub fn project<R: Runtime>(
 /// and checks whether the path belongs to the project root.
 pub fn project_path<R: Runtime, P: AsRef<Path>>(
     window: &Window<R>,
-    project_manager: &State<Arc<ProjectManager<R>>>,
+    project_manager: &State<Arc<ProjectManager>>,
     path: P,
 ) -> Result<(Arc<Project>, PathBuf)> {
     let project = project_manager
-        .get_project(window)
+        .get_project(&session(window))
         .ok_or(Error::UnknownProject)?;
     let root_len = project.root.as_os_str().len();
     let mut out = project.root.to_path_buf();
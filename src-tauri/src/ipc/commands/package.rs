@@ -0,0 +1,213 @@
+use super::{Error, Result};
+use crate::export;
+use crate::ipc::commands::project;
+use crate::project::{
+    clear_package_cache, index_cache_path, installed_packages, publish_local, remove_package,
+    IndexedPackage, InstalledPackage, PackageIndex, PackageQuery, ProjectManager, PublishOptions,
+    PublishedPackage, UsedPackage,
+};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tauri::Runtime;
+use typst::diag::PackageError;
+use typst::syntax::package::PackageSpec;
+use typst::World;
+
+#[derive(Serialize, Debug)]
+pub struct PackageSearchResponse {
+    pub packages: Vec<IndexedPackage>,
+    /// When the searched index was fetched from the registry.
+    pub fetched: Option<DateTime<Utc>>,
+    /// Whether the registry couldn't be reached and the cached index was
+    /// searched instead.
+    pub offline: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PackageCacheClearResponse {
+    /// The number of bytes freed.
+    pub size: u64,
+}
+
+/// Lists the packages in the project's package data and cache directories.
+#[tauri::command]
+pub async fn package_list<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+) -> Result<Vec<InstalledPackage>> {
+    let project = project(&window, &project_manager)?;
+    let world = project.world.lock().unwrap();
+    Ok(installed_packages(world.package_storage()))
+}
+
+/// Deletes a package version, e.g. `@preview/cetz:0.3.1`, from the data and
+/// cache directories.
+#[tauri::command]
+pub async fn package_remove<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    spec: String,
+) -> Result<()> {
+    let project = project(&window, &project_manager)?;
+    let spec = parse_spec(&spec)?;
+    let removed = remove_package(project.world.lock().unwrap().package_storage(), &spec)?;
+    if removed.is_empty() {
+        return Err(Error::Package(PackageError::NotFound(spec)));
+    }
+    project_manager.forget_packages_in(&removed);
+    Ok(())
+}
+
+/// Deletes all downloaded packages.
+#[tauri::command]
+pub async fn package_clear_cache<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+) -> Result<PackageCacheClearResponse> {
+    let project = project(&window, &project_manager)?;
+    let world = project.world.lock().unwrap();
+    let size = clear_package_cache(world.package_storage())?;
+    let cache_dir = world
+        .package_storage()
+        .package_cache_path()
+        .map(PathBuf::from);
+    drop(world);
+    if let Some(dir) = cache_dir {
+        project_manager.forget_packages_in(&[dir]);
+    }
+    Ok(PackageCacheClearResponse { size })
+}
+
+/// Downloads a package ahead of time, e.g. before going offline. Progress is
+/// reported with `package_download` events.
+#[tauri::command]
+pub async fn package_prefetch<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    spec: String,
+) -> Result<PathBuf> {
+    let project = project(&window, &project_manager)?;
+    let spec = parse_spec(&spec)?;
+    let world = project.world.lock().unwrap();
+    let path = world.fetch_package(&spec)?;
+    info!("prefetched package {} to {}", spec, path.display());
+    Ok(path)
+}
+
+/// Lists the packages the project's document has used since it was opened.
+#[tauri::command]
+pub async fn package_used<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+) -> Result<Vec<UsedPackage>> {
+    let project = project(&window, &project_manager)?;
+    let world = project.world.lock().unwrap();
+    Ok(world.used_packages())
+}
+
+/// Copies the packages in use into `.typster/packages`, so that the project
+/// compiles without the package directories, e.g. on another machine. The
+/// project is compiled first if no package has been used yet.
+#[tauri::command]
+pub async fn package_vendor<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+) -> Result<Vec<UsedPackage>> {
+    let project = project(&window, &project_manager)?;
+    let world = project.world.lock().unwrap();
+    if world.used_packages().is_empty() {
+        if let Err(e) = export::compile(&world) {
+            warn!("compiling before vendoring packages failed: {}", e);
+        }
+    }
+    let packages = world.vendor_packages()?;
+    info!("vendored {} packages", packages.len());
+    Ok(packages)
+}
+
+/// Installs the project as a package of the `@local` namespace, creating its
+/// `typst.toml` if necessary. Other open projects can complete imports of the
+/// package right away.
+#[tauri::command]
+pub async fn package_publish<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    options: Option<PublishOptions>,
+) -> Result<PublishedPackage> {
+    let project = project(&window, &project_manager)?;
+    let published = {
+        let world = project.world.lock().unwrap();
+        let entrypoint = world.main().vpath().as_rootless_path().to_path_buf();
+        publish_local(
+            &project.root,
+            &entrypoint,
+            world.package_storage(),
+            &options.unwrap_or_default(),
+        )?
+    };
+    info!(
+        "published @{}/{}:{} to {}",
+        published.namespace,
+        published.name,
+        published.version,
+        published.path.display()
+    );
+
+    for project in project_manager.projects() {
+        project.world.lock().unwrap().refresh_package_completions();
+    }
+    Ok(published)
+}
+
+/// Searches the registry's package index. The index is fetched when it is
+/// outdated or `refresh` is set, otherwise the cached copy is used. Without a
+/// connection or in offline mode, the last cached copy is searched.
+#[tauri::command]
+pub async fn package_search<R: Runtime>(
+    window: tauri::Window<R>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
+    query: PackageQuery,
+    refresh: Option<bool>,
+) -> Result<PackageSearchResponse> {
+    let project = project(&window, &project_manager)?;
+    let (cached, cache_path, registry) = {
+        let world = project.world.lock().unwrap();
+        (
+            world.package_index(),
+            index_cache_path(world.package_storage()),
+            world.registry().clone(),
+        )
+    };
+
+    let mut offline = false;
+    let index = match cached {
+        Some(index) if !refresh.unwrap_or(false) && !index.is_outdated() => index,
+        cached => match PackageIndex::fetch(&registry, cache_path.as_deref()) {
+            Ok(index) => {
+                let index = Arc::new(index);
+                let mut world = project.world.lock().unwrap();
+                world.set_package_index(index.clone());
+                index
+            }
+            Err(e) => {
+                warn!("unable to fetch the package index: {}", e);
+                offline = true;
+                cached.ok_or_else(|| Error::PackageIndex(e.to_string()))?
+            }
+        },
+    };
+
+    Ok(PackageSearchResponse {
+        packages: index.search(&query).into_iter().cloned().collect(),
+        fetched: index.fetched,
+        offline,
+    })
+}
+
+fn parse_spec(spec: &str) -> Result<PackageSpec> {
+    spec.parse()
+        .map_err(|e: ecow::EcoString| Error::PackageSpec(e.to_string()))
+}
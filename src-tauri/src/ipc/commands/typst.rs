@@ -1,22 +1,16 @@
 use super::{Error, Result};
-use crate::ipc::commands::project;
+use crate::ipc::commands::{project, session};
 use crate::ipc::model::TypstRenderResponse;
-use crate::ipc::{
-    TypstCompileEvent, TypstDiagnosticSeverity, TypstDocument, TypstPage, TypstSourceDiagnostic,
-};
+use crate::ipc::{TypstCompileEvent, TypstDocument, TypstPage, TypstSourceDiagnostic};
 use crate::project::ProjectManager;
 use base64::Engine;
 use log::{debug, info};
 use serde::Serialize;
 use serde_repr::Serialize_repr;
-use siphasher::sip128::{Hasher128, SipHasher};
-use std::hash::Hash;
-use std::ops::Range;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Instant;
 use tauri::Runtime;
-use typst::diag::Severity;
 use typst::visualize::Color;
 use typst::World;
 use typst_ide::{Completion, CompletionKind};
@@ -67,7 +61,7 @@ impl From<Completion> for TypstCompletion {
 #[tauri::command]
 pub async fn typst_slot_update<R: Runtime>(
     window: tauri::Window<R>,
-    project_manager: tauri::State<'_, Arc<ProjectManager<R>>>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
     path: PathBuf,
     content: String,
 ) -> Result<()> {
@@ -83,116 +77,18 @@ pub async fn typst_slot_update<R: Runtime>(
 #[tauri::command]
 pub async fn typst_compile_doc<R: Runtime>(
     window: tauri::Window<R>,
-    project_manager: tauri::State<'_, Arc<ProjectManager<R>>>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
     path: PathBuf,
     content: String,
 ) -> Result<(Vec<TypstPage>, Vec<TypstSourceDiagnostic>)> {
-
-    let project = project(&window, &project_manager)?;
-    let mut world = project.world.lock().unwrap();
-    let source_id = world
-        .slot_update(&path, Some(content.clone()))
-        .map_err(Into::<Error>::into)?;
-
-    if !world.is_main_set() {
-        
-        let config = project.config.read().unwrap();
-        if config.apply_main(&project, &mut world).is_err() {
-            debug!("skipped compilation for {:?} (main not set)", project);
-            return Err(Error::Unknown);
-        }
-    }
-
-    let now = Instant::now();
-
-    let mut pages: Vec<TypstPage> = Vec::new();
-    let mut diags: Vec<TypstSourceDiagnostic> = Vec::new();
-    match typst::compile(&*world).output {
-        Ok(doc) => {
-            let elapsed = now.elapsed();
-            debug!(
-                "compilation succeeded for {:?} in {:?} ms",
-                project,
-                elapsed.as_millis()
-            );
-            let mut idx: u32 = 0;
-            for page in &doc.pages {
-                let mut hasher = SipHasher::new();
-                page.frame.hash(&mut hasher);
-                let hash = hex::encode(hasher.finish128().as_bytes());
-                let width = page.frame.width().to_pt();
-                let height = page.frame.height().to_pt();
-                idx += 1;
-                let pag = TypstPage {
-                    num: idx,
-                    width,
-                    height,
-                    hash: hash.clone(),
-                };
-                pages.push(pag);
-            }
-
-            project.cache.write().unwrap().document = Some(doc);
-        }
-        Err(diagnostics) => {
-            debug!("compilation failed with {:?} diagnostics", &diagnostics);
-
-            let source = world.source(source_id);
-            let diagnostics: Vec<TypstSourceDiagnostic> = match source {
-                Ok(source) => diagnostics
-                    .iter()
-                    .filter(|d| d.span.id() == Some(source_id))
-                    .filter_map(|d| {
-                        let span = source.find(d.span)?;
-                        let range = span.range();
-
-                        let message = d.message.to_string();
-                        Some(TypstSourceDiagnostic {
-                            pos: get_range_position(&content, range.clone()),
-                            range,
-                            severity: match d.severity {
-                                Severity::Error => TypstDiagnosticSeverity::Error,
-                                Severity::Warning => TypstDiagnosticSeverity::Warning,
-                            },
-                            message,
-                            hints: d.hints.iter().map(|hint| hint.to_string()).collect(),
-                        })
-                    })
-                    .collect(),
-                Err(_) => vec![],
-            };
-
-            diags = diagnostics.clone();
-        }
-    }
-
-    Ok((pages, diags))
-}
-
-
-pub fn get_range_position(text: &str, rang: Range<usize>) -> (usize, usize) {
-    let mut ln = 0;
-    let mut cn = 0;
-    let mut total: usize = 0;
-    for line in text.lines() {
-       
-        ln += 1;
-        let row = line.chars().count() + 1;
-
-        if total <= rang.start && rang.start <= total + row {
-            cn = rang.start - total;
-            break;
-        }
-
-        total += row;        
-    }
-    return (ln, cn);
+    let compilation = project_manager.compile(&session(&window), &path, content)?;
+    Ok((compilation.pages, compilation.diagnostics))
 }
 
 #[tauri::command]
 pub async fn typst_render<R: Runtime>(
     window: tauri::Window<R>,
-    project_manager: tauri::State<'_, Arc<ProjectManager<R>>>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
     page: usize,
     scale: f32,
     nonce: u32,
@@ -202,7 +98,7 @@ pub async fn typst_render<R: Runtime>(
         page, scale, nonce
     );
     let project = project_manager
-        .get_project(&window)
+        .get_project(&session(&window))
         .ok_or(Error::UnknownProject)?;
 
     let cache = project.cache.read().unwrap();
@@ -238,7 +134,7 @@ pub async fn typst_render<R: Runtime>(
 #[tauri::command]
 pub async fn typst_autocomplete<R: Runtime>(
     window: tauri::Window<R>,
-    project_manager: tauri::State<'_, Arc<ProjectManager<R>>>,
+    project_manager: tauri::State<'_, Arc<ProjectManager>>,
     path: PathBuf,
     content: String,
     offset: usize,
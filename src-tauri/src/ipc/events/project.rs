@@ -0,0 +1,23 @@
+use crate::project::{EventSink, ProjectEvent, SessionId};
+use log::warn;
+use tauri::{AppHandle, Emitter, EventTarget, Runtime};
+
+/// Delivers project events to the window whose label is the session id.
+pub struct TauriEventSink<R: Runtime> {
+    app: AppHandle<R>,
+}
+
+impl<R: Runtime> TauriEventSink<R> {
+    pub fn new(app: AppHandle<R>) -> Self {
+        Self { app }
+    }
+}
+
+impl<R: Runtime> EventSink for TauriEventSink<R> {
+    fn emit(&self, session: &SessionId, event: ProjectEvent) {
+        let target = EventTarget::window(session.as_str());
+        if let Err(e) = self.app.emit_to(target, event.name(), &event) {
+            warn!("unable to send {} to {}: {:?}", event.name(), session, e);
+        }
+    }
+}
@@ -1,4 +1,6 @@
+#[cfg(feature = "app")]
 pub mod commands;
+#[cfg(feature = "app")]
 pub mod events;
 
 mod model;
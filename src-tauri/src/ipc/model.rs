@@ -1,3 +1,4 @@
+use crate::export::{BatchRowResult, ExportDiagnostic};
 use serde::Serialize;
 use std::ops::Range;
 use std::path::PathBuf;
@@ -8,6 +9,48 @@ pub struct TypstCompileEvent {
     pub diagnostics: Option<Vec<TypstSourceDiagnostic>>,
 }
 
+/// Sent after watch mode wrote exports or failed to.
+#[derive(Serialize, Clone, Debug)]
+pub struct WatchExportEvent {
+    pub files: Vec<PathBuf>,
+    pub error: Option<String>,
+    pub diagnostics: Vec<ExportDiagnostic>,
+}
+
+/// Sent while a package is downloaded, which happens when it is first used.
+#[derive(Serialize, Clone, Debug)]
+pub struct PackageDownloadEvent {
+    /// The package, e.g. `@preview/cetz:0.3.1`.
+    pub package: String,
+    pub state: PackageDownloadState,
+    /// The number of bytes downloaded so far.
+    pub downloaded: usize,
+    /// The size of the package in bytes, if known.
+    pub total: Option<usize>,
+    /// The average speed in bytes per second.
+    pub speed: usize,
+    /// The estimated remaining time in seconds, if the size is known.
+    pub eta: Option<u64>,
+    /// A summary of the progress or the error.
+    pub message: String,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageDownloadState {
+    Start,
+    Progress,
+    Finish,
+    Error,
+}
+
+/// Sent after each row of a batch export.
+#[derive(Serialize, Clone, Debug)]
+pub struct BatchExportProgressEvent {
+    pub result: BatchRowResult,
+    pub total: usize,
+}
+
 #[derive(Serialize, Clone, Debug)]
 pub struct TypstDocument {
     pub pages: usize,
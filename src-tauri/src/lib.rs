@@ -2,29 +2,39 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 #![allow(unused_imports, unused_variables, dead_code, unused_mut)]
 
+#[cfg(feature = "app")]
 mod cmd;
+pub mod export;
+pub mod ide;
 mod ipc;
-mod project;
+pub mod project;
 
+#[cfg(feature = "app")]
+use crate::ipc::events::project::TauriEventSink;
 use crate::project::ProjectManager;
 use env_logger::Env;
 use log::info;
 use std::sync::Arc;
-use tauri::Wry;
+#[cfg(feature = "app")]
+use tauri::Manager;
 
+#[cfg(feature = "app")]
 pub fn run() {
     env_logger::init_from_env(Env::default().default_filter_or("debug"));
     info!("initializing typster");
 
-    let project_manager = Arc::new(ProjectManager::<Wry>::new());
-    if let Ok(watcher) = ProjectManager::init_watcher(project_manager.clone()) {
-        project_manager.set_watcher(watcher);
-    }
-
     tauri::Builder::default()
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
-        .manage(project_manager)
+        .setup(|app| {
+            let events = TauriEventSink::new(app.handle().clone());
+            let project_manager = Arc::new(ProjectManager::new(Arc::new(events)));
+            if let Ok(watcher) = ProjectManager::init_watcher(project_manager.clone()) {
+                project_manager.set_watcher(watcher);
+            }
+            app.manage(project_manager);
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             cmd::greet,
             ipc::commands::fs_list_dir,
@@ -37,9 +47,37 @@ pub fn run() {
             ipc::commands::typst_compile_doc,
             ipc::commands::typst_render,
             ipc::commands::typst_autocomplete,
+            ipc::commands::typst_signature_help,
+            ipc::commands::typst_outline,
+            ipc::commands::typst_workspace_symbols,
+            ipc::commands::typst_references,
+            ipc::commands::typst_rename,
+            ipc::commands::typst_semantic_token_legend,
+            ipc::commands::typst_semantic_tokens,
+            ipc::commands::typst_format,
+            ipc::commands::typst_format_range,
+            ipc::commands::typst_folding_ranges,
+            ipc::commands::typst_selection_ranges,
+            ipc::commands::typst_code_actions,
+            ipc::commands::typst_evaluate,
+            ipc::commands::typst_library_docs,
+            ipc::commands::typst_query,
+            ipc::commands::preflight_pdf,
             ipc::commands::typst_slot_update,
             ipc::commands::export_pdf,
-            ipc::commands::clipboard_paste
+            ipc::commands::export_save_pdf_preset,
+            ipc::commands::export_png,
+            ipc::commands::export_svg,
+            ipc::commands::export_batch,
+            ipc::commands::clipboard_paste,
+            ipc::commands::package_list,
+            ipc::commands::package_remove,
+            ipc::commands::package_clear_cache,
+            ipc::commands::package_prefetch,
+            ipc::commands::package_used,
+            ipc::commands::package_vendor,
+            ipc::commands::package_publish,
+            ipc::commands::package_search
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,290 @@
+use super::package::{package_dirs, Registry};
+use chrono::{DateTime, Duration, Utc};
+use ecow::{eco_format, EcoString};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use typst::syntax::package::{PackageSpec, PackageVersion};
+use typst_kit::download::ProgressSink;
+use typst_kit::package::PackageStorage;
+
+/// The index is fetched again when the cached copy is older than this.
+const MAX_AGE_HOURS: i64 = 24;
+
+/// A package version listed in the registry's index.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexedPackage {
+    pub name: EcoString,
+    pub version: PackageVersion,
+    #[serde(default)]
+    pub description: Option<EcoString>,
+    #[serde(default)]
+    pub authors: Vec<EcoString>,
+    #[serde(default)]
+    pub keywords: Vec<EcoString>,
+    #[serde(default)]
+    pub categories: Vec<EcoString>,
+    #[serde(default)]
+    pub disciplines: Vec<EcoString>,
+}
+
+/// The packages of the `@preview` namespace.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PackageIndex {
+    pub packages: Vec<IndexedPackage>,
+    /// When the index was fetched from the registry.
+    pub fetched: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PackageQuery {
+    /// Matches the name, description and keywords, ignoring case.
+    pub text: Option<String>,
+    pub keyword: Option<String>,
+    pub category: Option<String>,
+    /// Whether all versions are listed instead of only the latest one.
+    pub all_versions: bool,
+}
+
+impl PackageIndex {
+    /// Fetches the index from the registry and caches it on disk.
+    pub fn fetch(registry: &Registry, cache: Option<&Path>) -> Result<Self, EcoString> {
+        let data = registry
+            .fetch("preview/index.json", &mut ProgressSink)
+            .map_err(|err| eco_format!("{err}"))?;
+        let packages = serde_json::from_slice(&data)
+            .map_err(|err| eco_format!("the index is malformed ({err})"))?;
+        let index = Self {
+            packages,
+            fetched: Some(Utc::now()),
+        };
+
+        if let Some(cache) = cache {
+            let written = cache
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(cache, serde_json::to_vec(&index)?));
+            if let Err(err) = written {
+                info!("unable to cache the package index: {err}");
+            }
+        }
+        Ok(index)
+    }
+
+    /// Reads the copy cached by the last fetch.
+    pub fn cached(cache: &Path) -> Option<Self> {
+        let data = fs::read(cache).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    /// Whether the index should be fetched again.
+    pub fn is_outdated(&self) -> bool {
+        self.fetched.map_or(true, |fetched| {
+            Utc::now() - fetched > Duration::hours(MAX_AGE_HOURS)
+        })
+    }
+
+    /// Finds the packages matching a query. Packages whose name matches the
+    /// text come first, newer versions before older ones.
+    pub fn search(&self, query: &PackageQuery) -> Vec<&IndexedPackage> {
+        let text = query.text.as_deref().map(str::to_lowercase);
+        let contains = |values: &[EcoString], value: &Option<String>| {
+            value.as_ref().map_or(true, |value| {
+                values
+                    .iter()
+                    .any(|candidate| candidate.eq_ignore_ascii_case(value))
+            })
+        };
+
+        let mut packages: Vec<(u8, &IndexedPackage)> = self
+            .packages
+            .iter()
+            .filter(|package| contains(&package.keywords, &query.keyword))
+            .filter(|package| contains(&package.categories, &query.category))
+            .filter_map(|package| {
+                let Some(text) = &text else {
+                    return Some((0, package));
+                };
+                let name = package.name.to_lowercase();
+                if name == *text {
+                    Some((0, package))
+                } else if name.contains(text.as_str()) {
+                    Some((1, package))
+                } else if package
+                    .keywords
+                    .iter()
+                    .any(|keyword| keyword.to_lowercase().contains(text.as_str()))
+                    || package.description.as_ref().is_some_and(|description| {
+                        description.to_lowercase().contains(text.as_str())
+                    })
+                {
+                    Some((2, package))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        packages.sort_by(|(a_rank, a), (b_rank, b)| {
+            (a_rank, &a.name, b.version).cmp(&(b_rank, &b.name, a.version))
+        });
+        if !query.all_versions {
+            packages.dedup_by(|(_, a), (_, b)| a.name == b.name);
+        }
+        packages.into_iter().map(|(_, package)| package).collect()
+    }
+}
+
+/// Where the package index is cached, next to the downloaded packages.
+pub fn index_cache_path(storage: &PackageStorage) -> Option<PathBuf> {
+    storage
+        .package_cache_path()
+        .map(|dir| dir.join("preview").join("index.json"))
+}
+
+/// The packages offered when completing an import, i.e. the ones from the
+/// index and the local ones, along with their descriptions.
+pub fn package_completions(
+    storage: &PackageStorage,
+    index: Option<&PackageIndex>,
+) -> Vec<(PackageSpec, Option<EcoString>)> {
+    let indexed = index
+        .into_iter()
+        .flat_map(|index| &index.packages)
+        .map(|package| {
+            let spec = PackageSpec {
+                namespace: "preview".into(),
+                name: package.name.clone(),
+                version: package.version,
+            };
+            (spec, package.description.clone())
+        });
+    let local = storage
+        .package_path()
+        .into_iter()
+        .flat_map(package_dirs)
+        .filter_map(|([namespace, name, version], _)| {
+            let spec = PackageSpec {
+                namespace: namespace.into(),
+                name: name.into(),
+                version: version.parse().ok()?,
+            };
+            Some((spec, None))
+        });
+    indexed.chain(local).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::package::storage;
+    use crate::project::RegistryConfig;
+    use tempfile::TempDir;
+    use url::Url;
+
+    const INDEX: &str = r#"[
+        {"name": "cetz", "version": "0.3.1", "description": "Drawing with Typst", "keywords": ["canvas"], "categories": ["visualization"]},
+        {"name": "cetz", "version": "0.3.2", "description": "Drawing with Typst", "keywords": ["canvas"], "categories": ["visualization"]},
+        {"name": "cetz-plot", "version": "0.1.0", "description": "Plots for CeTZ", "categories": ["visualization"]},
+        {"name": "fletcher", "version": "0.5.3", "description": "Arrows and diagrams", "keywords": ["cetz"]},
+        {"name": "tablex", "version": "0.0.9", "description": "More table features", "categories": ["layout"]}
+    ]"#;
+
+    fn index() -> PackageIndex {
+        PackageIndex {
+            packages: serde_json::from_str(INDEX).unwrap(),
+            fetched: None,
+        }
+    }
+
+    fn search(query: PackageQuery) -> Vec<String> {
+        index()
+            .search(&query)
+            .into_iter()
+            .map(|package| format!("{}:{}", package.name, package.version))
+            .collect()
+    }
+
+    #[test]
+    fn test_search() {
+        let query = |text: &str| PackageQuery {
+            text: Some(text.into()),
+            ..Default::default()
+        };
+        // Exact names come first, then other names and then keywords.
+        assert_eq!(
+            search(query("CeTZ")),
+            ["cetz:0.3.2", "cetz-plot:0.1.0", "fletcher:0.5.3"]
+        );
+        assert_eq!(search(query("table")), ["tablex:0.0.9"]);
+        assert_eq!(search(query("diagrams")), ["fletcher:0.5.3"]);
+        assert!(search(query("chemistry")).is_empty());
+
+        let all = PackageQuery {
+            all_versions: true,
+            ..query("cetz")
+        };
+        assert_eq!(search(all)[..2], ["cetz:0.3.2", "cetz:0.3.1"]);
+    }
+
+    #[test]
+    fn test_search_filters() {
+        let category = PackageQuery {
+            category: Some("Visualization".into()),
+            ..Default::default()
+        };
+        assert_eq!(search(category), ["cetz:0.3.2", "cetz-plot:0.1.0"]);
+        let keyword = PackageQuery {
+            text: Some("draw".into()),
+            keyword: Some("canvas".into()),
+            ..Default::default()
+        };
+        assert_eq!(search(keyword), ["cetz:0.3.2"]);
+    }
+
+    #[test]
+    fn test_cached() {
+        let dir = TempDir::new().unwrap();
+        let storage = storage(None, Some(dir.path().into()), None);
+        let path = index_cache_path(&storage).unwrap();
+        assert!(PackageIndex::cached(&path).is_none());
+        assert!(PackageIndex::default().is_outdated());
+
+        let index = PackageIndex {
+            fetched: Some(Utc::now()),
+            ..index()
+        };
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, serde_json::to_vec(&index).unwrap()).unwrap();
+        let cached = PackageIndex::cached(&path).unwrap();
+        assert!(!cached.is_outdated());
+        assert_eq!(package_completions(&storage, Some(&cached)).len(), 5);
+    }
+
+    #[test]
+    fn test_fetch() {
+        let mirror = TempDir::new().unwrap();
+        fs::create_dir(mirror.path().join("preview")).unwrap();
+        fs::write(mirror.path().join("preview/index.json"), INDEX).unwrap();
+        let config = RegistryConfig {
+            url: Url::from_directory_path(mirror.path()).unwrap().to_string(),
+            timeout: 0,
+            ..Default::default()
+        };
+        let registry = Registry::new(config, None);
+
+        let cache = TempDir::new().unwrap();
+        let storage = storage(None, Some(cache.path().into()), None);
+        let path = index_cache_path(&storage).unwrap();
+        let index = PackageIndex::fetch(&registry, Some(&path)).unwrap();
+        assert_eq!(index.packages.len(), 5);
+        assert!(!index.is_outdated());
+        assert!(PackageIndex::default().is_outdated());
+
+        let cached = PackageIndex::cached(&path).unwrap();
+        assert_eq!(cached.fetched, index.fetched);
+        assert_eq!(package_completions(&storage, Some(&cached)).len(), 5);
+    }
+}
@@ -0,0 +1,208 @@
+use ecow::EcoString;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use typst::syntax::package::{PackageSpec, PackageVersion};
+use walkdir::WalkDir;
+
+const PATH_PACKAGE_LOCK_FILE: &str = ".typster/packages.lock";
+const PATH_VENDOR_DIR: &str = ".typster/packages";
+
+/// The packages a project was compiled with, so that a changed package is
+/// noticed instead of silently producing a different document.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PackageLock {
+    /// Maps package specs, e.g. `@preview/cetz:0.3.1`, to their entries.
+    pub packages: BTreeMap<String, LockedPackage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LockedPackage {
+    /// The hash of the package's files along with its algorithm, see
+    /// [`package_hash`].
+    pub hash: String,
+}
+
+/// A package resolved while compiling the project.
+#[derive(Serialize, Debug, Clone)]
+pub struct UsedPackage {
+    pub namespace: EcoString,
+    pub name: EcoString,
+    pub version: PackageVersion,
+    pub path: PathBuf,
+    pub hash: String,
+    /// Whether the package is read from the project's vendor directory.
+    pub vendored: bool,
+}
+
+impl PackageLock {
+    /// Reads the lock file of a project. A missing or malformed lock file
+    /// results in an empty lock, the latter with a warning.
+    pub fn read(root: &Path) -> Self {
+        let path = root.join(PATH_PACKAGE_LOCK_FILE);
+        let Ok(json) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        serde_json::from_str(&json).unwrap_or_else(|e| {
+            warn!("ignoring malformed package lock {}: {}", path.display(), e);
+            Self::default()
+        })
+    }
+
+    pub fn write(&self, root: &Path) -> io::Result<()> {
+        let path = root.join(PATH_PACKAGE_LOCK_FILE);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn get(&self, spec: &PackageSpec) -> Option<&LockedPackage> {
+        self.packages.get(&spec.to_string())
+    }
+
+    pub fn insert(&mut self, spec: &PackageSpec, hash: String) {
+        self.packages
+            .insert(spec.to_string(), LockedPackage { hash });
+    }
+}
+
+pub fn is_package_lock_file(relative: &Path) -> bool {
+    relative.as_os_str() == PATH_PACKAGE_LOCK_FILE
+}
+
+/// Where a package is copied to when it is vendored into a project.
+pub fn vendor_dir(root: &Path, spec: &PackageSpec) -> PathBuf {
+    root.join(PATH_VENDOR_DIR)
+        .join(spec.namespace.as_str())
+        .join(spec.name.as_str())
+        .join(spec.version.to_string())
+}
+
+/// The vendored copy of a package, if the project has one.
+pub fn vendored_package(root: &Path, spec: &PackageSpec) -> Option<PathBuf> {
+    Some(vendor_dir(root, spec)).filter(|dir| dir.is_dir())
+}
+
+/// Hashes the relative paths and contents of all files of a package, so that
+/// the hash doesn't depend on where the package is stored. The hash is given
+/// as `sha256:<hex>`.
+pub fn package_hash(dir: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    for entry in WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+        let relative: Vec<_> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        let data = fs::read(entry.path())?;
+        hasher.update(relative.join("/").as_bytes());
+        hasher.update([0]);
+        hasher.update((data.len() as u64).to_be_bytes());
+        hasher.update(&data);
+    }
+    Ok(format!("sha256:{}", hex::encode(hasher.finalize())))
+}
+
+/// Copies a package into the project's vendor directory, replacing an older
+/// copy, and returns the directory of the copy.
+pub fn vendor_package(root: &Path, spec: &PackageSpec, from: &Path) -> io::Result<PathBuf> {
+    let to = vendor_dir(root, spec);
+    if to == from {
+        return Ok(to);
+    }
+    if to.exists() {
+        fs::remove_dir_all(&to)?;
+    }
+    for entry in WalkDir::new(from) {
+        let entry = entry?;
+        let target = to.join(entry.path().strip_prefix(from).unwrap_or(entry.path()));
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)?;
+        } else if entry.file_type().is_file() {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use tempfile::TempDir;
+
+    fn package(dir: &Path, files: &[(&str, &str)]) {
+        for (name, text) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_package_hash() {
+        let dir = TempDir::new().unwrap();
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        let files = [("typst.toml", "[package]"), ("src/lib.typ", "#let x = 1")];
+        package(&a, &files);
+        package(&b, &files);
+        // The hash doesn't depend on where the package is stored.
+        let hash = package_hash(&a).unwrap();
+        assert_eq!(hash, package_hash(&b).unwrap());
+        assert!(hash.starts_with("sha256:"));
+        assert_eq!(hash.len(), "sha256:".len() + 64);
+
+        fs::write(b.join("src/lib.typ"), "#let x = 2").unwrap();
+        assert_ne!(package_hash(&a).unwrap(), package_hash(&b).unwrap());
+        fs::write(b.join("src/lib.typ"), "#let x = 1").unwrap();
+        fs::rename(b.join("src/lib.typ"), b.join("src/main.typ")).unwrap();
+        assert_ne!(package_hash(&a).unwrap(), package_hash(&b).unwrap());
+    }
+
+    #[test]
+    fn test_vendor_package() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("project");
+        let from = dir.path().join("cache");
+        package(&from, &[("typst.toml", "[package]"), ("src/lib.typ", "")]);
+        let spec = PackageSpec::from_str("@preview/example:0.1.0").unwrap();
+        assert_eq!(vendored_package(&root, &spec), None);
+
+        let to = vendor_package(&root, &spec, &from).unwrap();
+        assert_eq!(to, root.join(".typster/packages/preview/example/0.1.0"));
+        assert_eq!(vendored_package(&root, &spec), Some(to.clone()));
+        assert_eq!(package_hash(&to).unwrap(), package_hash(&from).unwrap());
+
+        // An older copy is replaced.
+        fs::write(to.join("stale.typ"), "").unwrap();
+        vendor_package(&root, &spec, &from).unwrap();
+        assert!(!to.join("stale.typ").exists());
+        assert_eq!(vendor_package(&root, &spec, &to).unwrap(), to);
+    }
+
+    #[test]
+    fn test_package_lock() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(PackageLock::read(dir.path()), PackageLock::default());
+
+        let spec = PackageSpec::from_str("@preview/example:0.1.0").unwrap();
+        let mut lock = PackageLock::default();
+        let hash = format!("sha256:{}", "0".repeat(64));
+        lock.insert(&spec, hash.clone());
+        lock.write(dir.path()).unwrap();
+        let read = PackageLock::read(dir.path());
+        assert_eq!(read.get(&spec).unwrap().hash, hash);
+
+        fs::write(dir.path().join(PATH_PACKAGE_LOCK_FILE), "{").unwrap();
+        assert_eq!(PackageLock::read(dir.path()), PackageLock::default());
+    }
+}
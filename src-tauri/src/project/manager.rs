@@ -1,13 +1,20 @@
-use crate::ipc::{FSRefreshEvent, ProjectChangeEvent, ProjectModel};
-use crate::project::{is_project_config_file, Project, ProjectConfig};
+use crate::ipc::{FSRefreshEvent, ProjectChangeEvent, ProjectModel, WatchExportEvent};
+use crate::project::{
+    Compilation, CompileError, EventSink, Project, ProjectEvent, SessionEvents, SessionId,
+};
 use log::{debug, error, info, trace, warn};
 use notify::event::ModifyKind;
 use notify::{Config, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::channel;
 use std::sync::{Arc, Mutex, RwLock};
-use tauri::{Emitter, Runtime, WebviewWindow, Window};
-use tokio::sync::mpsc::channel;
+use std::thread;
+use std::time::Duration;
+
+/// How long watch mode waits for further edits before it exports.
+const WATCH_EXPORT_DELAY: Duration = Duration::from_millis(500);
 
 #[derive(Clone, Copy, Debug)]
 enum FSHandleKind {
@@ -15,30 +22,23 @@ enum FSHandleKind {
     Reload,
 }
 
-pub struct ProjectManager<R: Runtime> {
-    projects: RwLock<HashMap<Window<R>, Arc<Project>>>,
+/// Keeps the projects opened by the clients of sessions, e.g. app windows,
+/// and sends them events through a sink.
+pub struct ProjectManager {
+    projects: RwLock<HashMap<SessionId, Arc<Project>>>,
     watcher: Mutex<Option<Box<dyn Watcher + Send + Sync>>>,
+    events: Arc<dyn EventSink>,
 }
 
-impl<R: Runtime> ProjectManager<R> {
+impl ProjectManager {
     pub fn init_watcher(
-        project_manager: Arc<ProjectManager<R>>,
+        project_manager: Arc<ProjectManager>,
     ) -> anyhow::Result<Box<dyn Watcher + Send + Sync>> {
-        let (tx, mut rx) = channel(1);
-
-        let rt = tokio::runtime::Builder::new_current_thread()
-            .enable_all()
-            .build()?;
-
-        let watcher = RecommendedWatcher::new(
-            move |res| {
-                let _ = rt.block_on(tx.send(res));
-            },
-            Config::default(),
-        )?;
+        let (tx, rx) = channel();
+        let watcher = RecommendedWatcher::new(tx, Config::default())?;
 
-        tokio::spawn(async move {
-            while let Some(res) = rx.recv().await {
+        thread::spawn(move || {
+            for res in rx {
                 match res {
                     Ok(event) => project_manager.handle_fs_event(event),
                     Err(e) => error!("watch error {:?}", e),
@@ -54,18 +54,77 @@ impl<R: Runtime> ProjectManager<R> {
         *inner = Some(watcher);
     }
 
-    pub fn get_project(&self, window: &Window<R>) -> Option<Arc<Project>> {
-        self.projects.read().unwrap().get(window).cloned()
+    pub fn get_project(&self, session: &SessionId) -> Option<Arc<Project>> {
+        self.projects.read().unwrap().get(session).cloned()
+    }
+
+    /// The projects open in any session.
+    pub fn projects(&self) -> Vec<Arc<Project>> {
+        self.projects.read().unwrap().values().cloned().collect()
+    }
+
+    /// Makes every open project fetch the packages in the given directories
+    /// again, after they were deleted.
+    pub fn forget_packages_in(&self, dirs: &[PathBuf]) {
+        for project in self.projects() {
+            project.world.lock().unwrap().forget_packages_in(dirs);
+        }
+    }
+
+    /// Compiles the project of a session with the editor's content of a file.
+    /// The watch mode exports are written once the edits pause.
+    pub fn compile(
+        &self,
+        session: &SessionId,
+        path: &Path,
+        content: String,
+    ) -> Result<Compilation, CompileError> {
+        let project = self.get_project(session).ok_or(CompileError::NoProject)?;
+        let compilation = project.compile(path, content)?;
+        if compilation.succeeded {
+            self.request_watch_export(session, &project, WATCH_EXPORT_DELAY);
+        }
+        Ok(compilation)
+    }
+
+    /// Writes the watch mode exports of the last compiled document in the
+    /// background after a delay, unless another export is requested in the
+    /// meantime. The client is told about written files or failures.
+    pub fn request_watch_export(
+        &self,
+        session: &SessionId,
+        project: &Arc<Project>,
+        delay: Duration,
+    ) {
+        if !project.config.read().unwrap().export.watch.enabled {
+            return;
+        }
+
+        let request = project.export_requests.fetch_add(1, Ordering::SeqCst) + 1;
+        let events = SessionEvents::new(session.clone(), self.events.clone());
+        let project = project.clone();
+        thread::spawn(move || {
+            thread::sleep(delay);
+            if project.export_requests.load(Ordering::SeqCst) == request {
+                watch_export(&project, &events);
+            }
+        });
+    }
+
+    /// Sends an event to the client of a session.
+    pub fn emit(&self, session: &SessionId, event: ProjectEvent) {
+        self.events.emit(session, event);
     }
 
-    pub fn set_project(&self, window: &Window<R>, project: Option<Arc<Project>>) {
+    pub fn set_project(&self, session: &SessionId, project: Option<Arc<Project>>) {
         let mut projects = self.projects.write().unwrap();
         let model = project.as_ref().map(|p| ProjectModel {
             root: p.root.clone(),
         });
         match project {
             None => {
-                if let Some(old) = projects.remove(window) {
+                if let Some(old) = projects.remove(session) {
+                    old.world.lock().unwrap().set_events(None);
                     let mut guard = self.watcher.lock().unwrap();
                     if let Some(watcher) = guard.as_mut() {
                         let _ = watcher.unwatch(&old.root);
@@ -73,11 +132,18 @@ impl<R: Runtime> ProjectManager<R> {
                 }
             }
             Some(p) => {
-                p.config.read().unwrap().apply(&*p);
+                p.config.read().unwrap().apply(&p);
+                p.world
+                    .lock()
+                    .unwrap()
+                    .set_events(Some(SessionEvents::new(
+                        session.clone(),
+                        self.events.clone(),
+                    )));
 
                 let root = &p.root.clone();
                 let mut guard = self.watcher.lock().unwrap();
-                if let Some(old) = projects.insert(window.clone(), p) {
+                if let Some(old) = projects.insert(session.clone(), p) {
                     if let Some(watcher) = guard.as_mut() {
                         let _ = watcher.unwatch(&old.root);
                     }
@@ -85,11 +151,20 @@ impl<R: Runtime> ProjectManager<R> {
                 if let Some(watcher) = guard.as_mut() {
                     let _ = watcher.watch(root, RecursiveMode::Recursive);
                 }
+
+                // Indexing walks the whole project, so it must not block the
+                // client while loading.
+                if let Some(project) = projects.get(session).cloned() {
+                    std::thread::spawn(move || project.index_symbols());
+                }
             }
         };
 
-        info!("project set for window {}: {:?}", window.label(), model);
-        let _ = window.emit("project_changed", ProjectChangeEvent { project: model });
+        info!("project set for session {}: {:?}", session, model);
+        self.emit(
+            session,
+            ProjectEvent::ProjectChanged(ProjectChangeEvent { project: model }),
+        );
     }
 
     fn handle_fs_event(&self, event: notify::Event) {
@@ -107,13 +182,45 @@ impl<R: Runtime> ProjectManager<R> {
             _ => None,
         };
 
+        self.update_symbols(&event);
+
         if let Some((path, kind)) = opt {
             let path = path.canonicalize().unwrap_or(path);
             let projects = self.projects.read().unwrap();
 
-            for (window, project) in &*projects {
+            for (session, project) in &*projects {
                 if path.starts_with(&project.root) {
-                    self.handle_project_fs_event(project, window, &path, kind);
+                    self.handle_project_fs_event(project, session, &path, kind);
+                }
+            }
+        }
+    }
+
+    /// Keeps the workspace symbol indexes in sync with created, modified,
+    /// removed and renamed files.
+    fn update_symbols(&self, event: &notify::Event) {
+        match event.kind {
+            EventKind::Create(_) | EventKind::Remove(_) => {}
+            EventKind::Modify(ModifyKind::Name(_) | ModifyKind::Data(_)) => {}
+            _ => return,
+        }
+
+        let projects = self.projects.read().unwrap();
+        for path in &event.paths {
+            // Removed paths can't be canonicalized, but their parent can.
+            let path = path.canonicalize().unwrap_or_else(|_| {
+                match (path.parent().map(Path::canonicalize), path.file_name()) {
+                    (Some(Ok(parent)), Some(name)) => parent.join(name),
+                    _ => path.clone(),
+                }
+            });
+            for project in projects.values() {
+                if path.starts_with(&project.root) {
+                    project
+                        .symbols
+                        .write()
+                        .unwrap()
+                        .update(&project.root, &path);
                 }
             }
         }
@@ -122,7 +229,7 @@ impl<R: Runtime> ProjectManager<R> {
     fn handle_project_fs_event(
         &self,
         project: &Project,
-        window: &Window<R>,
+        session: &SessionId,
         path: &PathBuf,
         kind: FSHandleKind,
     ) {
@@ -139,43 +246,50 @@ impl<R: Runtime> ProjectManager<R> {
                     let event = FSRefreshEvent {
                         path: relative.to_path_buf(),
                     };
-                    let _ = window.emit("fs_refresh", &event);
+                    self.emit(session, ProjectEvent::FsRefresh(event));
                 }
             }
             // Reloads the file content, eg. project config or project source files
-            FSHandleKind::Reload => {
-                if let Ok(relative) = path.strip_prefix(&project.root) {
-                    if is_project_config_file(relative) {
-                        if let Ok(config) = ProjectConfig::read_from_file(path) {
-                            debug!("updating project config for {:?}: {:?}", project, config);
-                            let mut config_write = project.config.write().unwrap();
-                            *config_write = config;
-                            config_write.apply(project);
-                        }
-                    } else {
-                        let mut world = project.world.lock().unwrap();
-                        let path = Path::new("/").join(relative);
-                        match world.slot_update(&path, None) {
-                            Ok(id) => {
-                                debug!("updated slot for {:?} {:?} in {:?}", path, id, project);
-                            }
-                            Err(e) => {
-                                warn!(
-                                    "unable to update slot for {:?} in {:?}: {:?}",
-                                    path, project, e
-                                );
-                            }
-                        }
-                    }
-                }
-            }
+            FSHandleKind::Reload => project.reload_file(path),
         }
     }
 
-    pub fn new() -> Self {
+    pub fn new(events: Arc<dyn EventSink>) -> Self {
         Self {
             projects: RwLock::new(HashMap::new()),
             watcher: Mutex::new(None),
+            events,
         }
     }
 }
+
+/// Writes the watch mode exports of the last compiled document.
+fn watch_export(project: &Project, events: &SessionEvents) {
+    let config = project.config.read().unwrap().export.watch.clone();
+    let world = project.world.lock().unwrap();
+    let cache = project.cache.read().unwrap();
+    let Some(document) = cache.document.as_ref() else {
+        return;
+    };
+    let mut exporter = project.exporter.lock().unwrap();
+    let event = match exporter.export(&*world, &project.root, document, &config) {
+        Ok(files) if files.is_empty() => return,
+        Ok(files) => {
+            debug!("watch mode wrote {:?} for {:?}", files, project);
+            WatchExportEvent {
+                files,
+                error: None,
+                diagnostics: vec![],
+            }
+        }
+        Err(e) => {
+            warn!("watch mode export failed for {:?}: {}", project, e);
+            WatchExportEvent {
+                files: vec![],
+                error: Some(e.to_string()),
+                diagnostics: e.diagnostics().to_vec(),
+            }
+        }
+    };
+    events.emit(ProjectEvent::WatchExport(event));
+}
@@ -1,9 +1,18 @@
+#[allow(clippy::module_inception)]
 mod project;
 mod world;
 mod manager;
 mod package;
+mod index;
+mod session;
+mod lock;
+mod publish;
 
 pub use project::*;
 pub use world::*;
 pub use manager::*;
 pub use package::*;
+pub use index::*;
+pub use session::*;
+pub use lock::*;
+pub use publish::*;
@@ -1,83 +1,181 @@
-use ecow::eco_format;
+use super::{ProjectEvent, RegistryConfig, SessionEvents};
+use crate::ipc::{PackageDownloadEvent, PackageDownloadState};
+use chrono::{DateTime, Utc};
+use ecow::{eco_format, EcoString};
 use log::info;
 use native_tls::TlsConnector;
+use serde::Serialize;
 use std::collections::VecDeque;
 use std::fmt::Display;
 use std::fs;
 use std::io::{self, ErrorKind, Read};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::thread;
 use std::time::{Duration, Instant};
 use typst::diag::{PackageError, PackageResult};
-use typst::syntax::package::PackageSpec;
+use typst::syntax::package::{PackageSpec, PackageVersion};
 use typst_kit::download::{DownloadState, Downloader, Progress};
 use typst_kit::package::PackageStorage;
 use typst_utils::format_duration;
 use ureq::Response;
+use url::Url;
+use walkdir::WalkDir;
 
-const HOST: &str = "https://packages.typst.org";
+pub(crate) const HOST: &str = "https://packages.typst.org";
 /// Keep track of this many download speed samples.
 const SPEED_SAMPLES: usize = 5;
+/// The delay before the first retry of a failed download, which grows with
+/// each further attempt.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
 
 /// Prints download progress by writing `downloading {0}` followed by repeatedly
-/// updating the last terminal line.
+/// updating the last terminal line. Goes to standard error, so that it doesn't
+/// mix with the output of the command line interface.
 pub struct PrintDownload<T>(pub T);
 
 impl<T: Display> Progress for PrintDownload<T> {
     fn print_start(&mut self) {
-        println!("downloading");
+        eprintln!("downloading {}", self.0);
     }
 
     fn print_progress(&mut self, state: &DownloadState) {
         let state_str = display_download_progress(state).expect("print progress error");
-        println!("{}", state_str);
+        eprintln!("{}", state_str);
     }
 
     fn print_finish(&mut self, state: &DownloadState) {
         let state_str = display_download_progress(state).expect("print progress finish error");
-        println!("{}", state_str);
+        eprintln!("{}", state_str);
+    }
+}
+
+/// Sends download progress as events to the client of a session, so that it
+/// can show why the compilation doesn't finish yet.
+pub struct EventDownload<'a> {
+    spec: &'a PackageSpec,
+    events: &'a SessionEvents,
+    started: bool,
+}
+
+impl<'a> EventDownload<'a> {
+    pub fn new(spec: &'a PackageSpec, events: &'a SessionEvents) -> Self {
+        Self {
+            spec,
+            events,
+            started: false,
+        }
+    }
+
+    /// Reports a failed download. Failures to find a package that was not
+    /// downloaded, e.g. a missing local one, are not reported.
+    pub fn fail(&mut self, error: &PackageError) {
+        if self.started {
+            self.emit(PackageDownloadState::Error, None, error.to_string());
+        }
+    }
+
+    fn emit(&self, state: PackageDownloadState, stats: Option<DownloadStats>, message: String) {
+        let event = PackageDownloadEvent {
+            package: self.spec.to_string(),
+            state,
+            downloaded: stats.as_ref().map_or(0, |stats| stats.downloaded),
+            total: stats.as_ref().and_then(|stats| stats.total),
+            speed: stats.as_ref().map_or(0, |stats| stats.speed),
+            eta: stats
+                .as_ref()
+                .and_then(|stats| stats.eta)
+                .map(|eta| eta.as_secs()),
+            message,
+        };
+        self.events.emit(ProjectEvent::PackageDownload(event));
+    }
+}
+
+impl Progress for EventDownload<'_> {
+    fn print_start(&mut self) {
+        self.started = true;
+        self.emit(
+            PackageDownloadState::Start,
+            None,
+            format!("downloading {}", self.spec),
+        );
+    }
+
+    fn print_progress(&mut self, state: &DownloadState) {
+        let message = display_download_progress(state).unwrap_or_default();
+        self.emit(
+            PackageDownloadState::Progress,
+            Some(DownloadStats::new(state)),
+            message,
+        );
+    }
+
+    fn print_finish(&mut self, state: &DownloadState) {
+        let message = display_download_progress(state).unwrap_or_default();
+        self.emit(
+            PackageDownloadState::Finish,
+            Some(DownloadStats::new(state)),
+            message,
+        );
+    }
+}
+
+/// Statistics of a running download.
+pub struct DownloadStats {
+    pub downloaded: usize,
+    pub total: Option<usize>,
+    /// The average speed in bytes per second.
+    pub speed: usize,
+    pub elapsed: Duration,
+    /// The estimated remaining time, if the size of the download is known.
+    pub eta: Option<Duration>,
+}
+
+impl DownloadStats {
+    pub fn new(state: &DownloadState) -> Self {
+        let sum: usize = state.bytes_per_second.iter().sum();
+        let len = state.bytes_per_second.len();
+        let speed = sum
+            .checked_div(len)
+            .unwrap_or_else(|| state.content_len.unwrap_or(0));
+
+        let eta = state.content_len.map(|content_len| {
+            let remaining = content_len.saturating_sub(state.total_downloaded);
+            Duration::from_secs(remaining.checked_div(speed).unwrap_or(0) as u64)
+        });
+
+        Self {
+            downloaded: state.total_downloaded,
+            total: state.content_len,
+            speed,
+            elapsed: Instant::now().saturating_duration_since(state.start_time),
+            eta,
+        }
     }
 }
 
 /// Compile and format several download statistics and make and attempt at
 /// displaying them on standard error.
 pub fn display_download_progress(state: &DownloadState) -> io::Result<String> {
-    let sum: usize = state.bytes_per_second.iter().sum();
-    let len = state.bytes_per_second.len();
-    let speed = if len > 0 {
-        sum / len
-    } else {
-        state.content_len.unwrap_or(0)
-    };
-
-    let total_downloaded = as_bytes_unit(state.total_downloaded);
-    let speed_h = as_throughput_unit(speed);
-    let elapsed = Instant::now().saturating_duration_since(state.start_time);
-    let res: String;
-    match state.content_len {
-        Some(content_len) => {
-            let percent = (state.total_downloaded as f64 / content_len as f64) * 100.;
-            let remaining = content_len - state.total_downloaded;
-
+    let stats = DownloadStats::new(state);
+    let total_downloaded = as_bytes_unit(stats.downloaded);
+    let speed_h = as_throughput_unit(stats.speed);
+    let res = match (stats.total, stats.eta) {
+        (Some(content_len), Some(eta)) => {
+            let percent = (stats.downloaded as f64 / content_len as f64) * 100.;
             let download_size = as_bytes_unit(content_len);
-            let eta = Duration::from_secs(if speed == 0 {
-                0
-            } else {
-                (remaining / speed) as u64
-            });
-            res = format!(
+            format!(
                 "{total_downloaded} / {download_size} ({percent:3.0} %) \
                  {speed_h} in {elapsed} ETA: {eta}",
-                elapsed = format_duration(elapsed),
+                elapsed = format_duration(stats.elapsed),
                 eta = format_duration(eta),
-            );
-        }
-        None => {
-            res = format!(
-                "{total_downloaded} / {speed_h} in {elapsed}",
-                elapsed = format_duration(elapsed),
             )
         }
+        _ => format!(
+            "{total_downloaded} / {speed_h} in {elapsed}",
+            elapsed = format_duration(stats.elapsed),
+        ),
     };
     Ok(res)
 }
@@ -107,11 +205,11 @@ fn as_throughput_unit(size: usize) -> String {
     as_bytes_unit(size) + "/s"
 }
 
-/// Download from a URL.
-#[allow(clippy::result_large_err)]
-pub fn download(url: &str) -> Result<ureq::Response, ureq::Error> {
+/// Builds an HTTP agent for a URL with the given timeout for connecting and
+/// reading, and an additional trusted certificate.
+fn agent(url: &str, timeout: Option<Duration>, cert: Option<&Path>) -> io::Result<ureq::Agent> {
     let mut builder = ureq::AgentBuilder::new();
-    let tls = TlsConnector::builder();
+    let mut tls = TlsConnector::builder();
 
     // Set user agent.
     builder = builder.user_agent(concat!("typst/", env!("CARGO_PKG_VERSION")));
@@ -124,59 +222,192 @@ pub fn download(url: &str) -> Result<ureq::Response, ureq::Error> {
         builder = builder.proxy(proxy);
     }
 
+    if let Some(timeout) = timeout {
+        builder = builder.timeout_connect(timeout).timeout_read(timeout);
+    }
+
     // Configure native TLS.
+    if let Some(cert) = cert {
+        let pem = fs::read(cert)?;
+        let cert = native_tls::Certificate::from_pem(&pem)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        tls.add_root_certificate(cert);
+    }
     let connector = tls
         .build()
-        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        .map_err(io::Error::other)?;
     builder = builder.tls_connector(Arc::new(connector));
 
-    builder.build().get(url).call()
+    Ok(builder.build())
+}
+
+/// Why a file couldn't be fetched from the registry.
+#[derive(Debug)]
+pub enum RegistryError {
+    NotFound,
+    /// Downloads are disabled in the registry configuration.
+    Offline,
+    Failed(EcoString),
+}
+
+impl RegistryError {
+    fn into_package_error(self, spec: &PackageSpec) -> PackageError {
+        match self {
+            Self::NotFound => PackageError::NotFound(spec.clone()),
+            error => PackageError::NetworkFailed(Some(eco_format!("{error}"))),
+        }
+    }
+}
+
+impl Display for RegistryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound => f.write_str("not found in the registry"),
+            Self::Offline => f.write_str(
+                "offline mode is enabled, the package must be installed or downloaded first",
+            ),
+            Self::Failed(error) => f.write_str(error),
+        }
+    }
+}
+
+/// Fetches packages and the package index from the configured registry,
+/// which is either served over HTTP or a local mirror with the same layout.
+#[derive(Debug, Clone)]
+pub struct Registry {
+    config: RegistryConfig,
+    cert: Option<PathBuf>,
+}
+
+impl Registry {
+    pub fn new(config: RegistryConfig, cert: Option<PathBuf>) -> Self {
+        Self { config, cert }
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.config.offline
+    }
+
+    /// Fetches a file by its path within the registry, e.g.
+    /// `preview/index.json`. Failed requests are retried unless the file
+    /// doesn't exist.
+    pub fn fetch(&self, path: &str, progress: &mut dyn Progress) -> Result<Vec<u8>, RegistryError> {
+        if self.config.offline {
+            return Err(RegistryError::Offline);
+        }
+
+        let base = self.config.url.trim_end_matches('/');
+        if let Some(dir) = local_mirror(base)? {
+            return fetch_local(&dir.join(path), progress);
+        }
+
+        let url = format!("{base}/{path}");
+        let timeout = (self.config.timeout > 0).then(|| Duration::from_secs(self.config.timeout));
+        let agent = agent(&url, timeout, self.cert.as_deref())
+            .map_err(|err| RegistryError::Failed(eco_format!("{err}")))?;
+
+        progress.print_start();
+        let mut attempt = 0;
+        loop {
+            let error = match agent.get(&url).call() {
+                Ok(response) => match RemoteReader::from_response(response, progress).download() {
+                    Ok(data) => return Ok(data),
+                    Err(err) => eco_format!("{url}: {err}"),
+                },
+                Err(ureq::Error::Status(404, _)) => return Err(RegistryError::NotFound),
+                Err(ureq::Error::Status(code, _)) if code < 500 => {
+                    return Err(RegistryError::Failed(eco_format!(
+                        "{url}: status code {code}"
+                    )))
+                }
+                Err(err) => eco_format!("{err}"),
+            };
+
+            if attempt >= self.config.retries {
+                return Err(RegistryError::Failed(error));
+            }
+            attempt += 1;
+            info!(
+                "retrying {} ({}/{}): {}",
+                url, attempt, self.config.retries, error
+            );
+            thread::sleep(RETRY_DELAY * attempt);
+        }
+    }
+}
+
+/// The directory of a registry given as a `file://` URL or a path, or `None`
+/// for a registry served over HTTP.
+fn local_mirror(url: &str) -> Result<Option<PathBuf>, RegistryError> {
+    let unsupported = || RegistryError::Failed(eco_format!("unsupported registry URL {url}"));
+    match Url::parse(url) {
+        Ok(parsed) => match parsed.scheme() {
+            "http" | "https" => Ok(None),
+            "file" => parsed.to_file_path().map(Some).map_err(|_| unsupported()),
+            // A Windows path starting with a drive letter, e.g. `C:\mirror`.
+            scheme if scheme.len() == 1 && cfg!(windows) => Ok(Some(PathBuf::from(url))),
+            _ => Err(unsupported()),
+        },
+        Err(url::ParseError::RelativeUrlWithoutBase) => Ok(Some(PathBuf::from(url))),
+        Err(_) => Err(unsupported()),
+    }
+}
+
+fn fetch_local(path: &Path, progress: &mut dyn Progress) -> Result<Vec<u8>, RegistryError> {
+    let start_time = Instant::now();
+    progress.print_start();
+    let data = fs::read(path).map_err(|err| match err.kind() {
+        ErrorKind::NotFound => RegistryError::NotFound,
+        _ => RegistryError::Failed(eco_format!("{}: {err}", path.display())),
+    })?;
+    progress.print_finish(&DownloadState {
+        content_len: Some(data.len()),
+        total_downloaded: data.len(),
+        bytes_per_second: VecDeque::new(),
+        start_time,
+    });
+    Ok(data)
 }
 
 /// A wrapper around [`ureq::Response`] that reads the response body in chunks
-/// over a websocket and displays statistics about its progress.
-///
-/// Downloads will _never_ fail due to statistics failing to print, print errors
-/// are silently ignored.
-struct RemoteReader {
+/// and reports statistics about its progress every second.
+struct RemoteReader<'p> {
     reader: Box<dyn Read + Send + Sync + 'static>,
-    content_len: Option<usize>,
-    total_downloaded: usize,
+    state: DownloadState,
     downloaded_this_sec: usize,
-    downloaded_last_few_secs: VecDeque<usize>,
-    start_time: Instant,
-    last_print: Option<Instant>,
+    last_progress: Option<Instant>,
+    progress: &'p mut dyn Progress,
 }
-impl RemoteReader {
+
+impl<'p> RemoteReader<'p> {
     /// Wraps a [`ureq::Response`] and prepares it for downloading.
     ///
     /// The 'Content-Length' header is used as a size hint for read
     /// optimization, if present.
-    pub fn from_response(response: Response) -> Self {
+    fn from_response(response: Response, progress: &'p mut dyn Progress) -> Self {
         let content_len: Option<usize> = response
             .header("Content-Length")
             .and_then(|header| header.parse().ok());
 
         Self {
             reader: response.into_reader(),
-            content_len,
-            total_downloaded: 0,
+            state: DownloadState {
+                content_len,
+                total_downloaded: 0,
+                bytes_per_second: VecDeque::with_capacity(SPEED_SAMPLES),
+                start_time: Instant::now(),
+            },
             downloaded_this_sec: 0,
-            downloaded_last_few_secs: VecDeque::with_capacity(SPEED_SAMPLES),
-            start_time: Instant::now(),
-            last_print: None,
+            last_progress: None,
+            progress,
         }
     }
 
-    /// Download the bodies content as raw bytes while attempting to print
-    /// download statistics to standard error. Download progress gets displayed
-    /// and updated every second.
-    ///
-    /// These statistics will never prevent a download from completing, errors
-    /// are silently ignored.
-    pub fn download(mut self) -> io::Result<Vec<u8>> {
+    /// Download the bodies content as raw bytes while reporting download
+    /// statistics every second.
+    fn download(mut self) -> io::Result<Vec<u8>> {
         let mut buffer = vec![0; 8192];
-        let mut data = match self.content_len {
+        let mut data = match self.state.content_len {
             Some(content_len) => Vec::with_capacity(content_len),
             None => Vec::with_capacity(8192),
         };
@@ -194,57 +425,49 @@ impl RemoteReader {
 
             data.extend(&buffer[..read]);
 
-            let last_printed = match self.last_print {
-                Some(prev) => prev,
-                None => {
-                    let current_time = Instant::now();
-                    self.last_print = Some(current_time);
-                    current_time
-                }
-            };
-            let elapsed = Instant::now().saturating_duration_since(last_printed);
+            let last_progress = *self.last_progress.get_or_insert_with(Instant::now);
+            let elapsed = Instant::now().saturating_duration_since(last_progress);
 
-            self.total_downloaded += read;
+            self.state.total_downloaded += read;
             self.downloaded_this_sec += read;
 
             if elapsed >= Duration::from_secs(1) {
-                if self.downloaded_last_few_secs.len() == SPEED_SAMPLES {
-                    self.downloaded_last_few_secs.pop_back();
+                if self.state.bytes_per_second.len() == SPEED_SAMPLES {
+                    self.state.bytes_per_second.pop_back();
                 }
 
-                self.downloaded_last_few_secs
+                self.state
+                    .bytes_per_second
                     .push_front(self.downloaded_this_sec);
                 self.downloaded_this_sec = 0;
 
-                self.last_print = Some(Instant::now());
+                self.progress.print_progress(&self.state);
+                self.last_progress = Some(Instant::now());
             }
         }
 
+        self.progress.print_finish(&self.state);
         Ok(data)
     }
 }
 
-/// Download binary data and display its progress.
-#[allow(clippy::result_large_err)]
-pub fn download_with_progress(url: &str) -> Result<Vec<u8>, ureq::Error> {
-    let response = download(url)?;
-    Ok(RemoteReader::from_response(response).download()?)
-}
-/// Download a package over the network.
-pub fn download_package(spec: &PackageSpec, package_dir: &Path) -> PackageResult<()> {
+/// Download a package from the registry and unpack it.
+pub fn download_package(
+    registry: &Registry,
+    spec: &PackageSpec,
+    package_dir: &Path,
+    progress: &mut dyn Progress,
+) -> PackageResult<()> {
     // The `@preview` namespace is the only namespace that supports on-demand
     // fetching.
     assert_eq!(spec.namespace, "preview");
 
-    let url = format!("{HOST}/preview/{}-{}.tar.gz", spec.name, spec.version);
-
     info!("downloading {}-{}", &spec.name, &spec.version);
 
-    let data = match download_with_progress(&url) {
-        Ok(data) => data,
-        Err(ureq::Error::Status(404, _)) => return Err(PackageError::NotFound(spec.clone())),
-        Err(err) => return Err(PackageError::NetworkFailed(Some(eco_format!("{err}")))),
-    };
+    let path = format!("preview/{}-{}.tar.gz", spec.name, spec.version);
+    let data = registry
+        .fetch(&path, progress)
+        .map_err(|err| err.into_package_error(spec))?;
 
     let decompressed = flate2::read::GzDecoder::new(data.as_slice());
     tar::Archive::new(decompressed)
@@ -255,6 +478,42 @@ pub fn download_package(spec: &PackageSpec, package_dir: &Path) -> PackageResult
         })
 }
 
+/// Makes a package available on disk. Packages in the data directory take
+/// precedence over downloaded ones, `@preview` packages are downloaded from
+/// the registry if they are missing.
+pub fn prepare_package(
+    storage: &PackageStorage,
+    registry: &Registry,
+    spec: &PackageSpec,
+    progress: &mut dyn Progress,
+) -> PackageResult<PathBuf> {
+    let subdir = format!("{}/{}/{}", spec.namespace, spec.name, spec.version);
+
+    if let Some(packages_dir) = storage.package_path() {
+        let dir = packages_dir.join(&subdir);
+        if dir.exists() {
+            return Ok(dir);
+        }
+    }
+
+    if let Some(cache_dir) = storage.package_cache_path() {
+        let dir = cache_dir.join(&subdir);
+        if dir.exists() {
+            return Ok(dir);
+        }
+
+        // Download from the registry if it doesn't exist yet.
+        if spec.namespace == "preview" {
+            download_package(registry, spec, &dir, progress)?;
+            if dir.exists() {
+                return Ok(dir);
+            }
+        }
+    }
+
+    Err(PackageError::NotFound(spec.clone()))
+}
+
 /// Returns a new downloader.
 pub fn downloader(cert: Option<PathBuf>) -> Downloader {
     let user_agent = concat!("typst/", env!("CARGO_PKG_VERSION"));
@@ -276,3 +535,299 @@ pub fn storage(
         downloader(cert),
     )
 }
+
+/// Where an installed package is stored.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageLocation {
+    /// The data directory, which holds local packages.
+    Data,
+    /// The cache directory, which holds downloaded packages.
+    Cache,
+}
+
+/// A package version found in one of the package directories.
+#[derive(Serialize, Debug, Clone)]
+pub struct InstalledPackage {
+    pub namespace: String,
+    pub name: String,
+    pub version: String,
+    pub location: PackageLocation,
+    pub path: PathBuf,
+    /// The size of all files of the package in bytes.
+    pub size: u64,
+    /// When a file of the package was last read, as far as the file system
+    /// keeps track of it, otherwise when one was last modified.
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+/// Lists the package versions in the data and cache directories of a storage.
+pub fn installed_packages(storage: &PackageStorage) -> Vec<InstalledPackage> {
+    let dirs = [
+        (storage.package_path(), PackageLocation::Data),
+        (storage.package_cache_path(), PackageLocation::Cache),
+    ];
+
+    let mut packages = vec![];
+    for (dir, location) in dirs {
+        let Some(dir) = dir else { continue };
+        for ([namespace, name, version], path) in package_dirs(dir) {
+            let mut size = 0;
+            let mut last_used = None;
+            for entry in WalkDir::new(&path).into_iter().filter_map(Result::ok) {
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                if metadata.is_file() {
+                    size += metadata.len();
+                }
+                let used = metadata.accessed().or_else(|_| metadata.modified());
+                if let Ok(used) = used {
+                    last_used = last_used.max(Some(DateTime::<Utc>::from(used)));
+                }
+            }
+
+            packages.push(InstalledPackage {
+                namespace,
+                name,
+                version,
+                location,
+                path,
+                size,
+                last_used,
+            });
+        }
+    }
+
+    packages.sort_by_cached_key(|package| {
+        (
+            package.namespace.clone(),
+            package.name.clone(),
+            package.version.parse::<PackageVersion>().ok(),
+        )
+    });
+    packages
+}
+
+/// Lists the package versions in a package directory, which are stored as
+/// `{namespace}/{name}/{version}`.
+pub fn package_dirs(dir: &Path) -> impl Iterator<Item = ([String; 3], PathBuf)> + '_ {
+    WalkDir::new(dir)
+        .min_depth(3)
+        .max_depth(3)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_dir())
+        .filter_map(move |entry| {
+            let path = entry.into_path();
+            let mut components = path
+                .strip_prefix(dir)
+                .ok()?
+                .iter()
+                .map(|component| component.to_string_lossy().into_owned());
+            let parts = [components.next()?, components.next()?, components.next()?];
+            Some((parts, path))
+        })
+}
+
+/// Deletes a package version from the data and cache directories. Returns
+/// the deleted directories, which is empty if the package wasn't found.
+pub fn remove_package(storage: &PackageStorage, spec: &PackageSpec) -> io::Result<Vec<PathBuf>> {
+    let subdir = format!("{}/{}/{}", spec.namespace, spec.name, spec.version);
+    let mut removed = vec![];
+    for dir in [storage.package_path(), storage.package_cache_path()]
+        .into_iter()
+        .flatten()
+    {
+        let dir = dir.join(&subdir);
+        if dir.is_dir() {
+            fs::remove_dir_all(&dir)?;
+            info!("removed package {} from {}", spec, dir.display());
+            removed.push(dir);
+        }
+    }
+    Ok(removed)
+}
+
+/// Deletes all downloaded packages. They are downloaded again when they are
+/// used the next time. Other files of the cache directory, like the cached
+/// package index, are kept. Returns the number of bytes freed.
+pub fn clear_package_cache(storage: &PackageStorage) -> io::Result<u64> {
+    let Some(dir) = storage.package_cache_path() else {
+        return Ok(0);
+    };
+
+    let mut size = 0;
+    for (_, path) in package_dirs(dir).collect::<Vec<_>>() {
+        size += WalkDir::new(&path)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter_map(|entry| entry.metadata().ok())
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+            .sum::<u64>();
+        fs::remove_dir_all(&path)?;
+        // Removes the package's and namespace's directories once they are
+        // empty, which fails harmlessly otherwise.
+        for parent in path.ancestors().skip(1).take(2) {
+            let _ = fs::remove_dir(parent);
+        }
+    }
+    info!("cleared package cache {}", dir.display());
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::{MemoryEventSink, SessionId};
+    use tempfile::TempDir;
+    use typst_kit::download::ProgressSink;
+
+    fn package(dir: &Path, subdir: &str) {
+        let dir = dir.join(subdir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("lib.typ"), "#let x = 1").unwrap();
+    }
+
+    #[test]
+    fn test_remove_package() {
+        let (data, cache) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        package(data.path(), "local/hello/0.1.0");
+        package(cache.path(), "preview/hello/0.1.0");
+        let storage = storage(Some(data.path().into()), Some(cache.path().into()), None);
+
+        let spec: PackageSpec = "@local/hello:0.1.0".parse().unwrap();
+        let removed = remove_package(&storage, &spec).unwrap();
+        assert_eq!(removed, vec![data.path().join("local/hello/0.1.0")]);
+        assert!(remove_package(&storage, &spec).unwrap().is_empty());
+        assert!(cache.path().join("preview/hello/0.1.0").is_dir());
+    }
+
+    #[test]
+    fn test_clear_package_cache_keeps_index() {
+        let cache = TempDir::new().unwrap();
+        package(cache.path(), "preview/hello/0.1.0");
+        package(cache.path(), "preview/hello/0.2.0");
+        fs::write(cache.path().join("preview/index.json"), "[]").unwrap();
+        let storage = storage(None, Some(cache.path().into()), None);
+
+        assert_eq!(clear_package_cache(&storage).unwrap(), 20);
+        assert!(!cache.path().join("preview/hello").exists());
+        assert!(cache.path().join("preview/index.json").is_file());
+        assert_eq!(clear_package_cache(&storage).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_local_mirror() {
+        assert_eq!(local_mirror("https://packages.typst.org").unwrap(), None);
+        assert_eq!(local_mirror("http://localhost:8080").unwrap(), None);
+        assert_eq!(
+            local_mirror("/srv/mirror").unwrap(),
+            Some(PathBuf::from("/srv/mirror"))
+        );
+        assert_eq!(
+            local_mirror("mirror").unwrap(),
+            Some(PathBuf::from("mirror"))
+        );
+        assert!(local_mirror("ftp://example.com/mirror").is_err());
+        #[cfg(unix)]
+        assert_eq!(
+            local_mirror("file:///srv/my%20mirror").unwrap(),
+            Some(PathBuf::from("/srv/my mirror"))
+        );
+    }
+
+    #[test]
+    fn test_registry_fetch_from_local_mirror() {
+        let mirror = TempDir::new().unwrap();
+        fs::create_dir(mirror.path().join("preview")).unwrap();
+        fs::write(mirror.path().join("preview/index.json"), "[]").unwrap();
+        let url = Url::from_directory_path(mirror.path()).unwrap();
+        let mut config = RegistryConfig {
+            url: url.to_string(),
+            timeout: 0,
+            ..Default::default()
+        };
+
+        let registry = Registry::new(config.clone(), None);
+        let index = registry.fetch("preview/index.json", &mut ProgressSink);
+        assert_eq!(index.unwrap(), b"[]");
+        let missing = registry.fetch("preview/missing.tar.gz", &mut ProgressSink);
+        assert!(matches!(missing, Err(RegistryError::NotFound)));
+
+        config.offline = true;
+        let registry = Registry::new(config, None);
+        let offline = registry.fetch("preview/index.json", &mut ProgressSink);
+        assert!(matches!(offline, Err(RegistryError::Offline)));
+    }
+
+    fn download_state(downloaded: usize, total: Option<usize>) -> DownloadState {
+        DownloadState {
+            content_len: total,
+            total_downloaded: downloaded,
+            bytes_per_second: VecDeque::from([1024, 3072]),
+            start_time: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn test_download_stats() {
+        let stats = DownloadStats::new(&download_state(4096, Some(10240)));
+        assert_eq!(stats.speed, 2048);
+        assert_eq!(stats.eta, Some(Duration::from_secs(3)));
+
+        let stats = DownloadStats::new(&download_state(4096, None));
+        assert_eq!(stats.eta, None);
+
+        assert_eq!(as_bytes_unit(512), "512 B");
+        assert_eq!(as_bytes_unit(1536), "  1.5 KiB");
+        assert_eq!(as_throughput_unit(3 * 1024 * 1024), "  3.0 MiB/s");
+        let progress = display_download_progress(&download_state(5120, Some(10240))).unwrap();
+        assert!(progress.starts_with("  5.0 KiB /  10.0 KiB ( 50 %)   2.0 KiB/s in"));
+    }
+
+    #[test]
+    fn test_event_download() {
+        let sink = Arc::new(MemoryEventSink::default());
+        let events = SessionEvents::new(SessionId::new("test"), sink.clone());
+        let spec: PackageSpec = "@preview/hello:0.1.0".parse().unwrap();
+        let states = |sink: &MemoryEventSink| {
+            sink.take()
+                .into_iter()
+                .map(|(_, event)| match event {
+                    ProjectEvent::PackageDownload(event) => event,
+                    event => panic!("unexpected event {event:?}"),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Packages that aren't downloaded don't fail visibly.
+        let mut download = EventDownload::new(&spec, &events);
+        download.fail(&PackageError::NotFound(spec.clone()));
+        assert!(states(&sink).is_empty());
+
+        download.print_start();
+        download.print_progress(&download_state(4096, Some(10240)));
+        download.print_finish(&download_state(10240, Some(10240)));
+        download.fail(&PackageError::NetworkFailed(None));
+        let events = states(&sink);
+        let kinds: Vec<_> = events.iter().map(|event| event.state).collect();
+        assert_eq!(
+            kinds,
+            [
+                PackageDownloadState::Start,
+                PackageDownloadState::Progress,
+                PackageDownloadState::Finish,
+                PackageDownloadState::Error,
+            ]
+        );
+        assert!(events
+            .iter()
+            .all(|event| event.package == "@preview/hello:0.1.0"));
+        assert_eq!(events[0].message, "downloading @preview/hello:0.1.0");
+        assert_eq!((events[1].downloaded, events[1].total), (4096, Some(10240)));
+        assert_eq!(events[1].eta, Some(3));
+        assert_eq!(events[3].total, None);
+    }
+}
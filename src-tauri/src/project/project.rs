@@ -1,15 +1,27 @@
-use super::world::ProjectWorld;
+use super::lock::is_package_lock_file;
+use super::package::HOST;
+use super::world::{ProjectWorld, WorldCreationError};
+use crate::export::{ImageExportOptions, PdfExportOptions, WatchExporter};
+use crate::ide::SymbolIndex;
+use crate::ipc::{TypstDiagnosticSeverity, TypstPage, TypstSourceDiagnostic};
 use chrono::{DateTime, Utc};
-use log::{debug, info};
+use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
+use siphasher::sip128::{Hasher128, SipHasher};
+use std::collections::BTreeMap;
 use std::fmt::{self,Debug, Display, Formatter};
+use std::hash::Hash;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicU64;
 use std::sync::{Mutex, RwLock};
+use std::time::Instant;
 use std::{fs, io};
 use thiserror::Error;
-use typst::diag::{FileError, FileResult};
+use typst::diag::{FileError, FileResult, Severity, SourceDiagnostic};
 use typst::model::Document;
 use typst::syntax::VirtualPath;
+use typst::World;
 
 const PATH_PROJECT_CONFIG_FILE: &str = ".typster/project.json";
 
@@ -18,11 +30,39 @@ pub struct Project {
     pub world: Mutex<ProjectWorld>,
     pub cache: RwLock<ProjectCache>,
     pub config: RwLock<ProjectConfig>,
+    pub symbols: RwLock<SymbolIndex>,
+    pub exporter: Mutex<WatchExporter>,
+    /// Counts the requested watch mode exports, so that only the last of
+    /// several quick requests is carried out.
+    pub export_requests: AtomicU64,
 }
 
 #[derive(Default)]
 pub struct ProjectCache {
     pub document: Option<Document>,
+    /// The errors of the last failed compilation, kept for quick fixes.
+    pub diagnostics: Vec<SourceDiagnostic>,
+}
+
+/// The result of compiling the project for the preview.
+#[derive(Debug, Default)]
+pub struct Compilation {
+    /// The pages of the document, if it compiled.
+    pub pages: Vec<TypstPage>,
+    /// The errors of a failed compilation that are in the compiled file.
+    pub diagnostics: Vec<TypstSourceDiagnostic>,
+    /// Whether the compilation succeeded and the document was cached.
+    pub succeeded: bool,
+}
+
+#[derive(Error, Debug)]
+pub enum CompileError {
+    #[error("unknown project")]
+    NoProject,
+    #[error("no main file is configured")]
+    NoMain,
+    #[error(transparent)]
+    File(#[from] FileError),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Hash)]
@@ -38,6 +78,12 @@ pub struct ProjectConfig {
     pub package_cache_path: Option<PathBuf>,
     pub jobs: Option<usize>,
     pub cert: Option<PathBuf>,
+    #[serde(default)]
+    pub format: FormatConfig,
+    #[serde(default)]
+    pub export: ExportConfig,
+    #[serde(default)]
+    pub registry: RegistryConfig,
 }
 
 /// Which format to use for diagnostics.
@@ -47,6 +93,53 @@ pub enum DiagnosticFormat {
     Short,
 }
 
+/// Options for formatting sources with typstyle.
+#[derive(Serialize, Deserialize, Debug, Clone, Hash)]
+#[serde(default)]
+pub struct FormatConfig {
+    /// The maximum line width the formatter aims for.
+    pub line_width: usize,
+    /// The number of spaces per indentation level.
+    pub indent: usize,
+    /// Whether sources are formatted when written from the editor.
+    pub on_save: bool,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self {
+            line_width: 120,
+            indent: 2,
+            on_save: false,
+        }
+    }
+}
+
+/// Options for exporting the document.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Hash)]
+#[serde(default)]
+pub struct ExportConfig {
+    /// Named PDF export settings, e.g. `print` or `archive`.
+    pub pdf_presets: BTreeMap<String, PdfExportOptions>,
+    pub watch: WatchConfig,
+}
+
+/// Exports that are rewritten after every successful compilation, like with
+/// `typst watch`. Paths are relative to the project root and must stay in it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Hash)]
+#[serde(default)]
+pub struct WatchConfig {
+    pub enabled: bool,
+    /// The PDF file to write.
+    pub pdf: Option<PathBuf>,
+    pub pdf_options: PdfExportOptions,
+    /// The directory to write PNG images of the pages to.
+    pub png: Option<PathBuf>,
+    /// The directory to write SVG images of the pages to.
+    pub svg: Option<PathBuf>,
+    pub image_options: ImageExportOptions,
+}
+
 impl Display for DiagnosticFormat {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
@@ -80,6 +173,7 @@ impl ProjectConfig {
 
     pub fn apply(&self, project: &Project) {
         let mut world = project.world.lock().unwrap();
+        world.set_package_config(self);
         match self.apply_main(project, &mut world) {
             Ok(_) => debug!(
                 "applied main source configuration for project {:?}",
@@ -107,6 +201,34 @@ impl ProjectConfig {
     }
 }
 
+/// Where `@preview` packages are downloaded from.
+#[derive(Serialize, Deserialize, Debug, Clone, Hash)]
+#[serde(default)]
+pub struct RegistryConfig {
+    /// The base URL of the registry, e.g. a company mirror. A `file://` URL
+    /// or a path refers to a local mirror with the same layout.
+    pub url: String,
+    /// Whether downloads are disabled, so that only packages that are already
+    /// installed or downloaded can be used.
+    pub offline: bool,
+    /// The timeout for connecting and for reading, in seconds. Zero disables
+    /// the timeout.
+    pub timeout: u64,
+    /// How often a failed download is retried.
+    pub retries: u32,
+}
+
+impl Default for RegistryConfig {
+    fn default() -> Self {
+        Self {
+            url: HOST.into(),
+            offline: false,
+            timeout: 30,
+            retries: 2,
+        }
+    }
+}
+
 impl Default for ProjectConfig {
     fn default() -> Self {
         Self {
@@ -121,33 +243,167 @@ impl Default for ProjectConfig {
             package_cache_path: None,
             jobs: None,
             cert: None,
+            format: FormatConfig::default(),
+            export: ExportConfig::default(),
+            registry: RegistryConfig::default(),
         }
     }
 }
 
 impl Project {
-    pub fn load_from_path(path: PathBuf) -> Self {
+    /// Loads the project in a directory with its `.typster/project.json`, or
+    /// with `main.typ` as the main file if it has none.
+    pub fn load_from_path(path: PathBuf) -> Result<Self, WorldCreationError> {
         let path = fs::canonicalize(&path).unwrap_or(path);
         let config: ProjectConfig = {
             match ProjectConfig::read_from_file(path.join(PATH_PROJECT_CONFIG_FILE)) {
                 Ok(config) => config,
-                Err(e) => {
-                    let mut config = ProjectConfig::default();
-                    config.input = Some(PathBuf::from("main.typ"));
-                    config.root = Some(path.clone());
-                    config.main = Some(path.join("main.typ"));
-                    config
-                }
+                Err(e) => ProjectConfig {
+                    input: Some(PathBuf::from("main.typ")),
+                    root: Some(path.clone()),
+                    main: Some(path.join("main.typ")),
+                    ..Default::default()
+                },
             }
         };
         info!("the config is: {:#?}", &config);
-        Self {
-            world: Mutex::new(ProjectWorld::new(path.clone(), config.clone()).expect("failed to create project world")),
+        Ok(Self {
+            world: Mutex::new(ProjectWorld::new(path.clone(), config.clone())?),
             cache: RwLock::new(Default::default()),
             config: RwLock::new(config),
+            symbols: RwLock::new(Default::default()),
+            exporter: Mutex::new(Default::default()),
+            export_requests: AtomicU64::new(0),
             root: path,
+        })
+    }
+
+    /// Picks up the new contents of a changed file, which is either the
+    /// project configuration, the package lock or a file the document may
+    /// depend on.
+    pub fn reload_file(&self, path: &Path) {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return;
+        };
+        if is_project_config_file(relative) {
+            if let Ok(config) = ProjectConfig::read_from_file(path) {
+                debug!("updating project config for {:?}: {:?}", self, config);
+                let mut config_write = self.config.write().unwrap();
+                *config_write = config;
+                config_write.apply(self);
+            }
+        } else if is_package_lock_file(relative) {
+            debug!("reloading package lock for {:?}", self);
+            self.world.lock().unwrap().reload_package_lock();
+        } else {
+            let mut world = self.world.lock().unwrap();
+            let path = Path::new("/").join(relative);
+            match world.slot_update(&path, None) {
+                Ok(id) => {
+                    debug!("updated slot for {:?} {:?} in {:?}", path, id, self);
+                }
+                Err(e) => {
+                    warn!("unable to update slot for {:?} in {:?}: {:?}", path, self, e);
+                }
+            }
         }
     }
+
+    /// Updates a file with the editor's content and compiles the document.
+    /// A compiled document is cached for the preview and later queries, the
+    /// errors of a failed compilation are kept for quick fixes.
+    pub fn compile(&self, path: &Path, content: String) -> Result<Compilation, CompileError> {
+        let mut world = self.world.lock().unwrap();
+        let source_id = world.slot_update(path, Some(content.clone()))?;
+
+        if !world.is_main_set() {
+            let config = self.config.read().unwrap();
+            if config.apply_main(self, &mut world).is_err() {
+                debug!("skipped compilation for {:?} (main not set)", self);
+                return Err(CompileError::NoMain);
+            }
+        }
+
+        let now = Instant::now();
+        let mut compilation = Compilation::default();
+        match typst::compile(&*world).output {
+            Ok(doc) => {
+                debug!(
+                    "compilation succeeded for {:?} in {:?} ms",
+                    self,
+                    now.elapsed().as_millis()
+                );
+                for (num, page) in (1..).zip(&doc.pages) {
+                    let mut hasher = SipHasher::new();
+                    page.frame.hash(&mut hasher);
+                    compilation.pages.push(TypstPage {
+                        num,
+                        width: page.frame.width().to_pt(),
+                        height: page.frame.height().to_pt(),
+                        hash: hex::encode(hasher.finish128().as_bytes()),
+                    });
+                }
+                compilation.succeeded = true;
+
+                let mut cache = self.cache.write().unwrap();
+                cache.document = Some(doc);
+                cache.diagnostics.clear();
+            }
+            Err(diagnostics) => {
+                debug!("compilation failed with {:?} diagnostics", &diagnostics);
+                self.cache.write().unwrap().diagnostics = diagnostics.to_vec();
+
+                if let Ok(source) = world.source(source_id) {
+                    compilation.diagnostics = diagnostics
+                        .iter()
+                        .filter(|d| d.span.id() == Some(source_id))
+                        .filter_map(|d| {
+                            let range = source.find(d.span)?.range();
+                            Some(TypstSourceDiagnostic {
+                                pos: get_range_position(&content, range.clone()),
+                                range,
+                                severity: match d.severity {
+                                    Severity::Error => TypstDiagnosticSeverity::Error,
+                                    Severity::Warning => TypstDiagnosticSeverity::Warning,
+                                },
+                                message: d.message.to_string(),
+                                hints: d.hints.iter().map(|hint| hint.to_string()).collect(),
+                            })
+                        })
+                        .collect();
+                }
+            }
+        }
+        Ok(compilation)
+    }
+
+    /// Adds or replaces a PDF export preset and saves the configuration to
+    /// `.typster/project.json`. The configuration is only changed if it could
+    /// be saved.
+    pub fn save_pdf_preset(
+        &self,
+        name: String,
+        options: PdfExportOptions,
+    ) -> Result<(), ProjectConfigError> {
+        let mut config = self.config.write().unwrap();
+        let mut updated = config.clone();
+        updated.export.pdf_presets.insert(name, options);
+
+        let path = self.root.join(PATH_PROJECT_CONFIG_FILE);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        updated.write_to_file(path)?;
+        *config = updated;
+        Ok(())
+    }
+
+    /// Rebuilds the workspace symbol index from the files on disk. Files the
+    /// watcher updated while the project was walked keep their entries.
+    pub fn index_symbols(&self) {
+        let index = SymbolIndex::build(&self.root);
+        self.symbols.write().unwrap().merge(index);
+    }
 }
 
 impl Debug for Project {
@@ -159,3 +415,22 @@ impl Debug for Project {
 pub fn is_project_config_file(relative: &Path) -> bool {
     relative.as_os_str() == PATH_PROJECT_CONFIG_FILE
 }
+
+/// The 1-based line and 0-based column of the start of a range.
+pub fn get_range_position(text: &str, rang: Range<usize>) -> (usize, usize) {
+    let mut ln = 0;
+    let mut cn = 0;
+    let mut total: usize = 0;
+    for line in text.lines() {
+        ln += 1;
+        let row = line.chars().count() + 1;
+
+        if total <= rang.start && rang.start <= total + row {
+            cn = rang.start - total;
+            break;
+        }
+
+        total += row;
+    }
+    (ln, cn)
+}
@@ -0,0 +1,506 @@
+use ecow::{eco_format, EcoString};
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+use toml_edit::{DocumentMut, Value};
+use typst::syntax::is_ident;
+use typst::syntax::package::{PackageInfo, PackageManifest, PackageSpec, PackageVersion};
+use typst_kit::package::PackageStorage;
+use walkdir::WalkDir;
+
+const MANIFEST_FILE: &str = "typst.toml";
+
+#[derive(Error, Debug)]
+pub enum PublishError {
+    #[error("invalid package manifest: {0}")]
+    Manifest(EcoString),
+    #[error("{0} is already published, bump its version or overwrite it")]
+    AlreadyPublished(EcoString),
+    #[error("no package data directory is configured")]
+    NoDataDir,
+    #[error(transparent)]
+    IO(#[from] io::Error),
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VersionBump {
+    Major,
+    Minor,
+    Patch,
+}
+
+impl VersionBump {
+    pub fn apply(self, version: PackageVersion) -> PackageVersion {
+        let PackageVersion {
+            major,
+            minor,
+            patch,
+        } = version;
+        match self {
+            Self::Major => PackageVersion {
+                major: major + 1,
+                minor: 0,
+                patch: 0,
+            },
+            Self::Minor => PackageVersion {
+                major,
+                minor: minor + 1,
+                patch: 0,
+            },
+            Self::Patch => PackageVersion {
+                major,
+                minor,
+                patch: patch + 1,
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PublishOptions {
+    /// Increments the manifest's version before publishing.
+    pub bump: Option<VersionBump>,
+    /// Replaces an installed package of the same version.
+    pub overwrite: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PublishedPackage {
+    pub namespace: EcoString,
+    pub name: EcoString,
+    pub version: PackageVersion,
+    pub path: PathBuf,
+    /// The number of files copied into the package.
+    pub files: usize,
+    /// Whether the project had no manifest and one was created.
+    pub manifest_created: bool,
+}
+
+/// Installs a project into the `@local` namespace of the package data
+/// directory, so that other projects can import it right away.
+///
+/// The project's `typst.toml` is read, or created with the project's
+/// directory name and `entrypoint` if there is none. Hidden files and the
+/// manifest's exclusions aren't copied.
+pub fn publish_local(
+    root: &Path,
+    entrypoint: &Path,
+    storage: &PackageStorage,
+    options: &PublishOptions,
+) -> Result<PublishedPackage, PublishError> {
+    let manifest_path = root.join(MANIFEST_FILE);
+    let (text, created) = match fs::read_to_string(&manifest_path) {
+        Ok(text) => (text, false),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            (default_manifest(root, entrypoint)?, true)
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let mut manifest: PackageManifest =
+        toml::from_str(&text).map_err(|e| PublishError::Manifest(e.message().into()))?;
+    if let Some(bump) = options.bump {
+        manifest.package.version = bump.apply(manifest.package.version);
+    }
+    let exclude = validate_manifest(root, &manifest)?;
+
+    let spec = PackageSpec {
+        namespace: "local".into(),
+        name: manifest.package.name.clone(),
+        version: manifest.package.version,
+    };
+    let dir = storage
+        .package_path()
+        .ok_or(PublishError::NoDataDir)?
+        .join(spec.namespace.as_str())
+        .join(spec.name.as_str())
+        .join(spec.version.to_string());
+    if dir.exists() && !options.overwrite {
+        return Err(PublishError::AlreadyPublished(eco_format!("{spec}")));
+    }
+
+    // A new or bumped manifest is written to the project only once the
+    // package is published, so that a failure leaves the project as it was.
+    let new_manifest = if created || options.bump.is_some() {
+        Some(set_version(&text, manifest.package.version)?)
+    } else {
+        None
+    };
+
+    // The package is copied next to its final place and then moved there, so
+    // that a failed copy doesn't leave a broken package behind.
+    let parent = dir.parent().unwrap_or(&dir);
+    fs::create_dir_all(parent)?;
+    let staging = parent.join(format!(".{}-{}.new", spec.version, std::process::id()));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    let files = match stage_package(root, &exclude, &staging, new_manifest.as_deref()) {
+        Ok(files) => files,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e.into());
+        }
+    };
+    replace_dir(&staging, &dir)?;
+    if let Some(new_manifest) = new_manifest {
+        fs::write(&manifest_path, new_manifest)?;
+    }
+
+    Ok(PublishedPackage {
+        namespace: spec.namespace,
+        name: spec.name,
+        version: spec.version,
+        path: dir,
+        files,
+        manifest_created: created,
+    })
+}
+
+/// Copies a package to the staging directory, with a new manifest in place
+/// of the project's one if there is one. Returns the number of files.
+fn stage_package(
+    root: &Path,
+    exclude: &[Pattern],
+    to: &Path,
+    manifest: Option<&str>,
+) -> io::Result<usize> {
+    let mut files = copy_package(root, exclude, to)?;
+    if let Some(manifest) = manifest {
+        let path = to.join(MANIFEST_FILE);
+        if !path.exists() {
+            files += 1;
+        }
+        fs::write(path, manifest)?;
+    }
+    Ok(files)
+}
+
+/// Copies the files of a package that aren't hidden or excluded. Returns the
+/// number of files.
+fn copy_package(root: &Path, exclude: &[Pattern], to: &Path) -> io::Result<usize> {
+    let mut files = 0;
+    let entries = WalkDir::new(root)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+            entry.depth() == 0 || !(is_hidden(relative) || is_excluded(relative, exclude))
+        });
+    for entry in entries {
+        let entry = entry?;
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(to.join(relative))?;
+        } else if entry.file_type().is_file() {
+            fs::copy(entry.path(), to.join(relative))?;
+            files += 1;
+        }
+    }
+    Ok(files)
+}
+
+/// Moves a directory to a place that may hold an older copy. The older copy
+/// is moved aside first and only removed once the new one is in place.
+fn replace_dir(from: &Path, to: &Path) -> io::Result<()> {
+    if !to.exists() {
+        return fs::rename(from, to);
+    }
+    let old = from.with_extension("old");
+    if old.exists() {
+        fs::remove_dir_all(&old)?;
+    }
+    fs::rename(to, &old)?;
+    if let Err(e) = fs::rename(from, to) {
+        let _ = fs::rename(&old, to);
+        let _ = fs::remove_dir_all(from);
+        return Err(e);
+    }
+    fs::remove_dir_all(old)
+}
+
+/// A manifest named after the project's directory, at version 0.1.0.
+fn default_manifest(root: &Path, entrypoint: &Path) -> Result<String, PublishError> {
+    let dir_name = root
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let name: String = dir_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let name = name.trim_matches('-');
+    if !is_ident(name) {
+        return Err(PublishError::Manifest(eco_format!(
+            "no package name can be derived from `{dir_name}`, create a {MANIFEST_FILE}"
+        )));
+    }
+
+    let entrypoint: Vec<_> = entrypoint
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    Ok(format!(
+        "[package]\nname = {:?}\nversion = \"0.1.0\"\nentrypoint = {:?}\n",
+        name,
+        entrypoint.join("/"),
+    ))
+}
+
+/// Checks what a package needs to be imported and returns its exclusions.
+fn validate_manifest(
+    root: &Path,
+    manifest: &PackageManifest,
+) -> Result<Vec<Pattern>, PublishError> {
+    let invalid = |message: EcoString| Err(PublishError::Manifest(message));
+    let PackageInfo {
+        name,
+        version,
+        entrypoint,
+        exclude,
+        unknown_fields,
+        ..
+    } = &manifest.package;
+
+    if let Some(field) = manifest.unknown_fields.keys().next() {
+        return invalid(eco_format!("unknown section `{field}`"));
+    }
+    if let Some(field) = unknown_fields.keys().next() {
+        return invalid(eco_format!("unknown field `package.{field}`"));
+    }
+    if !is_ident(name) {
+        return invalid(eco_format!("`{name}` is not a valid package name"));
+    }
+    let spec = PackageSpec {
+        namespace: "local".into(),
+        name: name.clone(),
+        version: *version,
+    };
+    manifest.validate(&spec).map_err(PublishError::Manifest)?;
+
+    let exclude = exclude
+        .iter()
+        .map(|pattern| {
+            let normalized = pattern.trim_start_matches("./").trim_end_matches('/');
+            Pattern::new(normalized).map_err(|e| {
+                PublishError::Manifest(eco_format!("invalid exclusion `{pattern}` ({e})"))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut required = vec![("entrypoint", entrypoint.clone())];
+    if let Some(template) = &manifest.template {
+        required.push(("template.path", template.path.clone()));
+        required.push((
+            "template.entrypoint",
+            eco_format!("{}/{}", template.path, template.entrypoint),
+        ));
+        if let Some(thumbnail) = &template.thumbnail {
+            required.push(("template.thumbnail", thumbnail.clone()));
+        }
+    }
+    for (field, path) in required {
+        let relative = Path::new(path.as_str());
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return invalid(eco_format!("`{field}` must be within the package"));
+        }
+        if !root.join(relative).exists() {
+            return invalid(eco_format!("`{field}` {path} does not exist"));
+        }
+        if is_hidden(relative) || is_excluded(relative, &exclude) {
+            return invalid(eco_format!("`{field}` {path} is excluded"));
+        }
+    }
+    Ok(exclude)
+}
+
+/// Replaces the version in the manifest's `[package]` section, so that the
+/// formatting and comments of the manifest are kept.
+fn set_version(text: &str, version: PackageVersion) -> Result<String, PublishError> {
+    let mut document: DocumentMut = text
+        .parse()
+        .map_err(|e: toml_edit::TomlError| PublishError::Manifest(e.message().into()))?;
+    let current = document
+        .get_mut("package")
+        .and_then(|package| package.as_table_like_mut())
+        .and_then(|package| package.get_mut("version"))
+        .and_then(|version| version.as_value_mut())
+        .filter(|version| version.is_str())
+        .ok_or_else(|| {
+            PublishError::Manifest(
+                "the version of `[package]` can't be updated, change it by hand".into(),
+            )
+        })?;
+
+    let mut updated = Value::from(version.to_string());
+    *updated.decor_mut() = current.decor().clone();
+    *current = updated;
+    Ok(document.to_string())
+}
+
+fn is_hidden(relative: &Path) -> bool {
+    relative
+        .components()
+        .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+}
+
+/// Whether a path relative to the package root matches an exclusion. Patterns
+/// without a `/` also match file names in subdirectories, e.g. `*.pdf`.
+fn is_excluded(relative: &Path, exclude: &[Pattern]) -> bool {
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+    let path: Vec<_> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    let name = path.last().map(AsRef::as_ref).unwrap_or_default();
+    let path = path.join("/");
+    exclude.iter().any(|pattern| {
+        pattern.matches_with(&path, options)
+            || (!pattern.as_str().contains('/') && pattern.matches_with(name, options))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::storage;
+    use std::str::FromStr;
+    use tempfile::TempDir;
+
+    fn version(text: &str) -> PackageVersion {
+        PackageVersion::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn test_version_bump() {
+        let current = version("1.2.3");
+        assert_eq!(VersionBump::Major.apply(current), version("2.0.0"));
+        assert_eq!(VersionBump::Minor.apply(current), version("1.3.0"));
+        assert_eq!(VersionBump::Patch.apply(current), version("1.2.4"));
+    }
+
+    #[test]
+    fn test_set_version() {
+        let text = "# The package.\n[package]\nname = \"report\"\n\
+                    version   = \"0.1.0\" # bumped on publish\nentrypoint = \"lib.typ\"\n\n\
+                    [tool.other]\nversion = \"9.9.9\"\n";
+        assert_eq!(
+            set_version(text, version("0.2.0")).unwrap(),
+            text.replace("\"0.1.0\"", "\"0.2.0\"")
+        );
+
+        let inline = "package = { name = \"report\", version = '0.1.0' }\n";
+        assert_eq!(
+            set_version(inline, version("1.0.0")).unwrap(),
+            "package = { name = \"report\", version = \"1.0.0\" }\n"
+        );
+
+        assert!(set_version("[package]\nname = \"report\"\n", version("1.0.0")).is_err());
+        assert!(set_version("[package\n", version("1.0.0")).is_err());
+    }
+
+    #[test]
+    fn test_is_excluded() {
+        let exclude: Vec<Pattern> = ["*.pdf", "docs/*", "build"]
+            .iter()
+            .map(|pattern| Pattern::new(pattern).unwrap())
+            .collect();
+        assert!(is_excluded(Path::new("manual.pdf"), &exclude));
+        assert!(is_excluded(Path::new("examples/out.pdf"), &exclude));
+        assert!(is_excluded(Path::new("docs/guide.typ"), &exclude));
+        assert!(!is_excluded(Path::new("src/docs/guide.typ"), &exclude));
+        assert!(is_excluded(Path::new("src/build"), &exclude));
+        assert!(!is_excluded(Path::new("lib.typ"), &exclude));
+    }
+
+    #[test]
+    fn test_publish_local() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("my-report");
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join("lib.typ"), "#let x = 1").unwrap();
+        fs::write(root.join("draft.pdf"), "").unwrap();
+        fs::write(root.join(".git/HEAD"), "").unwrap();
+        let storage = storage(Some(dir.path().join("data")), None, None);
+
+        let published =
+            publish_local(&root, Path::new("lib.typ"), &storage, &Default::default()).unwrap();
+        assert!(published.manifest_created);
+        assert_eq!(published.name, "my-report");
+        assert_eq!(published.version, version("0.1.0"));
+        assert_eq!(published.files, 3);
+        assert!(published.path.join("lib.typ").is_file());
+        assert!(!published.path.join(".git").exists());
+
+        assert!(matches!(
+            publish_local(&root, Path::new("lib.typ"), &storage, &Default::default()),
+            Err(PublishError::AlreadyPublished(_))
+        ));
+
+        // Overwriting replaces the whole package.
+        let manifest = fs::read_to_string(root.join(MANIFEST_FILE)).unwrap();
+        fs::write(
+            root.join(MANIFEST_FILE),
+            manifest + "exclude = [\"*.pdf\"]\n",
+        )
+        .unwrap();
+        let options = PublishOptions {
+            overwrite: true,
+            ..Default::default()
+        };
+        let published = publish_local(&root, Path::new("lib.typ"), &storage, &options).unwrap();
+        assert_eq!(published.files, 2);
+        assert!(!published.path.join("draft.pdf").exists());
+        let siblings = fs::read_dir(published.path.parent().unwrap()).unwrap();
+        assert_eq!(siblings.count(), 1);
+
+        let options = PublishOptions {
+            bump: Some(VersionBump::Minor),
+            ..Default::default()
+        };
+        let published = publish_local(&root, Path::new("lib.typ"), &storage, &options).unwrap();
+        assert_eq!(published.version, version("0.2.0"));
+        let manifest = fs::read_to_string(root.join(MANIFEST_FILE)).unwrap();
+        assert!(manifest.contains("version = \"0.2.0\""));
+        assert!(manifest.contains("exclude = [\"*.pdf\"]"));
+        let published_manifest = fs::read_to_string(published.path.join(MANIFEST_FILE)).unwrap();
+        assert_eq!(published_manifest, manifest);
+    }
+
+    #[test]
+    fn test_publish_local_failure_keeps_manifest() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("my-report");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("lib.typ"), "#let x = 1").unwrap();
+        // The package directory can't be created below a file.
+        fs::write(dir.path().join("data"), "").unwrap();
+        let storage = storage(Some(dir.path().join("data")), None, None);
+
+        assert!(publish_local(&root, Path::new("lib.typ"), &storage, &Default::default()).is_err());
+        assert!(!root.join(MANIFEST_FILE).exists());
+
+        let manifest =
+            "[package]\nname = \"my-report\"\nversion = \"0.1.0\"\nentrypoint = \"lib.typ\"\n";
+        fs::write(root.join(MANIFEST_FILE), manifest).unwrap();
+        let options = PublishOptions {
+            bump: Some(VersionBump::Patch),
+            ..Default::default()
+        };
+        assert!(publish_local(&root, Path::new("lib.typ"), &storage, &options).is_err());
+        assert_eq!(
+            fs::read_to_string(root.join(MANIFEST_FILE)).unwrap(),
+            manifest
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use crate::ipc::{
+    BatchExportProgressEvent, FSRefreshEvent, PackageDownloadEvent, ProjectChangeEvent,
+    WatchExportEvent,
+};
+use serde::Serialize;
+use std::fmt::{self, Display, Formatter};
+use std::sync::{Arc, Mutex};
+
+/// Identifies a client that has a project open, e.g. an app window.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SessionId(String);
+
+impl SessionId {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for SessionId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// An event sent from a project to the client of a session. It serializes to
+/// its payload alone, the kind of event is given by its name.
+#[derive(Serialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum ProjectEvent {
+    ProjectChanged(ProjectChangeEvent),
+    FsRefresh(FSRefreshEvent),
+    WatchExport(WatchExportEvent),
+    BatchExportProgress(BatchExportProgressEvent),
+    PackageDownload(PackageDownloadEvent),
+}
+
+impl ProjectEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ProjectChanged(_) => "project_changed",
+            Self::FsRefresh(_) => "fs_refresh",
+            Self::WatchExport(_) => "watch_export",
+            Self::BatchExportProgress(_) => "batch_export_progress",
+            Self::PackageDownload(_) => "package_download",
+        }
+    }
+}
+
+/// Delivers project events to the clients of sessions.
+pub trait EventSink: Send + Sync {
+    fn emit(&self, session: &SessionId, event: ProjectEvent);
+}
+
+/// Sends events to the client of a single session, for code that doesn't
+/// know about sessions, e.g. the world while it downloads packages.
+#[derive(Clone)]
+pub struct SessionEvents {
+    session: SessionId,
+    sink: Arc<dyn EventSink>,
+}
+
+impl SessionEvents {
+    pub fn new(session: SessionId, sink: Arc<dyn EventSink>) -> Self {
+        Self { session, sink }
+    }
+
+    pub fn emit(&self, event: ProjectEvent) {
+        self.sink.emit(&self.session, event);
+    }
+}
+
+/// Keeps events in memory, so that projects can be driven without a client,
+/// e.g. in tests.
+#[derive(Default)]
+pub struct MemoryEventSink {
+    events: Mutex<Vec<(SessionId, ProjectEvent)>>,
+}
+
+impl MemoryEventSink {
+    /// Removes and returns the events received so far.
+    pub fn take(&self) -> Vec<(SessionId, ProjectEvent)> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+}
+
+impl EventSink for MemoryEventSink {
+    fn emit(&self, session: &SessionId, event: ProjectEvent) {
+        self.events.lock().unwrap().push((session.clone(), event));
+    }
+}
@@ -1,6 +1,6 @@
 use chrono::{DateTime, Datelike, FixedOffset, Local, Utc};
-use log::{debug, info};
-use parking_lot::{Mutex, RwLock};
+use log::{debug, info, warn};
+use parking_lot::Mutex;
 use std::cell::{OnceCell, RefCell, RefMut};
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
@@ -8,9 +8,8 @@ use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, LazyLock, OnceLock};
 use std::{fmt, fs, io, mem};
-use typst::diag::{FileError, FileResult, PackageError, PackageResult};
-use typst::foundations::{Bytes, Datetime};
-use typst::layout::Frame;
+use typst::diag::{eco_format, EcoString, FileError, FileResult, PackageError, PackageResult};
+use typst::foundations::{Bytes, Datetime, Dict};
 use typst::syntax::package::PackageSpec;
 use typst::syntax::{FileId, Source, VirtualPath};
 use typst::text::{Font, FontBook};
@@ -20,9 +19,12 @@ use typst_kit::fonts::{FontSearcher, FontSlot, Fonts};
 use typst_kit::package::PackageStorage;
 use typst_timing::timed;
 
-use super::package::{self, PrintDownload};
+use super::package::{self, EventDownload, PrintDownload, Registry};
 
-use super::{download_package, ProjectConfig};
+use super::{
+    index_cache_path, package_completions, package_hash, vendor_package, vendored_package,
+    PackageIndex, PackageLock, ProjectConfig, SessionEvents, UsedPackage,
+};
 
 
 
@@ -35,6 +37,8 @@ pub enum WorldCreationError {
     InputOutsideRoot,
     /// The root directory does not appear to exist.
     RootNotFound(PathBuf),
+    /// The configuration names no main file.
+    NoInput,
     /// Another type of I/O error.
     Io(io::Error),
 }
@@ -54,11 +58,14 @@ impl fmt::Display for WorldCreationError {
                     path.display()
                 )
             }
+            WorldCreationError::NoInput => write!(f, "no main file is configured"),
             WorldCreationError::Io(err) => write!(f, "{err}"),
         }
     }
 }
 
+impl std::error::Error for WorldCreationError {}
+
 /// Lazily processes data for a file.
 struct SlotCell<T> {
     /// The processed data.
@@ -110,14 +117,10 @@ impl FileSlot {
     }
 
     /// Retrieve the source for this file.
-    fn source(
-        &mut self,
-        project_root: &Path,
-        package_storage: &PackageStorage,
-    ) -> FileResult<Source> {
+    fn source(&mut self, world: &ProjectWorld) -> FileResult<Source> {
         info!("fn source: {:?}", self.id.vpath());
         self.source.get_or_init(
-            || read(self.id, project_root, package_storage),
+            || read(self.id, world),
             |data, prev| {
                 let text = decode_utf8(&data)?;
                 if let Some(mut prev) = prev {
@@ -131,10 +134,10 @@ impl FileSlot {
     }
 
     /// Retrieve the file's bytes.
-    fn file(&mut self, project_root: &Path, package_storage: &PackageStorage) -> FileResult<Bytes> {
+    fn file(&mut self, world: &ProjectWorld) -> FileResult<Bytes> {
         info!("fn file: {:?}", self.id.vpath());
         self.file.get_or_init(
-            || read(self.id, project_root, package_storage),
+            || read(self.id, world),
             |data, _| Ok(data.into()),
         )
     }
@@ -150,17 +153,13 @@ fn decode_utf8(buf: &[u8]) -> FileResult<&str> {
 
 /// Resolves the path of a file id on the system, downloading a package if
 /// necessary.
-fn system_path(
-    project_root: &Path,
-    id: FileId,
-    package_storage: &PackageStorage,
-) -> FileResult<PathBuf> {
+fn system_path(id: FileId, world: &ProjectWorld) -> FileResult<PathBuf> {
     // Determine the root path relative to which the file path
     // will be resolved.
     let buf;
-    let mut root = project_root;
+    let mut root = world.root.as_path();
     if let Some(spec) = id.package() {
-        buf = package_storage.prepare_package(spec, &mut PrintDownload(&spec))?;
+        buf = world.fetch_package(spec)?;
         root = &buf;
     }
     info!("system_path: {:?}", root, );
@@ -174,9 +173,9 @@ fn system_path(
 ///
 /// If the ID represents stdin it will read from standard input,
 /// otherwise it gets the file path of the ID and reads the file from disk.
-fn read(id: FileId, project_root: &Path, package_storage: &PackageStorage) -> FileResult<Vec<u8>> {
-    info!("read file: {}", project_root.display());
-    read_from_disk(&system_path(project_root, id, package_storage)?)
+fn read(id: FileId, world: &ProjectWorld) -> FileResult<Vec<u8>> {
+    info!("read file: {}", world.root.display());
+    read_from_disk(&system_path(id, world)?)
     
 }
 
@@ -277,19 +276,29 @@ pub struct ProjectWorld {
     slots: Mutex<HashMap<FileId, FileSlot>>,
     /// Holds information about where packages are stored.
     package_storage: PackageStorage,
+    /// Where packages are downloaded from.
+    registry: Registry,
+    /// Receives the progress of package downloads.
+    events: Option<SessionEvents>,
+    /// The registry's packages, as far as they are known.
+    package_index: Option<Arc<PackageIndex>>,
+    /// The packages offered when completing imports.
+    packages: Vec<(PackageSpec, Option<EcoString>)>,
+    /// The hashes packages are verified against.
+    package_lock: Mutex<PackageLock>,
+    /// The packages resolved so far, i.e. the ones in use.
+    used_packages: Mutex<HashMap<PackageSpec, UsedPackage>>,
     /// The current datetime if requested. This is stored here to ensure it is
     /// always the same within one compilation.
     /// Reset between compilations if not [`Now::Fixed`].
     now: Now,
-    /// The export cache, used for caching output files in `typst watch`
-    /// sessions.
-    export_cache: ExportCache,
 }
 
 impl ProjectWorld {
     pub fn new(root: PathBuf, config: ProjectConfig) -> Result<Self, WorldCreationError> {
-        let main_path = VirtualPath::within_root(&config.main.expect("input is required"), &root)
-            .ok_or(WorldCreationError::InputOutsideRoot)?;
+        let main = config.main.ok_or(WorldCreationError::NoInput)?;
+        let main_path =
+            VirtualPath::within_root(&main, &root).ok_or(WorldCreationError::InputOutsideRoot)?;
          info!("main_path: {:?} root: {:?} ", main_path, root);
         let main: FileId = FileId::new(None, main_path);
 
@@ -297,6 +306,14 @@ impl ProjectWorld {
 
         let now = Now::System(OnceLock::new());
         let fonts = Fonts::searcher().search();
+        let registry = Registry::new(config.registry, config.cert.clone());
+        let package_storage =
+            package::storage(config.package_path, config.package_cache_path, config.cert);
+        let package_index = index_cache_path(&package_storage)
+            .and_then(|path| PackageIndex::cached(&path))
+            .map(Arc::new);
+        let packages = package_completions(&package_storage, package_index.as_deref());
+        let package_lock = PackageLock::read(&root);
 
         Ok(Self {
             workdir: Some(root.clone()),
@@ -306,13 +323,14 @@ impl ProjectWorld {
             book: LazyHash::new(fonts.book),
             fonts: fonts.fonts,
             slots: Mutex::new(HashMap::new()),
-            package_storage: package::storage(
-                config.package_path,
-                config.package_cache_path,
-                config.cert,
-            ),
+            package_storage,
+            registry,
+            events: None,
+            package_index,
+            packages,
+            package_lock: Mutex::new(package_lock),
+            used_packages: Mutex::new(HashMap::new()),
             now,
-            export_cache: ExportCache::new(),
         })
     }
     pub fn slot_update<P: AsRef<Path>>(
@@ -364,13 +382,235 @@ impl ProjectWorld {
     }
 
     pub fn set_main(&mut self, id: FileId) {
-        self.main = id.clone();
+        self.main = id;
     }
 
     pub fn set_main_path(&mut self, main: VirtualPath) {
         self.set_main(FileId::new(None, main))
     }
 
+    pub fn package_storage(&self) -> &PackageStorage {
+        &self.package_storage
+    }
+
+    pub fn package_index(&self) -> Option<Arc<PackageIndex>> {
+        self.package_index.clone()
+    }
+
+    /// Replaces the index used to complete imports of registry packages.
+    pub fn set_package_index(&mut self, index: Arc<PackageIndex>) {
+        self.packages = package_completions(&self.package_storage, Some(&index));
+        self.package_index = Some(index);
+    }
+
+    /// Lists the local packages again for import completions, e.g. after one
+    /// was published.
+    pub fn refresh_package_completions(&mut self) {
+        self.packages = package_completions(&self.package_storage, self.package_index.as_deref());
+    }
+
+    /// Applies the package settings of a changed configuration. Packages
+    /// that were read from a directory that is no longer configured are
+    /// fetched again.
+    pub fn set_package_config(&mut self, config: &ProjectConfig) {
+        self.registry = Registry::new(config.registry.clone(), config.cert.clone());
+        let storage = package::storage(
+            config.package_path.clone(),
+            config.package_cache_path.clone(),
+            config.cert.clone(),
+        );
+        let dirs = |storage: &PackageStorage| {
+            [storage.package_path(), storage.package_cache_path()].map(|dir| dir.map(PathBuf::from))
+        };
+        let (old, new) = (dirs(&self.package_storage), dirs(&storage));
+        self.package_storage = storage;
+        if old != new {
+            self.forget_packages_in(&old.into_iter().flatten().collect::<Vec<_>>());
+            self.refresh_package_completions();
+        }
+    }
+
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Makes a package available on disk, downloading it if necessary, e.g.
+    /// ahead of time before going offline. A vendored copy in the project
+    /// takes precedence. The download progress is sent to the session's
+    /// client if there is one, and printed otherwise.
+    ///
+    /// The package is verified against the project's lock file, a package
+    /// that isn't locked yet is added to it.
+    pub fn fetch_package(&self, spec: &PackageSpec) -> PackageResult<PathBuf> {
+        if let Some(used) = self.used_packages.lock().get(spec) {
+            return Ok(used.path.clone());
+        }
+
+        let vendored = vendored_package(&self.root, spec);
+        let path = match vendored.clone() {
+            Some(path) => path,
+            None => self.prepare_package(spec)?,
+        };
+        let hash = package_hash(&path)
+            .map_err(|e| PackageError::Other(Some(eco_format!("failed to read {spec} ({e})"))))?;
+        self.lock_package(spec, &hash)?;
+
+        let used = UsedPackage {
+            namespace: spec.namespace.clone(),
+            name: spec.name.clone(),
+            version: spec.version,
+            path: path.clone(),
+            hash,
+            vendored: vendored.is_some(),
+        };
+        self.used_packages.lock().insert(spec.clone(), used);
+        Ok(path)
+    }
+
+    fn prepare_package(&self, spec: &PackageSpec) -> PackageResult<PathBuf> {
+        let storage = &self.package_storage;
+        match &self.events {
+            Some(events) => {
+                let mut progress = EventDownload::new(spec, events);
+                let result = package::prepare_package(storage, &self.registry, spec, &mut progress);
+                if let Err(e) = &result {
+                    progress.fail(e);
+                }
+                result
+            }
+            None => {
+                let mut progress = PrintDownload(spec);
+                package::prepare_package(storage, &self.registry, spec, &mut progress)
+            }
+        }
+    }
+
+    /// Checks a package's hash against the lock file. Packages of the
+    /// `@local` namespace are the user's own and may change, so their entry
+    /// is updated instead.
+    fn lock_package(&self, spec: &PackageSpec, hash: &str) -> PackageResult<()> {
+        let mut lock = self.package_lock.lock();
+        match lock.get(spec) {
+            Some(locked) if locked.hash == hash => return Ok(()),
+            Some(_) if spec.namespace != "local" => {
+                return Err(PackageError::Other(Some(eco_format!(
+                    "{spec} does not match the hash in the package lock, \
+                     remove its entry to accept the change"
+                ))));
+            }
+            _ => lock.insert(spec, hash.into()),
+        }
+        if let Err(e) = lock.write(&self.root) {
+            warn!("unable to write the package lock: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Forgets the packages stored in one of the given directories after they
+    /// were deleted, so that they are fetched again by the next compilation
+    /// instead of being read from where they used to be.
+    pub fn forget_packages_in(&self, dirs: &[PathBuf]) {
+        let mut used_packages = self.used_packages.lock();
+        let forgotten: Vec<_> = used_packages
+            .iter()
+            .filter(|(_, used)| dirs.iter().any(|dir| used.path.starts_with(dir)))
+            .map(|(spec, _)| spec.clone())
+            .collect();
+        if forgotten.is_empty() {
+            return;
+        }
+        for spec in &forgotten {
+            used_packages.remove(spec);
+        }
+        self.slots
+            .lock()
+            .retain(|id, _| !id.package().is_some_and(|spec| forgotten.contains(spec)));
+    }
+
+    /// The packages resolved while compiling, sorted by their spec.
+    pub fn used_packages(&self) -> Vec<UsedPackage> {
+        let mut packages: Vec<_> = self.used_packages.lock().values().cloned().collect();
+        packages.sort_by(|a, b| {
+            (&a.namespace, &a.name, a.version).cmp(&(&b.namespace, &b.name, b.version))
+        });
+        packages
+    }
+
+    /// Reads the lock file again after it changed on disk. The packages in use
+    /// are checked against it and added to it if their entry was removed.
+    pub fn reload_package_lock(&self) {
+        *self.package_lock.lock() = PackageLock::read(&self.root);
+        for (spec, used) in self.used_packages.lock().iter() {
+            if let Err(e) = self.lock_package(spec, &used.hash) {
+                warn!("{}", e);
+            }
+        }
+    }
+
+    /// Copies the packages in use into the project, so that it compiles
+    /// without the package directories. If any package changed since it was
+    /// used, nothing is copied.
+    ///
+    /// The packages are copied from a snapshot, so that compilations can
+    /// resolve packages in the meantime.
+    pub fn vendor_packages(&self) -> io::Result<Vec<UsedPackage>> {
+        let packages: Vec<(PackageSpec, UsedPackage)> = self
+            .used_packages
+            .lock()
+            .iter()
+            .map(|(spec, used)| (spec.clone(), used.clone()))
+            .collect();
+        for (spec, used) in &packages {
+            if package_hash(&used.path)? != used.hash {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{spec} changed since it was used"),
+                ));
+            }
+        }
+
+        for (spec, used) in packages {
+            let path = vendor_package(&self.root, &spec, &used.path)?;
+            if let Some(current) = self.used_packages.lock().get_mut(&spec) {
+                current.path = path;
+                current.vendored = true;
+            }
+        }
+        Ok(self.used_packages())
+    }
+
+    /// Sets where the progress of package downloads is sent.
+    pub fn set_events(&mut self, events: Option<SessionEvents>) {
+        self.events = events;
+    }
+
+    /// Replaces the values documents read from `sys.inputs`.
+    pub fn set_inputs(&mut self, inputs: Dict) {
+        self.library = LazyHash::new(Library::builder().with_inputs(inputs).build());
+    }
+
+    /// Identifies the contents of all files loaded so far, including unsaved
+    /// edits. Documents compiled from the same revision are the same.
+    pub fn revision(&self) -> u128 {
+        let map = self.slots.lock();
+        let mut hashes: Vec<u128> = map
+            .values()
+            .map(|slot| {
+                let text = match &slot.source.data {
+                    Some(Ok(source)) => Some(source.text()),
+                    _ => None,
+                };
+                let bytes = match &slot.file.data {
+                    Some(Ok(bytes)) => Some(bytes),
+                    _ => None,
+                };
+                typst::utils::hash128(&(slot.id, text, bytes))
+            })
+            .collect();
+        hashes.sort_unstable();
+        typst::utils::hash128(&hashes)
+    }
+
     pub fn is_main_set(&self) -> bool {
         // TODO: Check if the file exists
         true
@@ -410,37 +650,6 @@ impl ProjectWorld {
             .map_err(|e| FileError::from_io(e, &path))
             .map(Bytes::from)
     }
-
-    fn prepare_package(spec: &PackageSpec) -> PackageResult<PathBuf> {
-        let subdir = format!(
-            "typst/packages/{}/{}/{}",
-            spec.namespace, spec.name, spec.version
-        );
-
-        if let Some(data_dir) = dirs::data_dir() {
-            let dir = data_dir.join(&subdir);
-            info!("----package load_from_path: {:?}", &dir);
-            if dir.exists() {
-                return Ok(dir);
-            }
-        }
-
-        if let Some(cache_dir) = dirs::cache_dir() {
-            let dir = cache_dir.join(&subdir);
-            if dir.exists() {
-                return Ok(dir);
-            }
-            // Download from network if it doesn't exist yet.
-            if spec.namespace == "preview" {
-                download_package(spec, &dir)?;
-                if dir.exists() {
-                    return Ok(dir);
-                }
-            }
-        }
-
-        Err(PackageError::NotFound(spec.clone()))
-    }
 }
 
 impl World for ProjectWorld {
@@ -457,11 +666,11 @@ impl World for ProjectWorld {
     }
 
     fn source(&self, id: FileId) -> FileResult<Source> {
-        self.slot(id, |slot| slot.source(&self.root, &self.package_storage))
+        self.slot(id, |slot| slot.source(self))
     }
 
     fn file(&self, id: FileId) -> FileResult<Bytes> {
-        self.slot(id, |slot| slot.file(&self.root, &self.package_storage))
+        self.slot(id, |slot| slot.file(self))
     }
 
     fn font(&self, id: usize) -> Option<Font> {
@@ -489,43 +698,104 @@ impl World for ProjectWorld {
             with_offset.day().try_into().ok()?,
         )
     }
-    fn packages(&self) -> &[(PackageSpec, Option<typst::diag::EcoString>)] {
-        &[]
+    fn packages(&self) -> &[(PackageSpec, Option<EcoString>)] {
+        &self.packages
     }
 }
 
-
-/// Caches exported files so that we can avoid re-exporting them if they haven't
-/// changed.
-///
-/// This is done by having a list of size `files.len()` that contains the hashes
-/// of the last rendered frame in each file. If a new frame is inserted, this
-/// will invalidate the rest of the cache, this is deliberate as to decrease the
-/// complexity and memory usage of such a cache.
-pub struct ExportCache {
-    /// The hashes of last compilation's frames.
-    pub cache: RwLock<Vec<u128>>,
+/// A world for tests with the given files in a temporary project directory,
+/// the first being the main file. The directory lives as long as the
+/// returned [`tempfile::TempDir`].
+#[cfg(test)]
+pub(crate) fn test_world(files: &[(&str, &str)]) -> (tempfile::TempDir, ProjectWorld) {
+    let dir = tempfile::TempDir::new().unwrap();
+    for (name, text) in files {
+        let path = dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+    let config = ProjectConfig {
+        main: Some(dir.path().join(files[0].0)),
+        ..Default::default()
+    };
+    let world = ProjectWorld::new(dir.path().into(), config).unwrap();
+    (dir, world)
 }
 
-impl ExportCache {
-    /// Creates a new export cache.
-    pub fn new() -> Self {
-        Self {
-            cache: RwLock::new(Vec::with_capacity(32)),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::vendor_dir;
+    use std::str::FromStr;
+    use tempfile::TempDir;
+
+    /// A world whose package cache holds the given packages, each with a
+    /// manifest and an entrypoint.
+    fn world(dir: &TempDir, specs: &[&PackageSpec]) -> ProjectWorld {
+        let cache = dir.path().join("cache");
+        for spec in specs {
+            let package = cache
+                .join(spec.namespace.as_str())
+                .join(spec.name.as_str())
+                .join(spec.version.to_string());
+            fs::create_dir_all(&package).unwrap();
+            let manifest = format!(
+                "[package]\nname = \"{}\"\nversion = \"{}\"\nentrypoint = \"lib.typ\"\n",
+                spec.name, spec.version
+            );
+            fs::write(package.join("typst.toml"), manifest).unwrap();
+            fs::write(package.join("lib.typ"), "#let x = 1").unwrap();
         }
-    }
-
-    /// Returns true if the entry is cached and appends the new hash to the
-    /// cache (for the next compilation).
-    pub fn is_cached(&self, i: usize, frame: &Frame) -> bool {
-        let hash = typst::utils::hash128(frame);
 
-        let mut cache = self.cache.upgradable_read();
-        if i >= cache.len() {
-            cache.with_upgraded(|cache| cache.push(hash));
-            return false;
-        }
+        let root = dir.path().join("project");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("main.typ"), "").unwrap();
+        let mut config = ProjectConfig {
+            main: Some(root.join("main.typ")),
+            package_path: Some(dir.path().join("data")),
+            package_cache_path: Some(cache),
+            ..Default::default()
+        };
+        config.registry.offline = true;
+        ProjectWorld::new(root, config).unwrap()
+    }
+
+    #[test]
+    fn test_vendor_packages() {
+        let dir = TempDir::new().unwrap();
+        let a = PackageSpec::from_str("@preview/a:0.1.0").unwrap();
+        let b = PackageSpec::from_str("@preview/b:0.2.0").unwrap();
+        let world = world(&dir, &[&a, &b]);
+        world.fetch_package(&a).unwrap();
+        world.fetch_package(&b).unwrap();
+
+        let used = world.vendor_packages().unwrap();
+        assert_eq!(used.len(), 2);
+        assert!(used.iter().all(|package| package.vendored));
+        assert_eq!(used[0].path, vendor_dir(&world.root, &a));
+        assert_eq!(
+            world.fetch_package(&b).unwrap(),
+            vendor_dir(&world.root, &b)
+        );
+    }
 
-        cache.with_upgraded(|cache| std::mem::replace(&mut cache[i], hash) == hash)
+    #[test]
+    fn test_vendor_packages_checks_all_packages_first() {
+        let dir = TempDir::new().unwrap();
+        let a = PackageSpec::from_str("@preview/a:0.1.0").unwrap();
+        let b = PackageSpec::from_str("@preview/b:0.2.0").unwrap();
+        let world = world(&dir, &[&a, &b]);
+        let path = world.fetch_package(&a).unwrap();
+        world.fetch_package(&b).unwrap();
+
+        fs::write(path.join("lib.typ"), "#let x = 2").unwrap();
+        let error = world.vendor_packages().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(vendored_package(&world.root, &a).is_none());
+        assert!(vendored_package(&world.root, &b).is_none());
+        assert!(world
+            .used_packages()
+            .iter()
+            .all(|package| !package.vendored));
     }
 }
@@ -0,0 +1,55 @@
+// Mirrors the functions of @preview/basic-resume:0.1.4 with a plain layout.
+
+#let resume(author: "", accent-color: black, font: "New Computer Modern", body, ..contact) = {
+  set document(author: author, title: author)
+  set page(paper: "us-letter", margin: 0.5in)
+  set text(font: font, size: 10pt)
+  set list(marker: [-])
+  show heading: set text(fill: rgb(accent-color))
+  align(center, text(size: 20pt, strong(author)))
+  align(center, contact.named().values().join("  |  "))
+  body
+}
+
+#let dates-helper(start-date: "", end-date: "") = start-date + " " + sym.dash.en + " " + end-date
+
+#let generic-two-by-two(top-left: "", top-right: "", bottom-left: "", bottom-right: "") = {
+  grid(
+    columns: (1fr, auto),
+    row-gutter: 0.6em,
+    top-left, top-right,
+    bottom-left, bottom-right,
+  )
+}
+
+#let generic-one-by-two(left: "", right: "") = grid(columns: (1fr, auto), left, right)
+
+#let edu(institution: "", dates: "", degree: "", gpa: "", location: "") = {
+  generic-two-by-two(
+    top-left: strong(institution),
+    top-right: location,
+    bottom-left: emph(degree),
+    bottom-right: emph(dates),
+  )
+}
+
+#let work(title: "", dates: "", company: "", location: "") = {
+  generic-two-by-two(
+    top-left: strong(title),
+    top-right: dates,
+    bottom-left: company,
+    bottom-right: emph(location),
+  )
+}
+
+#let project(role: "", name: "", url: "", dates: "") = {
+  generic-one-by-two(left: [*#role*, #name (#url)], right: dates)
+}
+
+#let certificates(name: "", issuer: "", url: "", date: "") = {
+  [*#name*, #issuer #h(1fr) #date]
+}
+
+#let extracurriculars(activity: "", dates: "") = {
+  generic-one-by-two(left: strong(activity), right: dates)
+}
@@ -0,0 +1,236 @@
+//! Drives the example project in `tests/basic-resume` without the app. Its
+//! template is read from `tests/packages`, so that nothing is downloaded.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use typster_lib::export::PdfExportOptions;
+use typster_lib::project::{
+    MemoryEventSink, Project, ProjectConfig, ProjectEvent, ProjectManager, SessionId,
+};
+
+const MAIN: &str = "/main.typ";
+
+struct Session {
+    /// Keeps the copy of the project until the test ends.
+    _dir: TempDir,
+    root: PathBuf,
+    manager: Arc<ProjectManager>,
+    events: Arc<MemoryEventSink>,
+    id: SessionId,
+}
+
+impl Session {
+    /// Opens a copy of the example project with a configuration, watching it
+    /// for changes like the app does.
+    fn open(configure: impl FnOnce(&mut ProjectConfig)) -> Self {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let example = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/basic-resume");
+        for entry in fs::read_dir(example).unwrap() {
+            let entry = entry.unwrap();
+            fs::copy(entry.path(), root.join(entry.file_name())).unwrap();
+        }
+
+        let mut config = config(&root);
+        configure(&mut config);
+        write_config(&root, &config);
+
+        let events = Arc::new(MemoryEventSink::default());
+        let manager = Arc::new(ProjectManager::new(events.clone()));
+        let watcher = ProjectManager::init_watcher(manager.clone()).unwrap();
+        manager.set_watcher(watcher);
+        let id = SessionId::new("test");
+        let project = Project::load_from_path(root.clone()).unwrap();
+        manager.set_project(&id, Some(Arc::new(project)));
+
+        Self {
+            _dir: dir,
+            root,
+            manager,
+            events,
+            id,
+        }
+    }
+
+    fn project(&self) -> Arc<Project> {
+        self.manager.get_project(&self.id).unwrap()
+    }
+
+    fn main(&self) -> String {
+        fs::read_to_string(self.root.join("main.typ")).unwrap()
+    }
+
+    /// The hashes of the pages compiled from the main file with an edit.
+    fn compile(&self, content: String) -> Vec<String> {
+        let compilation = self
+            .manager
+            .compile(&self.id, Path::new(MAIN), content)
+            .unwrap();
+        assert!(compilation.succeeded, "{:?}", compilation.diagnostics);
+        compilation
+            .pages
+            .into_iter()
+            .map(|page| page.hash)
+            .collect()
+    }
+}
+
+fn config(root: &Path) -> ProjectConfig {
+    let mut config = ProjectConfig {
+        main: Some(root.join("main.typ")),
+        package_path: Some(root.join(".data")),
+        package_cache_path: Some(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/packages")),
+        ..Default::default()
+    };
+    config.registry.offline = true;
+    config
+}
+
+fn write_config(root: &Path, config: &ProjectConfig) {
+    fs::create_dir_all(root.join(".typster")).unwrap();
+    config
+        .write_to_file(root.join(".typster/project.json"))
+        .unwrap();
+}
+
+/// Waits for a change picked up by the file watcher.
+fn eventually(mut condition: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !condition() {
+        assert!(Instant::now() < deadline, "timed out");
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn test_compile() {
+    let session = Session::open(|_| {});
+    let pages = session.compile(session.main());
+    assert!(!pages.is_empty());
+    assert!(session.project().cache.read().unwrap().document.is_some());
+
+    let lock = fs::read_to_string(session.root.join(".typster/packages.lock")).unwrap();
+    assert!(lock.contains("@preview/basic-resume:0.1.4"));
+
+    // The unchanged content compiles to the same pages.
+    assert_eq!(session.compile(session.main()), pages);
+
+    let broken = session.main() + "\n#let x = (";
+    let compilation = session
+        .manager
+        .compile(&session.id, Path::new(MAIN), broken.clone())
+        .unwrap();
+    assert!(!compilation.succeeded);
+    assert!(compilation.pages.is_empty());
+    let diagnostic = &compilation.diagnostics[0];
+    assert_eq!(diagnostic.pos.0, broken.lines().count());
+    assert!(!session
+        .project()
+        .cache
+        .read()
+        .unwrap()
+        .diagnostics
+        .is_empty());
+}
+
+#[test]
+fn test_compile_writes_watch_exports_once_edits_pause() {
+    let session = Session::open(|config| {
+        config.export.watch.enabled = true;
+        config.export.watch.pdf = Some("resume.pdf".into());
+    });
+    for i in 0..3 {
+        session.compile(session.main() + &"\n#pagebreak()".repeat(i));
+    }
+
+    let pdf = session.root.join("resume.pdf");
+    let mut exports = vec![];
+    eventually(|| {
+        exports.extend(
+            session
+                .events
+                .take()
+                .into_iter()
+                .filter_map(|(_, event)| match event {
+                    ProjectEvent::WatchExport(event) => Some(event),
+                    _ => None,
+                }),
+        );
+        !exports.is_empty()
+    });
+    thread::sleep(Duration::from_secs(1));
+    assert!(!session
+        .events
+        .take()
+        .iter()
+        .any(|(_, event)| matches!(event, ProjectEvent::WatchExport(_))));
+    assert_eq!(exports.len(), 1);
+    assert_eq!(exports[0].files, vec![pdf.clone()]);
+    assert!(fs::read(&pdf).unwrap().starts_with(b"%PDF"));
+}
+
+#[test]
+fn test_watcher_reloads_changed_files() {
+    let session = Session::open(|_| {});
+    let content = session.main() + "\n#include \"extra.typ\"\n";
+    fs::write(session.root.join("extra.typ"), "A short line.").unwrap();
+    let before = session.compile(content.clone());
+
+    fs::write(
+        session.root.join("extra.typ"),
+        "#for _ in range(5) { pagebreak() }",
+    )
+    .unwrap();
+    eventually(|| session.compile(content.clone()).len() == before.len() + 5);
+}
+
+#[test]
+fn test_watcher_reloads_config() {
+    let session = Session::open(|_| {});
+    session.compile(session.main());
+
+    fs::write(
+        session.root.join("cover.typ"),
+        "A\n#pagebreak()\nB\n#pagebreak()\nC",
+    )
+    .unwrap();
+    let mut config = config(&session.root);
+    config.main = Some(session.root.join("cover.typ"));
+    config.registry.offline = false;
+    write_config(&session.root, &config);
+    eventually(|| session.project().config.read().unwrap().main == config.main);
+    let project = session.project();
+    assert!(!project.world.lock().unwrap().registry().is_offline());
+
+    // Compiling after an edit of any file now starts from the new main file.
+    assert_eq!(session.compile(session.main()).len(), 3);
+}
+
+#[test]
+fn test_save_pdf_preset() {
+    let session = Session::open(|_| {});
+    let project = session.project();
+    let options = PdfExportOptions {
+        pages: Some("1".into()),
+        ..Default::default()
+    };
+    project
+        .save_pdf_preset("first-page".into(), options)
+        .unwrap();
+
+    let saved = ProjectConfig::read_from_file(session.root.join(".typster/project.json")).unwrap();
+    let preset = &saved.export.pdf_presets["first-page"];
+    assert_eq!(preset.pages.as_deref(), Some("1"));
+    assert_eq!(saved.main, project.config.read().unwrap().main);
+    assert!(project
+        .config
+        .read()
+        .unwrap()
+        .export
+        .pdf_presets
+        .contains_key("first-page"));
+}